@@ -1,8 +1,18 @@
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::hash::hashv;
+use anchor_lang::solana_program::program_option::COption;
 use anchor_spl::associated_token::AssociatedToken;
-use anchor_spl::token::{
-    self, Burn, Mint, MintTo, SetAuthority, Token, TokenAccount, Transfer,
+use anchor_spl::token_2022::spl_token_2022::{
+    self,
+    extension::transfer_hook::{TransferHook, TransferHookAccount},
+    extension::{BaseStateWithExtensions, StateWithExtensions},
+    onchain::invoke_transfer_checked,
 };
+use anchor_spl::token_interface::{
+    self as token, Burn, Mint, MintTo, SetAuthority, TokenAccount, TokenInterface, TransferChecked,
+};
+use spl_tlv_account_resolution::{account::ExtraAccountMeta, seeds::Seed, state::ExtraAccountMetaList};
+use spl_transfer_hook_interface::instruction::ExecuteInstruction;
 
 // ProgramID
 declare_id!("programid");
@@ -14,6 +24,27 @@ const VERSION: u8 = 1;
 const DEAL_VERSION: u8 = 1;
 const BPS_DENOMINATOR: u64 = 10_000;
 const MAX_COLLATERALS: usize = 4;
+const LIQUIDATION_BOUNTY_BPS: u64 = 100; // 1% of strike notional, capped by the liquidated side's margin
+const PRICE_HISTORY_LEN: usize = 64;
+const MAX_ORACLES: usize = 8;
+const ORACLE_ROUND_TIMEOUT_SECS: i64 = 300; // unfinished rounds older than this are discarded
+const DEFAULT_MAX_STALENESS_SECS: i64 = 3_600;
+const DEFAULT_MAX_DEVIATION_BPS: u16 = 2_000; // 20% per finalized round
+const DEFAULT_TIMELOCK_DELAY_SECS: i64 = 86_400;
+const MAX_TIMELOCK_DELAY_SECS: i64 = 30 * 86_400;
+const MAX_FEE_BPS: u16 = 1_000; // 10%
+const DEFAULT_PRICE_EXPONENT: i32 = -6; // 6 decimals (e.g., USDC quote)
+const MIN_PRICE_EXPONENT: i32 = -12;
+const MAX_COUNCIL_MEMBERS: usize = 8;
+const REDEMPTION_TIMEOUT_SECS: i64 = 7 * 86_400; // holder may reclaim escrow after this
+const SECONDS_PER_DAY: i64 = 86_400;
+const MAX_MERKLE_DEPTH: usize = 32;
+const DEFAULT_BOND_WITHDRAW_DELAY_SECS: i64 = 7 * 86_400;
+const MAX_GRADES: usize = 8;
+const MAX_GRADE_DIFF_BPS: u64 = 5_000; // ±50% of strike notional
+const MAX_STORAGE_RATE: u64 = 1_000_000_000; // quote base units per receipt unit per day
+const STORAGE_RATE_INCREASE_DELAY_SECS: i64 = 30 * 86_400; // notice before a higher rate applies
+const MAX_BOOK_ORDERS: usize = 16; // open orders indexed per side of an order book
 
 // ==========
 // Enums
@@ -30,6 +61,130 @@ pub enum Side {
     Short,
 }
 
+/// Admin changes that must wait out the market timelock (`pause_market` stays instant).
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum AdminAction {
+    AddAllowedCollateral { mint: Pubkey },
+    RemoveAllowedCollateral { mint: Pubkey },
+    SetStrategyOperator { operator: Pubkey },
+    UnpauseMarket,
+    SetSettlementMode { mode: SettlementPriceMode, twap_window_secs: i64 },
+    SetPriceGuards { max_staleness_secs: i64, max_deviation_bps: u16 },
+    AddOracle { oracle: Pubkey },
+    RemoveOracle { oracle: Pubkey },
+    SetOracleQuorum { quorum: u8 },
+    SetTimelockDelay { delay_secs: i64 },
+    UpdateMarketParams { params: MarketParams },
+    SetAuditor { auditor: Pubkey },
+    SetBondParams { min_bond: u64, withdraw_delay_secs: i64 },
+    SetGradeDifferential { grade: u16, diff_bps: i16 }, // premium (+) / discount (-) vs par
+    RemoveGrade { grade: u16 },
+}
+impl AdminAction {
+    pub const SIZE: usize = 1 + MarketParams::SIZE; // tag + largest payload
+}
+
+/// Risk/fee parameters fixed at `init_market` and changeable via `update_market_params`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub struct MarketParams {
+    pub fee_bps: u16,
+    pub base_initial_margin_bps: u16,
+    pub maintenance_margin_bps: u16,
+    pub vol_multiplier_bps: u16,
+    pub price_exponent: i32,
+    pub oracle_authority: Pubkey,
+}
+impl MarketParams {
+    pub const SIZE: usize = 2 + 2 + 2 + 2 + 4 + 32;
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum ActionStatus {
+    Queued = 0,
+    Executed = 1,
+    Cancelled = 2,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum WarehouseStatus {
+    Pending = 0,   // applied via init_warehouse, awaiting approval
+    Certified = 1, // may mint until `certified_until`
+    Revoked = 2,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum RedemptionStatus {
+    Requested = 0, // receipts escrowed, awaiting warehouse release
+    Released = 1,  // goods released, escrow burned
+    Cancelled = 2, // declined by the warehouse, escrow returned
+    Expired = 3,   // reclaimed by the holder after the timeout
+    Compensated = 4, // never released, holder paid out of the bond; escrow stays locked
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum OfferStatus {
+    Open = 0,
+    Accepted = 1,
+    Cancelled = 2,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum OrderStatus {
+    Open = 0,
+    Filled = 1,
+    Cancelled = 2,
+}
+
+/// Which price cash deals settle against.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum SettlementPriceMode {
+    Last = 0, // last posted print
+    Twap = 1, // time-weighted average over `twap_window_secs` before the deal's settle_ts
+}
+
+/// What a council proposal authorizes. `Market` and deal freezes are consumed by the matching
+/// admin instruction; membership changes are applied by `council_execute`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum CouncilAction {
+    Market { action: AdminAction }, // propose_action / update_market_params
+    FreezeDeal { deal: Pubkey },
+    UnfreezeDeal { deal: Pubkey },
+    AddMember { member: Pubkey },
+    RemoveMember { member: Pubkey },
+    SetThreshold { threshold: u8 },
+    SlashWarehouse { warehouse: Pubkey, recipient: Pubkey, amount: u64 }, // consumed by slash_warehouse
+    ExecuteAction { action_id: u64 },
+    CancelAction { action_id: u64 },
+    ApproveWarehouse { warehouse: Pubkey, certified_until: i64, mint_quota: u64 },
+    RevokeWarehouse { warehouse: Pubkey },
+    ArmDeviationOverride,
+    InitReceiptHook { receipt_mint: Pubkey },
+    SetParticipant { owner: Pubkey, is_active: bool },
+}
+impl CouncilAction {
+    pub const SIZE: usize = 1 + 32 + 32 + 8; // tag + largest payload (SlashWarehouse)
+}
+
+/// Physical description of the lot backing a `mint_receipt` call.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub struct LotMetadata {
+    pub grade: u16,                 // commodity grade code
+    pub gross_weight: u64,          // grams
+    pub net_weight: u64,            // grams
+    pub location: [u8; 16],         // storage location code (bay/row/bin), zero-padded
+    pub intake_ts: i64,
+    pub certificate_hash: [u8; 32], // hash of the inspection certificate
+}
+
+/// Privileged market keys that rotate via `propose_key_rotation` / `accept_key_rotation`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum KeyRole {
+    Authority = 0,
+    GovernanceAuthority = 1,
+    OracleAuthority = 2,
+    StrategyOperator = 3,
+}
+
 // ==========
 // Program
 // ==========
@@ -47,9 +202,18 @@ pub mod tokenized_warehouse_receipts {
         maintenance_margin_bps: u16,
         vol_multiplier_bps: u16, // scales how aggressively vol raises required margin
     ) -> Result<()> {
-        require!(fee_bps <= 1000, ErrorCode::FeeTooHigh); // <= 10%
+        validate_market_params(&MarketParams {
+            fee_bps,
+            base_initial_margin_bps,
+            maintenance_margin_bps,
+            vol_multiplier_bps,
+            price_exponent: DEFAULT_PRICE_EXPONENT,
+            oracle_authority,
+        })?;
         let market = &mut ctx.accounts.market;
         market.version = VERSION;
+        market.creator = ctx.accounts.authority.key();
+        market.bump = ctx.bumps.market;
         market.authority = ctx.accounts.authority.key();
         market.governance_authority = governance_authority;
         market.quote_mint = ctx.accounts.quote_mint.key();
@@ -59,7 +223,7 @@ pub mod tokenized_warehouse_receipts {
         market.is_paused = false;
         market.last_price = 0;
         market.last_vol_bps = 0;
-        market.price_exponent = -6; // default 6 decimals (e.g., USDC quote)
+        market.price_exponent = DEFAULT_PRICE_EXPONENT;
         market.settle_ts = 0;
         market.base_initial_margin_bps = base_initial_margin_bps;
         market.maintenance_margin_bps = maintenance_margin_bps;
@@ -67,6 +231,52 @@ pub mod tokenized_warehouse_receipts {
         market.allowed_collaterals = [Pubkey::default(); MAX_COLLATERALS];
         market.allowed_count = 0;
         market.strategy_operator = Pubkey::default();
+        market.settlement_price_mode = SettlementPriceMode::Last as u8;
+        market.twap_window_secs = 0;
+        market.last_price_ts = 0;
+        market.max_staleness_secs = DEFAULT_MAX_STALENESS_SECS;
+        market.max_deviation_bps = DEFAULT_MAX_DEVIATION_BPS;
+        market.deviation_override_armed = false;
+        market.timelock_delay_secs = DEFAULT_TIMELOCK_DELAY_SECS;
+        market.next_action_id = 0;
+        market.pending_authority = Pubkey::default();
+        market.pending_governance_authority = Pubkey::default();
+        market.pending_oracle_authority = Pubkey::default();
+        market.pending_strategy_operator = Pubkey::default();
+        market.council = Pubkey::default();
+        market.auditor = governance_authority;
+        market.receipt_auth_bump = 0; // set by the first init_warehouse
+        market.min_warehouse_bond = 0;
+        market.bond_withdraw_delay_secs = DEFAULT_BOND_WITHDRAW_DELAY_SECS;
+        // Token-2022 receipt mints whose transfer hook is this program only move between participants.
+        market.allowlist_enabled =
+            mint_transfer_hook_program(&ctx.accounts.receipt_mint.to_account_info())? == Some(crate::ID);
+        market.grade_codes = [0; MAX_GRADES];
+        market.grade_diff_bps = [0; MAX_GRADES];
+        market.grade_count = 0;
+
+        let history = &mut ctx.accounts.price_history;
+        history.market = market.key();
+        history.head = 0;
+        history.count = 0;
+        history.entries = [PricePoint::default(); PRICE_HISTORY_LEN];
+        history.bump = ctx.bumps.price_history;
+
+        let oracles = &mut ctx.accounts.oracle_set;
+        oracles.market = market.key();
+        oracles.members = [Pubkey::default(); MAX_ORACLES];
+        oracles.members[0] = oracle_authority;
+        oracles.count = 1;
+        oracles.quorum = 1;
+        oracles.round_id = 0;
+        oracles.round_open_ts = 0;
+        oracles.round_exponent = 0;
+        oracles.round_settle_ts = 0;
+        oracles.submitted_mask = 0;
+        oracles.prices = [0; MAX_ORACLES];
+        oracles.vols = [0; MAX_ORACLES];
+        oracles.bump = ctx.bumps.oracle_set;
+        oracles.displaced_mask = 0;
 
         emit!(MarketInitialized {
             market: market.key(),
@@ -79,64 +289,219 @@ pub mod tokenized_warehouse_receipts {
         Ok(())
     }
 
-    /// Allow governance/authority to add an allowed collateral mint (multi-stable support).
-    pub fn add_allowed_collateral(ctx: Context<AdminMarketWrite>, collateral_mint: Pubkey) -> Result<()> {
+    /// Emergency stop: stays instant (no timelock).
+    pub fn pause_market(ctx: Context<AdminMarketWrite>) -> Result<()> {
         only_admin(&ctx.accounts.market, &ctx.accounts.signer)?;
         let m = &mut ctx.accounts.market;
-        require!(!m.is_paused, ErrorCode::MarketPaused);
+        m.is_paused = true;
+        emit!(MarketPaused { market: m.key() });
+        Ok(())
+    }
+
+    /// Governance lets the next finalized round move beyond `max_deviation_bps` (one-shot).
+    pub fn arm_deviation_override(ctx: Context<ArmDeviationOverride>) -> Result<()> {
+        let a = &mut *ctx.accounts;
+        if a.market.council == Pubkey::default() {
+            require_keys_eq!(a.signer.key(), a.market.governance_authority, ErrorCode::Unauthorized);
+        }
+        authorize_admin(&a.market, &a.signer, &a.council, &mut a.council_proposal, CouncilAction::ArmDeviationOverride)?;
+        let m = &mut a.market;
+        m.deviation_override_armed = true;
+        emit!(DeviationOverrideArmed { market: m.key() });
+        Ok(())
+    }
+
+    // --- Key rotation ---
+    /// The current holder of `role` nominates its successor. Nothing changes until the nominee
+    /// signs `accept_key_rotation`; nominating `Pubkey::default()` withdraws the nomination.
+    /// Admins can also nominate oracle / strategy operator keys through the timelock.
+    pub fn propose_key_rotation(ctx: Context<AdminMarketWrite>, role: crate::KeyRole, new_key: Pubkey) -> Result<()> {
+        let market_key = ctx.accounts.market.key();
+        let signer = ctx.accounts.signer.key();
+        let (current, pending) = ctx.accounts.market.key_slots(role);
+        require_keys_eq!(signer, *current, ErrorCode::Unauthorized);
+        *pending = new_key;
+        emit!(KeyRotationProposed { market: market_key, role: role as u8, proposer: signer, pending: new_key });
+        Ok(())
+    }
 
-        for i in 0..m.allowed_count as usize {
-            if m.allowed_collaterals[i] == collateral_mint {
-                return Ok(()); // already present
+    /// The nominee takes over `role`. The bootstrap oracle's `OracleSet` seat moves with its key.
+    pub fn accept_key_rotation(ctx: Context<AcceptKeyRotation>, role: crate::KeyRole) -> Result<()> {
+        let market_key = ctx.accounts.market.key();
+        let new_key = ctx.accounts.new_key.key();
+        let (current, pending) = ctx.accounts.market.key_slots(role);
+        require!(*pending != Pubkey::default(), ErrorCode::NoPendingRotation);
+        require_keys_eq!(new_key, *pending, ErrorCode::Unauthorized);
+        let old = *current;
+        *current = new_key;
+        *pending = Pubkey::default();
+
+        if role == KeyRole::OracleAuthority {
+            let oracles = &mut ctx.accounts.oracle_set;
+            if let Some(i) = oracles.member_index(&old) {
+                require!(oracles.member_index(&new_key).is_none(), ErrorCode::OracleAlreadyRegistered);
+                oracles.members[i] = new_key;
+                oracles.submitted_mask = 0;
             }
         }
-        require!((m.allowed_count as usize) < MAX_COLLATERALS, ErrorCode::TooManyCollaterals);
-        let idx = m.allowed_count as usize;
-        m.allowed_collaterals[idx] = collateral_mint;
-        m.allowed_count += 1;
-        emit!(CollateralAdded { market: m.key(), collateral_mint });
+        emit!(KeyRotated { market: market_key, role: role as u8, old, new: new_key });
         Ok(())
     }
 
-    pub fn remove_allowed_collateral(ctx: Context<AdminMarketWrite>, collateral_mint: Pubkey) -> Result<()> {
+    // --- Governance council ---
+    /// Install an M-of-N council. From then on every admin instruction except the instant
+    /// `pause_market` (timelock queue/execute/cancel, deal freezes, warehouse certification,
+    /// slashing, the allowlist and price overrides) needs an approved `CouncilProposal` instead
+    /// of one admin key.
+    pub fn init_council(ctx: Context<InitCouncil>, members: Vec<Pubkey>, threshold: u8) -> Result<()> {
         only_admin(&ctx.accounts.market, &ctx.accounts.signer)?;
-        let m = &mut ctx.accounts.market;
-        require!(!m.is_paused, ErrorCode::MarketPaused);
+        require!(!members.is_empty() && members.len() <= MAX_COUNCIL_MEMBERS, ErrorCode::InvalidCouncil);
+        require!(threshold > 0 && threshold as usize <= members.len(), ErrorCode::InvalidCouncil);
+
+        let council = &mut ctx.accounts.council;
+        council.market = ctx.accounts.market.key();
+        council.members = [Pubkey::default(); MAX_COUNCIL_MEMBERS];
+        council.count = 0;
+        for m in members {
+            require!(m != Pubkey::default() && council.member_index(&m).is_none(), ErrorCode::InvalidCouncil);
+            let idx = council.count as usize;
+            council.members[idx] = m;
+            council.count += 1;
+        }
+        council.threshold = threshold;
+        council.epoch = 0;
+        council.next_proposal_id = 0;
+        council.bump = ctx.bumps.council;
+
+        ctx.accounts.market.council = council.key();
+        emit!(CouncilInitialized { market: council.market, council: council.key(), count: council.count, threshold });
+        Ok(())
+    }
+
+    /// A member opens a proposal; their approval is recorded with it.
+    pub fn council_propose(ctx: Context<CouncilPropose>, action: crate::CouncilAction) -> Result<()> {
+        if let CouncilAction::Market { action } = action {
+            check_admin_action_params(&action)?;
+        }
+        let council = &mut ctx.accounts.council;
+        let idx = council.member_index(&ctx.accounts.member.key()).ok_or(ErrorCode::NotCouncilMember)?;
+        let proposal_id = council.next_proposal_id;
+        council.next_proposal_id = proposal_id.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+
+        let p = &mut ctx.accounts.proposal;
+        p.council = council.key();
+        p.proposal_id = proposal_id;
+        p.action = action;
+        p.proposer = ctx.accounts.member.key();
+        p.epoch = council.epoch;
+        p.approvals = 1u16 << idx;
+        p.executed = false;
+        p.created_ts = Clock::get()?.unix_timestamp;
+        p.bump = ctx.bumps.proposal;
+
+        emit!(CouncilProposalCreated { council: p.council, proposal_id, proposer: p.proposer });
+        Ok(())
+    }
+
+    pub fn council_approve(ctx: Context<CouncilApprove>) -> Result<()> {
+        let council = &ctx.accounts.council;
+        let idx = council.member_index(&ctx.accounts.member.key()).ok_or(ErrorCode::NotCouncilMember)?;
+        let p = &mut ctx.accounts.proposal;
+        require!(!p.executed, ErrorCode::ProposalAlreadyExecuted);
+        require!(p.epoch == council.epoch, ErrorCode::StaleProposal);
+        require!(p.approvals & (1u16 << idx) == 0, ErrorCode::AlreadyApproved);
+        p.approvals |= 1u16 << idx;
+        emit!(CouncilProposalApproved {
+            council: p.council,
+            proposal_id: p.proposal_id,
+            member: ctx.accounts.member.key(),
+            approvals: p.approvals.count_ones() as u8,
+        });
+        Ok(())
+    }
 
-        let mut idx: Option<usize> = None;
-        for i in 0..m.allowed_count as usize {
-            if m.allowed_collaterals[i] == collateral_mint {
-                idx = Some(i);
-                break;
+    /// Apply an approved membership/threshold change. Any change bumps the council epoch, which
+    /// voids approvals on every older proposal.
+    pub fn council_execute(ctx: Context<CouncilExecute>) -> Result<()> {
+        let council = &mut ctx.accounts.council;
+        require!(council.member_index(&ctx.accounts.member.key()).is_some(), ErrorCode::NotCouncilMember);
+        let p = &mut ctx.accounts.proposal;
+        check_proposal_ready(council, p)?;
+        match p.action {
+            CouncilAction::AddMember { member } => {
+                require!(member != Pubkey::default() && council.member_index(&member).is_none(), ErrorCode::InvalidCouncil);
+                require!((council.count as usize) < MAX_COUNCIL_MEMBERS, ErrorCode::InvalidCouncil);
+                let idx = council.count as usize;
+                council.members[idx] = member;
+                council.count += 1;
+            }
+            CouncilAction::RemoveMember { member } => {
+                let i = council.member_index(&member).ok_or(ErrorCode::NotCouncilMember)?;
+                require!(council.count - 1 >= council.threshold, ErrorCode::InvalidCouncil);
+                let last = (council.count - 1) as usize;
+                council.members[i] = council.members[last];
+                council.members[last] = Pubkey::default();
+                council.count -= 1;
             }
+            CouncilAction::SetThreshold { threshold } => {
+                require!(threshold > 0 && threshold <= council.count, ErrorCode::InvalidCouncil);
+                council.threshold = threshold;
+            }
+            _ => return err!(ErrorCode::CouncilActionMismatch),
         }
-        require!(idx.is_some(), ErrorCode::CollateralNotFound);
-        let i = idx.unwrap();
-        let last = (m.allowed_count - 1) as usize;
-        m.allowed_collaterals[i] = m.allowed_collaterals[last];
-        m.allowed_collaterals[last] = Pubkey::default();
-        m.allowed_count -= 1;
-        emit!(CollateralRemoved { market: m.key(), collateral_mint });
+        council.epoch = council.epoch.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+        p.executed = true;
+        emit!(CouncilProposalExecuted { council: p.council, proposal_id: p.proposal_id });
+        emit!(CouncilMembershipChanged { council: council.key(), count: council.count, threshold: council.threshold, epoch: council.epoch });
         Ok(())
     }
 
-    pub fn pause_market(ctx: Context<AdminMarketWrite>) -> Result<()> {
-        only_admin(&ctx.accounts.market, &ctx.accounts.signer)?;
-        let m = &mut ctx.accounts.market;
-        m.is_paused = true;
-        emit!(MarketPaused { market: m.key() });
+    // --- Governance timelock ---
+    /// Queue an admin action; it becomes executable after `market.timelock_delay_secs`.
+    pub fn propose_action(ctx: Context<ProposeAction>, action: crate::AdminAction) -> Result<()> {
+        let a = &mut *ctx.accounts;
+        authorize_admin(&a.market, &a.proposer, &a.council, &mut a.council_proposal, CouncilAction::Market { action })?;
+        queue_admin_action(a, ctx.bumps.pending_action, action)
+    }
+
+    /// Queue a change to the market's risk/fee parameters. Bounds are validated now and again at
+    /// execution; open deals keep the `fee_bps` snapshotted at `open_deal`.
+    pub fn update_market_params(ctx: Context<ProposeAction>, params: crate::MarketParams) -> Result<()> {
+        let action = AdminAction::UpdateMarketParams { params };
+        let a = &mut *ctx.accounts;
+        authorize_admin(&a.market, &a.proposer, &a.council, &mut a.council_proposal, CouncilAction::Market { action })?;
+        queue_admin_action(a, ctx.bumps.pending_action, action)
+    }
+
+    /// Apply a queued action once its delay has elapsed.
+    pub fn execute_action(ctx: Context<ExecuteAction>) -> Result<()> {
+        let a = &mut *ctx.accounts;
+        let expected = CouncilAction::ExecuteAction { action_id: a.pending_action.action_id };
+        authorize_admin(&a.market, &a.signer, &a.council, &mut a.council_proposal, expected)?;
+        let pa = &mut ctx.accounts.pending_action;
+        require!(pa.status == ActionStatus::Queued as u8, ErrorCode::ActionNotQueued);
+        require!(Clock::get()?.unix_timestamp >= pa.eta, ErrorCode::TimelockNotElapsed);
+
+        apply_admin_action(&mut ctx.accounts.market, &mut ctx.accounts.oracle_set, &pa.action)?;
+        pa.status = ActionStatus::Executed as u8;
+        emit!(ActionExecuted { market: pa.market, action_id: pa.action_id });
         Ok(())
     }
 
-    pub fn unpause_market(ctx: Context<AdminMarketWrite>) -> Result<()> {
-        only_admin(&ctx.accounts.market, &ctx.accounts.signer)?;
-        let m = &mut ctx.accounts.market;
-        m.is_paused = false;
-        emit!(MarketUnpaused { market: m.key() });
+    pub fn cancel_action(ctx: Context<CancelAction>) -> Result<()> {
+        let a = &mut *ctx.accounts;
+        let expected = CouncilAction::CancelAction { action_id: a.pending_action.action_id };
+        authorize_admin(&a.market, &a.signer, &a.council, &mut a.council_proposal, expected)?;
+        let pa = &mut ctx.accounts.pending_action;
+        require!(pa.status == ActionStatus::Queued as u8, ErrorCode::ActionNotQueued);
+        pa.status = ActionStatus::Cancelled as u8;
+        emit!(ActionCancelled { market: pa.market, action_id: pa.action_id });
         Ok(())
     }
 
-    /// Oracle/authority posts price + volatility to drive dynamic margining.
+    /// A registered oracle submits price + volatility into the open round. Once `quorum` oracles
+    /// have submitted for the same (exponent, settle_ts), the median finalizes into the market
+    /// and drives dynamic margining and settlement.
     pub fn post_price(
         ctx: Context<PostPrice>,
         price: u64,
@@ -144,18 +509,54 @@ pub mod tokenized_warehouse_receipts {
         settle_ts: i64,
         vol_bps: u16,
     ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
         let market = &mut ctx.accounts.market;
+        let set = &mut ctx.accounts.oracle_set;
         let signer = ctx.accounts.poster.key();
-        require!(
-            signer == market.oracle_authority || signer == market.authority || signer == market.governance_authority,
-            ErrorCode::Unauthorized
-        );
+        let idx = set.member_index(&signer).ok_or(ErrorCode::Unauthorized)?;
+        require!(exponent == market.price_exponent, ErrorCode::PriceExponentMismatch);
+        let twap = &mut ctx.accounts.twap;
+        if twap.market == Pubkey::default() {
+            twap.market = market.key();
+            twap.settle_ts = settle_ts;
+            twap.start_ts = settle_ts.checked_sub(market.twap_window_secs).ok_or(ErrorCode::MathOverflow)?;
+            twap.bump = ctx.bumps.twap;
+        }
+
+        set.submit(idx, now, exponent, settle_ts, price, vol_bps)?;
+        emit!(OraclePriceSubmitted {
+            market: market.key(),
+            oracle: signer,
+            round_id: set.round_id,
+            price,
+            vol_bps,
+        });
+
+        if !set.has_quorum() {
+            return Ok(());
+        }
+
+        // Quorum reached: only finalized rounds feed the market.
+        let (price, vol_bps) = set.round_median();
+        let round_id = set.round_id;
+
+        if !within_deviation_band(market.last_price, price, market.max_deviation_bps) {
+            require!(market.deviation_override_armed, ErrorCode::PriceDeviationTooLarge);
+            market.deviation_override_armed = false;
+            emit!(DeviationOverrideUsed { market: market.key(), round_id, prev_price: market.last_price, price });
+        }
+        set.submitted_mask = 0;
+
         market.last_price = price;
+        market.last_price_ts = now;
         market.price_exponent = exponent;
         market.settle_ts = settle_ts;
         market.last_vol_bps = vol_bps;
+        ctx.accounts.price_history.push(price, now, settle_ts);
+        twap.record(price, now);
         emit!(PricePosted {
             market: market.key(),
+            round_id,
             price,
             exponent,
             settle_ts,
@@ -164,43 +565,383 @@ pub mod tokenized_warehouse_receipts {
         Ok(())
     }
 
+    /// A registered oracle approves the settlement price for one expiry. The price freezes once
+    /// `quorum` oracles have approved the same value; after that the account is never written
+    /// again and cash deals settle against it. If the derived price changes between approvals
+    /// (a later print, a mode switch), the earlier approvals are dropped.
+    pub fn finalize_settlement_price(ctx: Context<FinalizeSettlementPrice>, settle_ts: i64) -> Result<()> {
+        let market = &ctx.accounts.market;
+        let set = &ctx.accounts.oracle_set;
+        let oracle = ctx.accounts.oracle.key();
+        let idx = set.member_index(&oracle).ok_or(ErrorCode::Unauthorized)?;
+        let now = Clock::get()?.unix_timestamp;
+        require!(now >= settle_ts, ErrorCode::TooEarlyToSettle);
+        if market.settlement_price_mode == SettlementPriceMode::Last as u8 {
+            // The latest finalized round must have been quoted for this expiry.
+            require!(market.settle_ts == settle_ts, ErrorCode::SettlementExpiryMismatch);
+        }
+        let price = settlement_price(market, &ctx.accounts.twap, settle_ts)?;
+
+        let sp = &mut ctx.accounts.settlement_price;
+        require!(sp.finalized_ts == 0, ErrorCode::SettlementPriceAlreadyFinal);
+        if sp.market == Pubkey::default()
+            || sp.price != price
+            || sp.price_exponent != market.price_exponent
+            || sp.mode != market.settlement_price_mode
+        {
+            sp.market = market.key();
+            sp.settle_ts = settle_ts;
+            sp.price = price;
+            sp.price_exponent = market.price_exponent;
+            sp.mode = market.settlement_price_mode;
+            sp.bump = ctx.bumps.settlement_price;
+            sp.approvals_mask = 0;
+        }
+        let bit = 1u16 << idx;
+        require!(sp.approvals_mask & bit == 0, ErrorCode::DuplicateOracleSubmission);
+        sp.approvals_mask |= bit;
+        emit!(SettlementPriceApproved { market: sp.market, settle_ts, oracle, price });
+
+        if sp.approvals_mask.count_ones() < set.quorum as u32 {
+            return Ok(());
+        }
+        sp.finalized_ts = now;
+        emit!(SettlementPriceFinalized {
+            market: sp.market,
+            settle_ts,
+            price,
+            exponent: sp.price_exponent,
+            mode: sp.mode,
+        });
+        Ok(())
+    }
+
     // --- Warehouse lifecycle ---
+    /// Warehouse applies for certification; it cannot mint until `approve_warehouse`. The first
+    /// warehouse of a market hands the receipt mint's authority to the market-level PDA.
     pub fn init_warehouse(ctx: Context<InitWarehouse>) -> Result<()> {
         require_keys_eq!(ctx.accounts.market.receipt_mint, ctx.accounts.receipt_mint.key(), ErrorCode::ConstraintMismatch);
         require_keys_eq!(ctx.accounts.market.quote_mint, ctx.accounts.quote_mint.key(), ErrorCode::ConstraintMismatch);
-        require_keys_eq!(ctx.accounts.market.authority, ctx.accounts.authority.key(), ErrorCode::ConstraintMismatch);
 
         let warehouse = &mut ctx.accounts.warehouse;
         warehouse.market = ctx.accounts.market.key();
         warehouse.authority = ctx.accounts.warehouse_authority.key();
         warehouse.receipt_mint = ctx.accounts.receipt_mint.key();
-        warehouse.bump = ctx.bumps.receipt_mint_auth;
+        warehouse.bump = ctx.bumps.warehouse;
+        warehouse.attested_quantity = 0;
+        warehouse.total_minted = 0;
+        warehouse.total_burned = 0;
+        warehouse.last_attested_ts = 0;
+        warehouse.next_lot_id = 0;
+        warehouse.status = WarehouseStatus::Pending as u8;
+        warehouse.certified_until = 0;
+        warehouse.certified_by = Pubkey::default();
+        warehouse.next_redemption_id = 0;
+        warehouse.storage_rate = 0;
+        warehouse.storage_fees_accrued = 0;
+        warehouse.storage_fees_collected = 0;
+        warehouse.storage_auth_bump = ctx.bumps.storage_auth;
+        warehouse.mint_quota = 0;
+        warehouse.bond_amount = 0;
+        warehouse.bond_auth_bump = ctx.bumps.bond_auth;
+        warehouse.pending_bond_withdrawal = 0;
+        warehouse.bond_withdraw_eta = 0;
+        warehouse.total_slashed = 0;
+        warehouse.pending_storage_rate = 0;
+        warehouse.storage_rate_eta = 0;
+
+        let mint_auth = ctx.accounts.receipt_mint_auth.key();
+        if ctx.accounts.receipt_mint.mint_authority != COption::Some(mint_auth) {
+            token::set_authority(
+                CpiContext::new(
+                    ctx.accounts.receipt_token_program.to_account_info(),
+                    SetAuthority {
+                        account_or_mint: ctx.accounts.receipt_mint.to_account_info(),
+                        current_authority: ctx.accounts.warehouse_authority.to_account_info(),
+                    },
+                ),
+                spl_token_2022::instruction::AuthorityType::MintTokens,
+                Some(mint_auth),
+            )?;
+        }
+        ctx.accounts.market.receipt_auth_bump = ctx.bumps.receipt_mint_auth;
+
+        emit!(WarehouseInitialized {
+            market: warehouse.market,
+            warehouse: warehouse.key(),
+            warehouse_authority: warehouse.authority,
+            receipt_mint: warehouse.receipt_mint,
+        });
+        Ok(())
+    }
+
+    /// Market authority or governance certifies (or renews) a warehouse until `certified_until`
+    /// and sets how many receipts it may have outstanding against the shared mint.
+    pub fn approve_warehouse(ctx: Context<AdminWarehouseWrite>, certified_until: i64, mint_quota: u64) -> Result<()> {
+        let a = &mut *ctx.accounts;
+        let expected = CouncilAction::ApproveWarehouse { warehouse: a.warehouse.key(), certified_until, mint_quota };
+        authorize_admin(&a.market, &a.signer, &a.council, &mut a.council_proposal, expected)?;
+        require!(certified_until > Clock::get()?.unix_timestamp, ErrorCode::InvalidCertificationExpiry);
+        let w = &mut ctx.accounts.warehouse;
+        w.status = WarehouseStatus::Certified as u8;
+        w.certified_until = certified_until;
+        w.certified_by = ctx.accounts.signer.key();
+        w.mint_quota = mint_quota;
+        emit!(WarehouseApproved { warehouse: w.key(), approver: w.certified_by, certified_until, mint_quota });
+        Ok(())
+    }
+
+    pub fn revoke_warehouse(ctx: Context<AdminWarehouseWrite>) -> Result<()> {
+        let a = &mut *ctx.accounts;
+        let expected = CouncilAction::RevokeWarehouse { warehouse: a.warehouse.key() };
+        authorize_admin(&a.market, &a.signer, &a.council, &mut a.council_proposal, expected)?;
+        let w = &mut ctx.accounts.warehouse;
+        w.status = WarehouseStatus::Revoked as u8;
+        emit!(WarehouseRevoked { warehouse: w.key(), revoker: ctx.accounts.signer.key() });
+        Ok(())
+    }
 
-        token::set_authority(
+    // --- Performance bond ---
+    /// Warehouse tops up its bond (market quote mint). Minting needs `bond_amount >= min_warehouse_bond`.
+    pub fn post_bond(ctx: Context<PostBond>, amount: u64) -> Result<()> {
+        require!(amount > 0, ErrorCode::ZeroAmount);
+        token::transfer_checked(
             CpiContext::new(
                 ctx.accounts.token_program.to_account_info(),
-                SetAuthority {
-                    account_or_mint: ctx.accounts.receipt_mint.to_account_info(),
-                    current_authority: ctx.accounts.warehouse_authority.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.from_quote_ata.to_account_info(),
+                    mint: ctx.accounts.quote_mint.to_account_info(),
+                    to: ctx.accounts.bond_vault.to_account_info(),
+                    authority: ctx.accounts.warehouse_authority.to_account_info(),
                 },
             ),
-            anchor_spl::token::spl_token::instruction::AuthorityType::MintTokens,
-            Some(ctx.accounts.receipt_mint_auth.key()),
+            amount,
+            ctx.accounts.quote_mint.decimals,
         )?;
+        let w = &mut ctx.accounts.warehouse;
+        w.bond_amount = w.bond_amount.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+        emit!(BondPosted { warehouse: w.key(), amount, bond_amount: w.bond_amount });
+        Ok(())
+    }
 
-        emit!(WarehouseInitialized {
-            market: warehouse.market,
-            warehouse: warehouse.key(),
-            warehouse_authority: warehouse.authority,
-            receipt_mint: warehouse.receipt_mint,
+    /// Start the withdrawal clock for `amount`; replaces any pending request (0 cancels it).
+    pub fn request_bond_withdrawal(ctx: Context<RequestBondWithdrawal>, amount: u64) -> Result<()> {
+        let market = &ctx.accounts.market;
+        let w = &mut ctx.accounts.warehouse;
+        require!(amount <= w.bond_amount, ErrorCode::InsufficientBond);
+        let eta = if amount == 0 {
+            0
+        } else {
+            Clock::get()?.unix_timestamp.checked_add(market.bond_withdraw_delay_secs).ok_or(ErrorCode::MathOverflow)?
+        };
+        w.pending_bond_withdrawal = amount;
+        w.bond_withdraw_eta = eta;
+        emit!(BondWithdrawalRequested { warehouse: w.key(), amount, eta });
+        Ok(())
+    }
+
+    /// Pay out a matured withdrawal request. A warehouse with receipts outstanding must stay at
+    /// or above the market minimum; slashes during the delay shrink what can be withdrawn.
+    pub fn withdraw_bond(ctx: Context<WithdrawBond>) -> Result<()> {
+        let market = &ctx.accounts.market;
+        let w = &mut ctx.accounts.warehouse;
+        require!(w.pending_bond_withdrawal > 0, ErrorCode::NoPendingBondWithdrawal);
+        require!(Clock::get()?.unix_timestamp >= w.bond_withdraw_eta, ErrorCode::TimelockNotElapsed);
+        let amount = w.pending_bond_withdrawal.min(w.bond_amount);
+        require!(amount > 0, ErrorCode::InsufficientBond);
+        let remaining = w.bond_amount - amount;
+        if w.outstanding()? > 0 {
+            require!(remaining >= market.min_warehouse_bond, ErrorCode::BondBelowMinimum);
+        }
+
+        let warehouse_key = w.key();
+        token::transfer_checked(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.bond_vault.to_account_info(),
+                    mint: ctx.accounts.quote_mint.to_account_info(),
+                    to: ctx.accounts.to_quote_ata.to_account_info(),
+                    authority: ctx.accounts.bond_auth.to_account_info(),
+                },
+                &[&[b"bond_auth", warehouse_key.as_ref(), &[w.bond_auth_bump]]],
+            ),
+            amount,
+            ctx.accounts.quote_mint.decimals,
+        )?;
+        w.bond_amount = remaining;
+        w.pending_bond_withdrawal = 0;
+        w.bond_withdraw_eta = 0;
+        emit!(BondWithdrawn { warehouse: warehouse_key, amount, bond_amount: remaining });
+        Ok(())
+    }
+
+    /// Governance pays `amount` of the bond to the holder of a redemption the warehouse let time
+    /// out, up to the redeemed receipts' value at the last price. The redemption is closed as
+    /// `Compensated`, so it can't also be released or returned. Under a council the proposal must
+    /// name this warehouse, recipient account and amount.
+    pub fn slash_warehouse(ctx: Context<SlashWarehouse>, amount: u64) -> Result<()> {
+        let a = &mut *ctx.accounts;
+        let expected = CouncilAction::SlashWarehouse {
+            warehouse: a.warehouse.key(),
+            recipient: a.recipient_quote_ata.key(),
+            amount,
+        };
+        authorize_admin(&a.market, &a.signer, &a.council, &mut a.council_proposal, expected)?;
+        require!(amount > 0, ErrorCode::ZeroAmount);
+        require!(amount <= a.warehouse.bond_amount, ErrorCode::InsufficientBond);
+        let r = &a.redemption;
+        require_keys_eq!(r.warehouse, a.warehouse.key(), ErrorCode::ConstraintMismatch);
+        require!(r.status == RedemptionStatus::Requested as u8, ErrorCode::RedemptionNotOpen);
+        let now = Clock::get()?.unix_timestamp;
+        require!(now >= r.expires_ts, ErrorCode::RedemptionNotExpired);
+        require_keys_eq!(a.recipient_quote_ata.owner, r.holder, ErrorCode::ConstraintMismatch);
+        let value = (a.market.last_price as u128)
+            .saturating_mul(r.amount as u128)
+            / pow10_u128(a.market.price_exponent.abs() as u32);
+        require!(amount as u128 <= value, ErrorCode::SlashExceedsRedemptionValue);
+        let redemption_key = r.key();
+
+        let warehouse_key = a.warehouse.key();
+        token::transfer_checked(
+            CpiContext::new_with_signer(
+                a.token_program.to_account_info(),
+                TransferChecked {
+                    from: a.bond_vault.to_account_info(),
+                    mint: a.quote_mint.to_account_info(),
+                    to: a.recipient_quote_ata.to_account_info(),
+                    authority: a.bond_auth.to_account_info(),
+                },
+                &[&[b"bond_auth", warehouse_key.as_ref(), &[a.warehouse.bond_auth_bump]]],
+            ),
+            amount,
+            a.quote_mint.decimals,
+        )?;
+        let w = &mut a.warehouse;
+        w.bond_amount -= amount;
+        w.total_slashed = w.total_slashed.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+        let r = &mut a.redemption;
+        r.status = RedemptionStatus::Compensated as u8;
+        r.closed_ts = now;
+        emit!(WarehouseSlashed {
+            warehouse: warehouse_key,
+            recipient: a.recipient_quote_ata.owner,
+            redemption: redemption_key,
+            amount,
+            bond_amount: w.bond_amount,
+        });
+        Ok(())
+    }
+
+    // --- Storage fees ---
+    /// Quote base units charged per whole receipt unit per day of storage. Storage accrues from
+    /// each lot's intake, so while receipts are outstanding an increase only applies after
+    /// `STORAGE_RATE_INCREASE_DELAY_SECS`; decreases apply at once and drop a queued increase.
+    pub fn set_storage_rate(ctx: Context<SetStorageRate>, storage_rate: u64) -> Result<()> {
+        require!(storage_rate <= MAX_STORAGE_RATE, ErrorCode::StorageRateTooHigh);
+        let now = Clock::get()?.unix_timestamp;
+        let w = &mut ctx.accounts.warehouse;
+        let current = w.storage_rate_at(now);
+        if storage_rate > current && w.outstanding()? > 0 {
+            let eta = now.checked_add(STORAGE_RATE_INCREASE_DELAY_SECS).ok_or(ErrorCode::MathOverflow)?;
+            w.storage_rate = current;
+            w.pending_storage_rate = storage_rate;
+            w.storage_rate_eta = eta;
+            emit!(StorageRateIncreaseQueued { warehouse: w.key(), storage_rate, eta });
+        } else {
+            w.storage_rate = storage_rate;
+            w.pending_storage_rate = 0;
+            w.storage_rate_eta = 0;
+            emit!(StorageRateSet { warehouse: w.key(), storage_rate });
+        }
+        Ok(())
+    }
+
+    /// Warehouse withdraws the storage fees it has earned (escrow for open redemptions stays).
+    pub fn collect_storage_fees(ctx: Context<CollectStorageFees>) -> Result<()> {
+        let w = &mut ctx.accounts.warehouse;
+        let amount = w.storage_fees_accrued;
+        require!(amount > 0, ErrorCode::ZeroAmount);
+        let warehouse_key = w.key();
+        token::transfer_checked(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.storage_vault.to_account_info(),
+                    mint: ctx.accounts.quote_mint.to_account_info(),
+                    to: ctx.accounts.to_quote_ata.to_account_info(),
+                    authority: ctx.accounts.storage_auth.to_account_info(),
+                },
+                &[&[b"storage_auth", warehouse_key.as_ref(), &[w.storage_auth_bump]]],
+            ),
+            amount,
+            ctx.accounts.quote_mint.decimals,
+        )?;
+        w.storage_fees_accrued = 0;
+        w.storage_fees_collected = w.storage_fees_collected.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+        emit!(StorageFeesCollected { warehouse: warehouse_key, amount });
+        Ok(())
+    }
+
+    /// The market's auditor records the physical quantity on hand (receipt base units).
+    /// Minting is capped so outstanding receipts never exceed the latest attestation.
+    pub fn attest_inventory(ctx: Context<AttestInventory>, quantity: u64) -> Result<()> {
+        let market = &ctx.accounts.market;
+        require!(market.auditor != Pubkey::default(), ErrorCode::Unauthorized);
+        require_keys_eq!(ctx.accounts.auditor.key(), market.auditor, ErrorCode::Unauthorized);
+        let w = &mut ctx.accounts.warehouse;
+        w.attested_quantity = quantity;
+        w.last_attested_ts = Clock::get()?.unix_timestamp;
+        emit!(InventoryAttested {
+            warehouse: w.key(),
+            auditor: market.auditor,
+            quantity,
+            outstanding: w.outstanding()?,
         });
         Ok(())
     }
 
-    pub fn mint_receipt(ctx: Context<MintReceipt>, amount: u64) -> Result<()> {
+    /// Mint receipts for one physical lot; a `ReceiptLot` recording the lot is created alongside.
+    pub fn mint_receipt(ctx: Context<MintReceipt>, amount: u64, lot: crate::LotMetadata) -> Result<()> {
         require_keys_eq!(ctx.accounts.warehouse.market, ctx.accounts.market.key(), ErrorCode::ConstraintMismatch);
         require_keys_eq!(ctx.accounts.warehouse.authority, ctx.accounts.warehouse_authority.key(), ErrorCode::Unauthorized);
         require_keys_eq!(ctx.accounts.warehouse.receipt_mint, ctx.accounts.receipt_mint.key(), ErrorCode::ConstraintMismatch);
+        require!(ctx.accounts.warehouse.status == WarehouseStatus::Certified as u8, ErrorCode::WarehouseNotCertified);
+        require!(Clock::get()?.unix_timestamp < ctx.accounts.warehouse.certified_until, ErrorCode::CertificationExpired);
+        require!(ctx.accounts.warehouse.bond_amount >= ctx.accounts.market.min_warehouse_bond, ErrorCode::BondBelowMinimum);
+        require!(amount > 0, ErrorCode::ZeroAmount);
+        require!(lot.net_weight > 0 && lot.net_weight <= lot.gross_weight, ErrorCode::InvalidLotMetadata);
+        let now = Clock::get()?.unix_timestamp;
+        require!(lot.intake_ts <= now, ErrorCode::InvalidLotMetadata);
+        if ctx.accounts.market.allowlist_enabled {
+            let p = ctx.accounts.recipient_participant.as_ref().ok_or(ErrorCode::NotParticipant)?;
+            require_keys_eq!(p.market, ctx.accounts.market.key(), ErrorCode::NotParticipant);
+            require_keys_eq!(p.owner, ctx.accounts.to_receipt_ata.owner, ErrorCode::NotParticipant);
+            require!(p.is_active, ErrorCode::NotParticipant);
+        }
+
+        let w = &mut ctx.accounts.warehouse;
+        let outstanding = w.outstanding()?.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+        require!(outstanding <= w.attested_quantity, ErrorCode::ExceedsAttestedInventory);
+        require!(outstanding <= w.mint_quota, ErrorCode::ExceedsMintQuota);
+        w.total_minted = w.total_minted.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+        let lot_id = w.next_lot_id;
+        w.next_lot_id = lot_id.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+
+        let l = &mut ctx.accounts.lot;
+        l.warehouse = w.key();
+        l.lot_id = lot_id;
+        l.grade = lot.grade;
+        l.gross_weight = lot.gross_weight;
+        l.net_weight = lot.net_weight;
+        l.location = lot.location;
+        l.intake_ts = lot.intake_ts;
+        l.certificate_hash = lot.certificate_hash;
+        l.quantity_minted = amount;
+        l.quantity_burned = 0;
+        l.storage_credit = 0;
+        l.bump = ctx.bumps.lot;
+        l.minted_ts = now;
 
         token::mint_to(
             CpiContext::new_with_signer(
@@ -212,39 +953,286 @@ pub mod tokenized_warehouse_receipts {
                 },
                 &[&[
                     b"receipt_auth",
-                    ctx.accounts.warehouse.key().as_ref(),
-                    &[ctx.accounts.warehouse.bump],
+                    ctx.accounts.market.key().as_ref(),
+                    &[ctx.accounts.market.receipt_auth_bump],
                 ]],
             ),
             amount,
         )?;
         emit!(ReceiptMinted {
             warehouse: ctx.accounts.warehouse.key(),
+            lot_id,
             to: ctx.accounts.to_receipt_ata.owner,
             amount,
         });
         Ok(())
     }
 
-    pub fn burn_receipt(ctx: Context<BurnReceipt>, amount: u64) -> Result<()> {
+    // --- Redemption ---
+    /// Holder escrows receipts against `lot` and leaves delivery instructions for the warehouse.
+    pub fn request_redemption<'info>(
+        ctx: Context<'_, '_, '_, 'info, RequestRedemption<'info>>,
+        amount: u64,
+        delivery_instructions: [u8; 64],
+    ) -> Result<()> {
+        require!(amount > 0, ErrorCode::ZeroAmount);
+        require!(amount <= ctx.accounts.lot.outstanding()?, ErrorCode::ExceedsOutstandingReceipts);
+        let now = Clock::get()?.unix_timestamp;
+
+        // Storage since intake, net of what was prepaid on this lot at physical delivery.
+        let l = &mut ctx.accounts.lot;
+        let gross = storage_fee_for(amount, ctx.accounts.warehouse.storage_rate_at(now), l.storage_start_ts(), now, ctx.accounts.receipt_mint.decimals)?;
+        let credit_used = gross.min(l.storage_credit);
+        l.storage_credit -= credit_used;
+        let storage_fee = gross - credit_used;
+
+        let w = &mut ctx.accounts.warehouse;
+        let redemption_id = w.next_redemption_id;
+        w.next_redemption_id = redemption_id.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+
+        let r = &mut ctx.accounts.redemption;
+        r.warehouse = w.key();
+        r.lot = ctx.accounts.lot.key();
+        r.redemption_id = redemption_id;
+        r.holder = ctx.accounts.holder.key();
+        r.amount = amount;
+        r.delivery_instructions = delivery_instructions;
+        r.status = RedemptionStatus::Requested as u8;
+        r.requested_ts = now;
+        r.expires_ts = now.checked_add(REDEMPTION_TIMEOUT_SECS).ok_or(ErrorCode::MathOverflow)?;
+        r.closed_ts = 0;
+        r.bump = ctx.bumps.redemption;
+        r.auth_bump = ctx.bumps.redemption_auth;
+        r.storage_fee = storage_fee;
+        r.storage_credit_used = credit_used;
+
+        // On allowlisted markets the escrow authority is a participant so receipts can enter and
+        // leave escrow; the account is closed back to the holder when the redemption ends.
+        let allowlist = ctx.accounts.market.allowlist_enabled;
+        require!(ctx.accounts.escrow_participant.is_some() == allowlist, ErrorCode::ConstraintMismatch);
+        r.has_escrow_participant = allowlist;
+        let market_key = ctx.accounts.market.key();
+        let auth_key = ctx.accounts.redemption_auth.key();
+        if let Some(ep) = ctx.accounts.escrow_participant.as_mut() {
+            ep.market = market_key;
+            ep.owner = auth_key;
+            ep.is_active = true;
+            ep.updated_by = ctx.accounts.holder.key();
+            ep.updated_ts = now;
+            ep.bump = Pubkey::find_program_address(&[b"participant", market_key.as_ref(), auth_key.as_ref()], &crate::ID).1;
+            ep.exit(&crate::ID)?; // persist now: the hook reads it during the escrow transfer below
+        }
+
+        if storage_fee > 0 {
+            token::transfer_checked(
+                CpiContext::new(
+                    ctx.accounts.token_program.to_account_info(),
+                    TransferChecked {
+                        from: ctx.accounts.holder_quote_ata.to_account_info(),
+                        mint: ctx.accounts.quote_mint.to_account_info(),
+                        to: ctx.accounts.storage_vault.to_account_info(),
+                        authority: ctx.accounts.holder.to_account_info(),
+                    },
+                ),
+                storage_fee,
+                ctx.accounts.quote_mint.decimals,
+            )?;
+        }
+
+        transfer_receipts(
+            &ctx.accounts.receipt_token_program,
+            ctx.accounts.holder_receipt_ata.to_account_info(),
+            &ctx.accounts.receipt_mint,
+            ctx.accounts.redemption_vault.to_account_info(),
+            ctx.accounts.holder.to_account_info(),
+            &ctx.accounts.market,
+            &ctx.accounts.receipt_mint_auth,
+            ctx.remaining_accounts,
+            &[],
+            amount,
+        )?;
+        emit!(RedemptionRequested {
+            warehouse: r.warehouse,
+            redemption: r.key(),
+            lot_id: ctx.accounts.lot.lot_id,
+            holder: r.holder,
+            amount,
+            storage_fee,
+            expires_ts: r.expires_ts,
+        });
+        Ok(())
+    }
+
+    /// Warehouse confirms the goods left the building; the escrow is burned and the lot and
+    /// warehouse ledgers are reconciled.
+    pub fn confirm_release(ctx: Context<ConfirmRelease>) -> Result<()> {
+        require_keys_eq!(ctx.accounts.warehouse.authority, ctx.accounts.warehouse_authority.key(), ErrorCode::Unauthorized);
+        let r = &mut ctx.accounts.redemption;
+        require!(r.status == RedemptionStatus::Requested as u8, ErrorCode::RedemptionNotOpen);
+        require!(ctx.accounts.escrow_participant.is_some() == r.has_escrow_participant, ErrorCode::ConstraintMismatch);
+        let amount = r.amount;
+
+        let w = &mut ctx.accounts.warehouse;
+        require!(amount <= w.outstanding()?, ErrorCode::ExceedsOutstandingReceipts);
+        w.total_burned = w.total_burned.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+        let l = &mut ctx.accounts.lot;
+        require!(amount <= l.outstanding()?, ErrorCode::ExceedsOutstandingReceipts);
+        l.quantity_burned = l.quantity_burned.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+        w.storage_fees_accrued = w.storage_fees_accrued.checked_add(r.storage_fee).ok_or(ErrorCode::MathOverflow)?;
+
+        let redemption_key = r.key();
         token::burn(
-            CpiContext::new(
+            CpiContext::new_with_signer(
                 ctx.accounts.token_program.to_account_info(),
                 Burn {
                     mint: ctx.accounts.receipt_mint.to_account_info(),
-                    from: ctx.accounts.from_receipt_ata.to_account_info(),
-                    authority: ctx.accounts.owner.to_account_info(),
+                    from: ctx.accounts.redemption_vault.to_account_info(),
+                    authority: ctx.accounts.redemption_auth.to_account_info(),
                 },
+                &[&[b"redemption_auth", redemption_key.as_ref(), &[r.auth_bump]]],
             ),
             amount,
         )?;
-        emit!(ReceiptBurned {
-            owner: ctx.accounts.owner.key(),
+        r.status = RedemptionStatus::Released as u8;
+        r.closed_ts = Clock::get()?.unix_timestamp;
+        emit!(RedemptionReleased {
+            warehouse: r.warehouse,
+            redemption: redemption_key,
+            lot_id: l.lot_id,
+            holder: r.holder,
             amount,
         });
         Ok(())
     }
 
+    /// Warehouse declines an open redemption; the escrow goes back to the holder.
+    pub fn cancel_redemption<'info>(ctx: Context<'_, '_, '_, 'info, CloseRedemption<'info>>) -> Result<()> {
+        require_keys_eq!(ctx.accounts.warehouse.authority, ctx.accounts.signer.key(), ErrorCode::Unauthorized);
+        return_redemption_escrow(ctx.accounts, ctx.remaining_accounts, RedemptionStatus::Cancelled)
+    }
+
+    /// Timeout path: the holder takes the escrow back if the warehouse never acted.
+    pub fn reclaim_redemption<'info>(ctx: Context<'_, '_, '_, 'info, CloseRedemption<'info>>) -> Result<()> {
+        require_keys_eq!(ctx.accounts.redemption.holder, ctx.accounts.signer.key(), ErrorCode::Unauthorized);
+        require!(Clock::get()?.unix_timestamp >= ctx.accounts.redemption.expires_ts, ErrorCode::RedemptionNotExpired);
+        return_redemption_escrow(ctx.accounts, ctx.remaining_accounts, RedemptionStatus::Expired)
+    }
+
+    /// Read-only lot lookup (simulate and read the return data).
+    pub fn get_lot(ctx: Context<GetLot>) -> Result<ReceiptLot> {
+        Ok((*ctx.accounts.lot).clone())
+    }
+
+    // --- Proof of reserve ---
+    /// Warehouse or auditor publishes a Merkle root over every physical lot on hand plus the total
+    /// quantity. Leaves are `sha256(lot_pubkey || quantity_le)`, pairs are hashed in sorted order.
+    pub fn post_reserve_snapshot(
+        ctx: Context<PostReserveSnapshot>,
+        merkle_root: [u8; 32],
+        total_quantity: u64,
+        lot_count: u32,
+        as_of_ts: i64,
+    ) -> Result<()> {
+        let signer = ctx.accounts.poster.key();
+        require!(
+            signer == ctx.accounts.warehouse.authority || signer == ctx.accounts.market.auditor,
+            ErrorCode::Unauthorized
+        );
+        let now = Clock::get()?.unix_timestamp;
+        let snap = &mut ctx.accounts.reserve_snapshot;
+        require!(as_of_ts <= now && as_of_ts >= snap.as_of_ts, ErrorCode::InvalidSnapshotTime);
+
+        snap.warehouse = ctx.accounts.warehouse.key();
+        snap.merkle_root = merkle_root;
+        snap.total_quantity = total_quantity;
+        snap.lot_count = lot_count;
+        snap.as_of_ts = as_of_ts;
+        snap.posted_ts = now;
+        snap.posted_by = signer;
+        snap.seq = snap.seq.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+        snap.bump = ctx.bumps.reserve_snapshot;
+
+        emit!(ReserveSnapshotPosted {
+            warehouse: snap.warehouse,
+            seq: snap.seq,
+            merkle_root,
+            total_quantity,
+            lot_count,
+            as_of_ts,
+        });
+        // The mint is shared by the market's warehouses, so compare against this warehouse's share.
+        let outstanding = ctx.accounts.warehouse.outstanding()?;
+        if outstanding > total_quantity {
+            emit!(ReserveShortfall { warehouse: snap.warehouse, seq: snap.seq, outstanding, total_quantity });
+        }
+        Ok(())
+    }
+
+    /// Anyone can check that `lot` with `quantity` is included in the warehouse's latest snapshot.
+    pub fn verify_lot_inclusion(ctx: Context<VerifyLotInclusion>, quantity: u64, proof: Vec<[u8; 32]>) -> Result<()> {
+        require!(proof.len() <= MAX_MERKLE_DEPTH, ErrorCode::InvalidMerkleProof);
+        let snap = &ctx.accounts.reserve_snapshot;
+        let lot_key = ctx.accounts.lot.key();
+        let leaf = hashv(&[lot_key.as_ref(), &quantity.to_le_bytes()]).to_bytes();
+        require!(verify_merkle_proof(leaf, &proof, snap.merkle_root), ErrorCode::InvalidMerkleProof);
+        emit!(LotInclusionVerified {
+            warehouse: snap.warehouse,
+            lot: lot_key,
+            seq: snap.seq,
+            quantity,
+            outstanding: ctx.accounts.lot.outstanding()?,
+        });
+        Ok(())
+    }
+
+    // --- Receipt allowlist (Token-2022 transfer hook) ---
+    /// Market authority onboards (or offboards) a holder of the receipt mint.
+    pub fn set_participant(ctx: Context<SetParticipant>, owner: Pubkey, is_active: bool) -> Result<()> {
+        let a = &mut *ctx.accounts;
+        authorize_admin(&a.market, &a.signer, &a.council, &mut a.council_proposal, CouncilAction::SetParticipant { owner, is_active })?;
+        let p = &mut ctx.accounts.participant;
+        p.market = ctx.accounts.market.key();
+        p.owner = owner;
+        p.is_active = is_active;
+        p.updated_by = ctx.accounts.signer.key();
+        p.updated_ts = Clock::get()?.unix_timestamp;
+        p.bump = ctx.bumps.participant;
+        emit!(ParticipantSet { market: p.market, owner, is_active });
+        Ok(())
+    }
+
+    /// Writes the extra accounts Token-2022 resolves for every receipt transfer: the market and
+    /// the `Participant` PDAs of the source and destination owners.
+    pub fn initialize_extra_account_meta_list(ctx: Context<InitReceiptHook>) -> Result<()> {
+        let a = &mut *ctx.accounts;
+        let expected = CouncilAction::InitReceiptHook { receipt_mint: a.receipt_mint.key() };
+        authorize_admin(&a.market, &a.signer, &a.council, &mut a.council_proposal, expected)?;
+        let metas = receipt_hook_metas(&ctx.accounts.market.key())?;
+        ExtraAccountMetaList::init::<ExecuteInstruction>(
+            &mut ctx.accounts.extra_account_meta_list.try_borrow_mut_data()?,
+            &metas,
+        )?;
+        emit!(ReceiptHookInitialized { market: ctx.accounts.market.key(), receipt_mint: ctx.accounts.receipt_mint.key() });
+        Ok(())
+    }
+
+    /// Transfer hook: both owners must be active participants of the market.
+    #[interface(spl_transfer_hook_interface::execute)]
+    pub fn transfer_hook(ctx: Context<ExecuteTransferHook>, _amount: u64) -> Result<()> {
+        let a = &ctx.accounts;
+        {
+            // Only reachable from inside a Token-2022 transfer.
+            let info = a.source_token.to_account_info();
+            let data = info.try_borrow_data()?;
+            let state = StateWithExtensions::<spl_token_2022::state::Account>::unpack(&data)?;
+            require!(bool::from(state.get_extension::<TransferHookAccount>()?.transferring), ErrorCode::NotTransferring);
+        }
+        let market_key = a.market.key();
+        check_participant(&a.source_participant, &market_key, &a.source_token.owner)?;
+        check_participant(&a.destination_participant, &market_key, &a.destination_token.owner)?;
+        Ok(())
+    }
+
     // --- Deal lifecycle ---
     pub fn open_deal(
         ctx: Context<OpenDeal>,
@@ -258,32 +1246,26 @@ pub mod tokenized_warehouse_receipts {
         initial_margin_short: u64,
     ) -> Result<()> {
         let market = &ctx.accounts.market;
-        require!(!market.is_paused, ErrorCode::MarketPaused);
         require!(deal_version == DEAL_VERSION, ErrorCode::DealVersionMismatch);
-        require!(settle_ts > Clock::get()?.unix_timestamp, ErrorCode::InvalidSettlementTime);
-        require!(is_allowed_collateral(market, &ctx.accounts.quote_mint.key()), ErrorCode::CollateralNotAllowed);
+        check_new_deal(market, &ctx.accounts.quote_mint.key(), settle_ts)?;
 
         let deal = &mut ctx.accounts.deal;
-        deal.version = VERSION;
-        deal.deal_version = deal_version;
-        deal.market = market.key();
-        deal.deal_id = deal_id;
-        deal.long = ctx.accounts.long.key();
-        deal.short = ctx.accounts.short.key();
-        deal.quote_mint = ctx.accounts.quote_mint.key();
-        deal.receipt_mint = market.receipt_mint;
-        deal.strike_price = strike_price;
-        deal.price_exponent = market.price_exponent;
-        deal.qty_receipt_amount = qty_receipt_amount;
-        deal.settle_ts = settle_ts;
-        deal.settlement_kind = settlement_kind as u8;
-        deal.long_margin = 0;
-        deal.short_margin = 0;
-        deal.fee_bps = market.fee_bps;
-        deal.is_settled = false;
-        deal.is_frozen = false;
-        deal.bump = ctx.bumps.deal;
-        deal.vault_bump = ctx.bumps.vault_auth;
+        init_deal(
+            deal,
+            market,
+            &NewDeal {
+                deal_id,
+                long: ctx.accounts.long.key(),
+                short: ctx.accounts.short.key(),
+                quote_mint: ctx.accounts.quote_mint.key(),
+                strike_price,
+                qty_receipt_amount,
+                settle_ts,
+                settlement_kind: settlement_kind as u8,
+            },
+            ctx.bumps.deal,
+            ctx.bumps.vault_auth,
+        );
 
         // Margin checks (dynamic)
         let snap = MarketSnapshot::from(market);
@@ -292,62 +1274,245 @@ pub mod tokenized_warehouse_receipts {
 
         // Fund margin vaults
         if initial_margin_long > 0 {
-            token::transfer(
+            token::transfer_checked(
                 CpiContext::new(
                     ctx.accounts.token_program.to_account_info(),
-                    Transfer {
+                    TransferChecked {
                         from: ctx.accounts.long_quote_ata.to_account_info(),
+                        mint: ctx.accounts.quote_mint.to_account_info(),
                         to: ctx.accounts.long_margin_vault.to_account_info(),
                         authority: ctx.accounts.long.to_account_info(),
                     },
                 ),
                 initial_margin_long,
+                ctx.accounts.quote_mint.decimals,
             )?;
             deal.long_margin = deal.long_margin.checked_add(initial_margin_long).ok_or(ErrorCode::MathOverflow)?;
         }
         if initial_margin_short > 0 {
-            token::transfer(
+            token::transfer_checked(
                 CpiContext::new(
                     ctx.accounts.token_program.to_account_info(),
-                    Transfer {
+                    TransferChecked {
                         from: ctx.accounts.short_quote_ata.to_account_info(),
+                        mint: ctx.accounts.quote_mint.to_account_info(),
                         to: ctx.accounts.short_margin_vault.to_account_info(),
                         authority: ctx.accounts.short.to_account_info(),
                     },
                 ),
                 initial_margin_short,
+                ctx.accounts.quote_mint.decimals,
             )?;
             deal.short_margin = deal.short_margin.checked_add(initial_margin_short).ok_or(ErrorCode::MathOverflow)?;
         }
 
-        emit!(DealOpened {
-            market: market.key(),
-            deal: deal.key(),
-            deal_id,
-            long: deal.long,
-            short: deal.short,
-            quote_mint: deal.quote_mint,
-            receipt_mint: deal.receipt_mint,
-            strike_price,
+        emit_deal_opened(deal);
+        Ok(())
+    }
+
+    // --- Two-step deal offers ---
+    /// Maker escrows its initial margin behind a standing offer. `taker` restricts who may accept
+    /// (`Pubkey::default()` = anyone); the offer lapses at `expires_ts`.
+    pub fn propose_deal(
+        ctx: Context<ProposeDeal>,
+        offer_id: u64,
+        deal_version: u8,
+        side: crate::Side, // maker side
+        taker: Pubkey,
+        deal_id: u64,
+        strike_price: u64,
+        qty_receipt_amount: u64,
+        settle_ts: i64,
+        settlement_kind: crate::SettlementKind,
+        margin: u64,
+        expires_ts: i64,
+    ) -> Result<()> {
+        let market = &ctx.accounts.market;
+        let now = Clock::get()?.unix_timestamp;
+        require!(deal_version == DEAL_VERSION, ErrorCode::DealVersionMismatch);
+        check_new_deal(market, &ctx.accounts.quote_mint.key(), settle_ts)?;
+        require!(expires_ts > now && expires_ts <= settle_ts, ErrorCode::InvalidOfferExpiry);
+        let required = required_initial_margin(&MarketSnapshot::from(market), strike_price, qty_receipt_amount);
+        require!(margin >= required, ErrorCode::InsufficientInitialMargin);
+
+        let o = &mut ctx.accounts.offer;
+        o.market = market.key();
+        o.maker = ctx.accounts.maker.key();
+        o.offer_id = offer_id;
+        o.maker_side = side as u8;
+        o.taker = taker;
+        o.quote_mint = ctx.accounts.quote_mint.key();
+        o.deal_id = deal_id;
+        o.strike_price = strike_price;
+        o.qty_receipt_amount = qty_receipt_amount;
+        o.settle_ts = settle_ts;
+        o.settlement_kind = settlement_kind as u8;
+        o.maker_margin = margin;
+        o.expires_ts = expires_ts;
+        o.status = OfferStatus::Open as u8;
+        o.deal = Pubkey::default();
+        o.bump = ctx.bumps.offer;
+        o.auth_bump = ctx.bumps.offer_auth;
+
+        if margin > 0 {
+            token::transfer_checked(
+                CpiContext::new(
+                    ctx.accounts.token_program.to_account_info(),
+                    TransferChecked {
+                        from: ctx.accounts.maker_quote_ata.to_account_info(),
+                        mint: ctx.accounts.quote_mint.to_account_info(),
+                        to: ctx.accounts.offer_vault.to_account_info(),
+                        authority: ctx.accounts.maker.to_account_info(),
+                    },
+                ),
+                margin,
+                ctx.accounts.quote_mint.decimals,
+            )?;
+        }
+        emit!(DealOfferProposed {
+            market: o.market,
+            offer: o.key(),
+            maker: o.maker,
+            side: o.maker_side,
+            taker,
+            deal_id,
+            strike_price,
             qty_receipt_amount,
             settle_ts,
-            kind: deal.settlement_kind,
-            fee_bps: deal.fee_bps,
+            margin,
+            expires_ts,
         });
         Ok(())
     }
 
+    /// Taker funds the other side; the `Deal` is created with the offer's terms after the same
+    /// initial-margin check `open_deal` runs (at current market vol).
+    pub fn accept_deal(ctx: Context<AcceptDeal>, taker_margin: u64) -> Result<()> {
+        let o = &ctx.accounts.offer;
+        let taker = ctx.accounts.taker.key();
+        require!(o.status == OfferStatus::Open as u8, ErrorCode::OfferNotOpen);
+        require!(Clock::get()?.unix_timestamp < o.expires_ts, ErrorCode::OfferExpired);
+        if o.taker != Pubkey::default() {
+            require_keys_eq!(o.taker, taker, ErrorCode::Unauthorized);
+        }
+        let maker_is_long = o.maker_side == crate::Side::Long as u8;
+        let (long, short) = if maker_is_long { (o.maker, taker) } else { (taker, o.maker) };
+        require_keys_eq!(ctx.accounts.long.key(), long, ErrorCode::ConstraintMismatch);
+        require_keys_eq!(ctx.accounts.short.key(), short, ErrorCode::ConstraintMismatch);
+
+        let market = &ctx.accounts.market;
+        check_new_deal(market, &o.quote_mint, o.settle_ts)?;
+        let required = required_initial_margin(&MarketSnapshot::from(market), o.strike_price, o.qty_receipt_amount);
+        require!(o.maker_margin >= required && taker_margin >= required, ErrorCode::InsufficientInitialMargin);
+
+        let offer_key = o.key();
+        let maker_margin = o.maker_margin;
+        let auth_bump = o.auth_bump;
+        let deal = &mut ctx.accounts.deal;
+        init_deal(
+            deal,
+            market,
+            &NewDeal {
+                deal_id: o.deal_id,
+                long,
+                short,
+                quote_mint: o.quote_mint,
+                strike_price: o.strike_price,
+                qty_receipt_amount: o.qty_receipt_amount,
+                settle_ts: o.settle_ts,
+                settlement_kind: o.settlement_kind,
+            },
+            ctx.bumps.deal,
+            ctx.bumps.vault_auth,
+        );
+
+        let (maker_vault, taker_vault) = if maker_is_long {
+            (&ctx.accounts.long_margin_vault, &ctx.accounts.short_margin_vault)
+        } else {
+            (&ctx.accounts.short_margin_vault, &ctx.accounts.long_margin_vault)
+        };
+        if maker_margin > 0 {
+            token::transfer_checked(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    TransferChecked {
+                        from: ctx.accounts.offer_vault.to_account_info(),
+                        mint: ctx.accounts.quote_mint.to_account_info(),
+                        to: maker_vault.to_account_info(),
+                        authority: ctx.accounts.offer_auth.to_account_info(),
+                    },
+                    &[&[b"offer_auth", offer_key.as_ref(), &[auth_bump]]],
+                ),
+                maker_margin,
+                ctx.accounts.quote_mint.decimals,
+            )?;
+        }
+        if taker_margin > 0 {
+            token::transfer_checked(
+                CpiContext::new(
+                    ctx.accounts.token_program.to_account_info(),
+                    TransferChecked {
+                        from: ctx.accounts.taker_quote_ata.to_account_info(),
+                        mint: ctx.accounts.quote_mint.to_account_info(),
+                        to: taker_vault.to_account_info(),
+                        authority: ctx.accounts.taker.to_account_info(),
+                    },
+                ),
+                taker_margin,
+                ctx.accounts.quote_mint.decimals,
+            )?;
+        }
+        let (long_margin, short_margin) = if maker_is_long { (maker_margin, taker_margin) } else { (taker_margin, maker_margin) };
+        deal.long_margin = long_margin;
+        deal.short_margin = short_margin;
+
+        let o = &mut ctx.accounts.offer;
+        o.status = OfferStatus::Accepted as u8;
+        o.deal = deal.key();
+        emit!(DealOfferAccepted { offer: offer_key, deal: o.deal, taker, taker_margin });
+        emit_deal_opened(deal);
+        Ok(())
+    }
+
+    /// Maker withdraws an open offer (before or after expiry) and gets its margin back.
+    pub fn cancel_offer(ctx: Context<CancelOffer>) -> Result<()> {
+        let o = &mut ctx.accounts.offer;
+        require!(o.status == OfferStatus::Open as u8, ErrorCode::OfferNotOpen);
+        let offer_key = o.key();
+        if o.maker_margin > 0 {
+            token::transfer_checked(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    TransferChecked {
+                        from: ctx.accounts.offer_vault.to_account_info(),
+                        mint: ctx.accounts.quote_mint.to_account_info(),
+                        to: ctx.accounts.maker_quote_ata.to_account_info(),
+                        authority: ctx.accounts.offer_auth.to_account_info(),
+                    },
+                    &[&[b"offer_auth", offer_key.as_ref(), &[o.auth_bump]]],
+                ),
+                o.maker_margin,
+                ctx.accounts.quote_mint.decimals,
+            )?;
+        }
+        o.status = OfferStatus::Cancelled as u8;
+        emit!(DealOfferCancelled { offer: offer_key, maker: o.maker, refunded: o.maker_margin });
+        Ok(())
+    }
+
     pub fn freeze_deal(ctx: Context<AdminDealWrite>) -> Result<()> {
-        only_admin(&ctx.accounts.market, &ctx.accounts.signer)?;
-        let d = &mut ctx.accounts.deal;
+        let a = &mut *ctx.accounts;
+        authorize_admin(&a.market, &a.signer, &a.council, &mut a.council_proposal, CouncilAction::FreezeDeal { deal: a.deal.key() })?;
+        let d = &mut a.deal;
         d.is_frozen = true;
         emit!(DealFrozen { deal: d.key() });
         Ok(())
     }
 
     pub fn unfreeze_deal(ctx: Context<AdminDealWrite>) -> Result<()> {
-        only_admin(&ctx.accounts.market, &ctx.accounts.signer)?;
-        let d = &mut ctx.accounts.deal;
+        let a = &mut *ctx.accounts;
+        authorize_admin(&a.market, &a.signer, &a.council, &mut a.council_proposal, CouncilAction::UnfreezeDeal { deal: a.deal.key() })?;
+        let d = &mut a.deal;
         d.is_frozen = false;
         emit!(DealUnfrozen { deal: d.key() });
         Ok(())
@@ -361,32 +1526,36 @@ pub mod tokenized_warehouse_receipts {
         match side {
             crate::Side::Long => {
                 require_keys_eq!(deal.long, ctx.accounts.payer.key(), ErrorCode::Unauthorized);
-                token::transfer(
+                token::transfer_checked(
                     CpiContext::new(
                         ctx.accounts.token_program.to_account_info(),
-                        Transfer {
+                        TransferChecked {
                             from: ctx.accounts.payer_quote_ata.to_account_info(),
+                            mint: ctx.accounts.quote_mint.to_account_info(),
                             to: ctx.accounts.long_margin_vault.to_account_info(),
                             authority: ctx.accounts.payer.to_account_info(),
                         },
                     ),
                     amount,
+                    ctx.accounts.quote_mint.decimals,
                 )?;
                 deal.long_margin = deal.long_margin.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
                 emit!(MarginDeposited { deal: deal.key(), side: 0, amount });
             }
             crate::Side::Short => {
                 require_keys_eq!(deal.short, ctx.accounts.payer.key(), ErrorCode::Unauthorized);
-                token::transfer(
+                token::transfer_checked(
                     CpiContext::new(
                         ctx.accounts.token_program.to_account_info(),
-                        Transfer {
+                        TransferChecked {
                             from: ctx.accounts.payer_quote_ata.to_account_info(),
+                            mint: ctx.accounts.quote_mint.to_account_info(),
                             to: ctx.accounts.short_margin_vault.to_account_info(),
                             authority: ctx.accounts.payer.to_account_info(),
                         },
                     ),
                     amount,
+                    ctx.accounts.quote_mint.decimals,
                 )?;
                 deal.short_margin = deal.short_margin.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
                 emit!(MarginDeposited { deal: deal.key(), side: 1, amount });
@@ -395,6 +1564,113 @@ pub mod tokenized_warehouse_receipts {
         Ok(())
     }
 
+    /// Hands one side of a live deal to a new counterparty. Both parties sign; the incoming party
+    /// posts at least `required_initial_margin` at current vol and the outgoing party gets its
+    /// recorded margin back, after any loss against a fresh print for the deal's expiry is
+    /// settled. The deal keeps its address (derived from the original parties).
+    pub fn novate_deal(ctx: Context<NovateDeal>, side: crate::Side, incoming_margin: u64) -> Result<()> {
+        let market = &ctx.accounts.market;
+        require!(!market.is_paused, ErrorCode::MarketPaused);
+        let deal = &mut ctx.accounts.deal;
+        require!(!deal.is_frozen, ErrorCode::DealFrozen);
+        require!(!deal.is_settled, ErrorCode::AlreadySettled);
+        let now = Clock::get()?.unix_timestamp;
+        require!(now < deal.settle_ts, ErrorCode::InvalidSettlementTime);
+
+        let outgoing = ctx.accounts.outgoing.key();
+        let incoming = ctx.accounts.incoming.key();
+        let (holder, counterparty) = match side {
+            crate::Side::Long => (deal.long, deal.short),
+            crate::Side::Short => (deal.short, deal.long),
+        };
+        require_keys_eq!(holder, outgoing, ErrorCode::Unauthorized);
+        require_keys_neq!(incoming, outgoing, ErrorCode::ConstraintMismatch);
+        require_keys_neq!(incoming, counterparty, ErrorCode::ConstraintMismatch);
+
+        // The outgoing side can't hand an unrealized loss to the incoming one: a cash deal is
+        // marked first (the loss moves to the counterparty), and a physical deal, which settles
+        // at strike, can only be novated while the outgoing side isn't below it.
+        let mark_price = fresh_mark_price(market, deal.settle_ts, deal.price_exponent, now)?;
+        if deal.settlement_kind == crate::SettlementKind::Cash as u8 {
+            let ds = DealSnapshot::from(deal);
+            post_variation_margin(
+                &ctx.accounts.token_program,
+                &ctx.accounts.quote_mint,
+                &ctx.accounts.long_margin_vault,
+                &ctx.accounts.short_margin_vault,
+                &ctx.accounts.vault_auth,
+                deal,
+                &ds,
+                mark_price,
+            )?;
+        }
+        let pnl_long = calc_pnl_long(&DealSnapshot::from(deal), mark_price, deal.qty_receipt_amount);
+        let side_pnl = match side {
+            crate::Side::Long => pnl_long,
+            crate::Side::Short => -pnl_long,
+        };
+        require!(side_pnl >= 0, ErrorCode::NovationAtLoss);
+
+        let required = required_initial_margin(&MarketSnapshot::from(market), deal.strike_price, deal.qty_receipt_amount);
+        require!(incoming_margin >= required, ErrorCode::InsufficientInitialMargin);
+
+        let vault = match side {
+            crate::Side::Long => &ctx.accounts.long_margin_vault,
+            crate::Side::Short => &ctx.accounts.short_margin_vault,
+        };
+        let returned = match side {
+            crate::Side::Long => deal.long_margin,
+            crate::Side::Short => deal.short_margin,
+        };
+        if returned > 0 {
+            transfer_signed(
+                &ctx.accounts.token_program,
+                vault,
+                &ctx.accounts.outgoing_quote_ata,
+                &ctx.accounts.quote_mint,
+                &ctx.accounts.vault_auth,
+                &deal.key(),
+                deal.vault_bump,
+                returned,
+            )?;
+        }
+        if incoming_margin > 0 {
+            token::transfer_checked(
+                CpiContext::new(
+                    ctx.accounts.token_program.to_account_info(),
+                    TransferChecked {
+                        from: ctx.accounts.incoming_quote_ata.to_account_info(),
+                        mint: ctx.accounts.quote_mint.to_account_info(),
+                        to: vault.to_account_info(),
+                        authority: ctx.accounts.incoming.to_account_info(),
+                    },
+                ),
+                incoming_margin,
+                ctx.accounts.quote_mint.decimals,
+            )?;
+        }
+
+        match side {
+            crate::Side::Long => {
+                deal.long = incoming;
+                deal.long_margin = incoming_margin;
+            }
+            crate::Side::Short => {
+                deal.short = incoming;
+                deal.short_margin = incoming_margin;
+            }
+        }
+        emit!(DealNovated {
+            deal: deal.key(),
+            side: if matches!(side, crate::Side::Long) { 0 } else { 1 },
+            outgoing,
+            incoming,
+            returned,
+            incoming_margin,
+        });
+        Ok(())
+    }
+
     /// Cross-Margin: create a per-(market, owner, quote_mint) vault (PDA) to share margin across deals.
     pub fn cm_create(ctx: Context<CmCreate>) -> Result<()> {
         let cm = &mut ctx.accounts.cross_margin;
@@ -402,6 +1678,7 @@ pub mod tokenized_warehouse_receipts {
         cm.owner = ctx.accounts.owner.key();
         cm.quote_mint = ctx.accounts.quote_mint.key();
         cm.vault_bump = ctx.bumps.cm_vault_auth;
+        cm.locked = 0;
         emit!(CrossMarginCreated {
             market: cm.market,
             owner: cm.owner,
@@ -414,16 +1691,18 @@ pub mod tokenized_warehouse_receipts {
     /// Deposit to cross-margin vault
     pub fn cm_deposit(ctx: Context<CmDeposit>, amount: u64) -> Result<()> {
         require!(amount > 0, ErrorCode::ZeroAmount);
-        token::transfer(
+        token::transfer_checked(
             CpiContext::new(
                 ctx.accounts.token_program.to_account_info(),
-                Transfer {
+                TransferChecked {
                     from: ctx.accounts.owner_quote_ata.to_account_info(),
+                    mint: ctx.accounts.quote_mint.to_account_info(),
                     to: ctx.accounts.cm_vault_ata.to_account_info(),
                     authority: ctx.accounts.owner.to_account_info(),
                 },
             ),
             amount,
+            ctx.accounts.quote_mint.decimals,
         )?;
         emit!(CrossMarginDeposited {
             market: ctx.accounts.cross_margin.market,
@@ -436,10 +1715,12 @@ pub mod tokenized_warehouse_receipts {
     /// Withdraw from cross-margin vault
     pub fn cm_withdraw(ctx: Context<CmWithdraw>, amount: u64) -> Result<()> {
         require!(amount > 0, ErrorCode::ZeroAmount);
-        transfer_signed(
+        require!(cm_free_margin(&ctx.accounts.cross_margin, &ctx.accounts.cm_vault_ata) >= amount, ErrorCode::InsufficientFreeMargin);
+        cm_transfer_signed(
             &ctx.accounts.token_program,
             &ctx.accounts.cm_vault_ata,
             &ctx.accounts.owner_quote_ata,
+            &ctx.accounts.quote_mint,
             &ctx.accounts.cm_vault_auth,
             &ctx.accounts.cross_margin.key(),
             ctx.accounts.cross_margin.vault_bump,
@@ -460,6 +1741,7 @@ pub mod tokenized_warehouse_receipts {
         require!(deal.quote_mint == ctx.accounts.cross_margin.quote_mint, ErrorCode::ConstraintMismatch);
         require_keys_eq!(deal.market, ctx.accounts.market.key(), ErrorCode::ConstraintMismatch);
         require!(!deal.is_frozen, ErrorCode::DealFrozen);
+        require!(cm_free_margin(&ctx.accounts.cross_margin, &ctx.accounts.cm_vault_ata) >= amount, ErrorCode::InsufficientFreeMargin);
 
         match side {
             crate::Side::Long => require_keys_eq!(deal.long, ctx.accounts.owner.key(), ErrorCode::Unauthorized),
@@ -471,10 +1753,11 @@ pub mod tokenized_warehouse_receipts {
             crate::Side::Short => &ctx.accounts.short_margin_vault,
         };
 
-        transfer_signed(
+        cm_transfer_signed(
             &ctx.accounts.token_program,
             &ctx.accounts.cm_vault_ata,
             dst,
+            &ctx.accounts.quote_mint,
             &ctx.accounts.cm_vault_auth,
             &ctx.accounts.cross_margin.key(),
             ctx.accounts.cross_margin.vault_bump,
@@ -513,6 +1796,7 @@ pub mod tokenized_warehouse_receipts {
             &ctx.accounts.token_program,
             src,
             &ctx.accounts.cm_vault_ata,
+            &ctx.accounts.quote_mint,
             &ctx.accounts.vault_auth,
             &deal.key(),
             deal.vault_bump,
@@ -532,26 +1816,248 @@ pub mod tokenized_warehouse_receipts {
         Ok(())
     }
 
+    // --- Order book ---
+    /// Opens a book for one market, collateral mint and expiry. Permissionless; deals matched on
+    /// the book all share its `settle_ts` and settlement kind.
+    pub fn init_order_book(
+        ctx: Context<InitOrderBook>,
+        settle_ts: i64,
+        settlement_kind: crate::SettlementKind,
+    ) -> Result<()> {
+        check_new_deal(&ctx.accounts.market, &ctx.accounts.quote_mint.key(), settle_ts)?;
+        let b = &mut ctx.accounts.book;
+        b.market = ctx.accounts.market.key();
+        b.quote_mint = ctx.accounts.quote_mint.key();
+        b.settle_ts = settle_ts;
+        b.settlement_kind = settlement_kind as u8;
+        b.next_order_id = 0;
+        b.fill_count = 0;
+        b.bump = ctx.bumps.book;
+        b.bid_count = 0;
+        b.ask_count = 0;
+        emit!(OrderBookCreated { market: b.market, book: b.key(), quote_mint: b.quote_mint, settle_ts, kind: b.settlement_kind });
+        Ok(())
+    }
+
+    /// Rests a bid (`Side::Long`) or ask (`Side::Short`) at `strike_price` for `qty_receipt_amount`.
+    /// The initial margin for the full size is locked in the owner's cross-margin vault until the
+    /// order fills or is cancelled.
+    pub fn place_order(
+        ctx: Context<PlaceOrder>,
+        side: crate::Side,
+        strike_price: u64,
+        qty_receipt_amount: u64,
+    ) -> Result<()> {
+        require!(strike_price > 0 && qty_receipt_amount > 0, ErrorCode::ZeroAmount);
+        let market = &ctx.accounts.market;
+        let book = &mut ctx.accounts.book;
+        check_new_deal(market, &book.quote_mint, book.settle_ts)?;
+
+        let lock = required_initial_margin(&MarketSnapshot::from(market), strike_price, qty_receipt_amount);
+        require!(cm_free_margin(&ctx.accounts.cross_margin, &ctx.accounts.cm_vault_ata) >= lock, ErrorCode::InsufficientFreeMargin);
+        let cm = &mut ctx.accounts.cross_margin;
+        cm.locked = cm.locked.checked_add(lock).ok_or(ErrorCode::MathOverflow)?;
+
+        let o = &mut ctx.accounts.order;
+        o.book = book.key();
+        o.owner = ctx.accounts.owner.key();
+        o.cross_margin = cm.key();
+        o.order_id = book.next_order_id;
+        o.side = side as u8;
+        o.strike_price = strike_price;
+        o.qty_receipt_amount = qty_receipt_amount;
+        o.remaining_qty = qty_receipt_amount;
+        o.locked_margin = lock;
+        o.status = OrderStatus::Open as u8;
+        o.created_ts = Clock::get()?.unix_timestamp;
+        o.bump = ctx.bumps.order;
+        book.add(o.side, BookEntry { order_id: o.order_id, owner: o.owner, strike_price })?;
+        book.next_order_id = book.next_order_id.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+
+        emit!(OrderPlaced {
+            book: o.book,
+            order: o.key(),
+            order_id: o.order_id,
+            owner: o.owner,
+            side: o.side,
+            strike_price,
+            qty_receipt_amount,
+            locked_margin: lock,
+        });
+        Ok(())
+    }
+
+    /// Owner pulls a resting order; whatever margin it still locks is released.
+    pub fn cancel_order(ctx: Context<CancelOrder>) -> Result<()> {
+        let o = &mut ctx.accounts.order;
+        require!(o.status == OrderStatus::Open as u8, ErrorCode::OrderNotOpen);
+        let cm = &mut ctx.accounts.cross_margin;
+        cm.locked = cm.locked.saturating_sub(o.locked_margin);
+        let released = o.locked_margin;
+        o.locked_margin = 0;
+        o.status = OrderStatus::Cancelled as u8;
+        ctx.accounts.book.remove(o.side, o.order_id);
+        emit!(OrderCancelled { book: o.book, order: o.key(), owner: o.owner, remaining_qty: o.remaining_qty, released });
+        Ok(())
+    }
+
+    /// Permissionless crank: crosses a bid with an ask on the same book and opens a `Deal` for
+    /// the overlap at the older order's price. Fills follow price-time priority: each order must
+    /// be the best-priced, oldest open order on its side, ignoring the counterparty's own orders.
+    /// Both sides must still cover `required_initial_margin` at that price, exactly as in
+    /// `open_deal`; the margin moves from their cross-margin vaults into the deal's vaults.
+    /// `deal_id` is chosen by the caller, as in `open_deal`.
+    pub fn match_orders(ctx: Context<MatchOrders>, deal_id: u64) -> Result<()> {
+        let bid = &ctx.accounts.bid;
+        let ask = &ctx.accounts.ask;
+        require!(bid.status == OrderStatus::Open as u8 && ask.status == OrderStatus::Open as u8, ErrorCode::OrderNotOpen);
+        require!(bid.side == crate::Side::Long as u8 && ask.side == crate::Side::Short as u8, ErrorCode::ConstraintMismatch);
+        require!(bid.strike_price >= ask.strike_price, ErrorCode::OrdersDoNotCross);
+        require_keys_neq!(bid.owner, ask.owner, ErrorCode::SelfTrade);
+
+        let book = &ctx.accounts.book;
+        require!(book.best(bid.side, &ask.owner) == Some(bid.order_id), ErrorCode::NotBestOrder);
+        require!(book.best(ask.side, &bid.owner) == Some(ask.order_id), ErrorCode::NotBestOrder);
+        let market = &ctx.accounts.market;
+        check_new_deal(market, &book.quote_mint, book.settle_ts)?;
+
+        // Resting (older) order sets the price.
+        let price = if bid.order_id < ask.order_id { bid.strike_price } else { ask.strike_price };
+        let qty = bid.remaining_qty.min(ask.remaining_qty);
+        let required = required_initial_margin(&MarketSnapshot::from(market), price, qty);
+
+        let bid_release = order_lock_release(bid, qty)?;
+        let ask_release = order_lock_release(ask, qty)?;
+        // Free margin once this fill's lock is released must cover the deal's initial margin.
+        let bid_free = cm_free_margin(&ctx.accounts.bid_cross_margin, &ctx.accounts.bid_cm_vault_ata)
+            .checked_add(bid_release).ok_or(ErrorCode::MathOverflow)?;
+        let ask_free = cm_free_margin(&ctx.accounts.ask_cross_margin, &ctx.accounts.ask_cm_vault_ata)
+            .checked_add(ask_release).ok_or(ErrorCode::MathOverflow)?;
+        require!(bid_free >= required && ask_free >= required, ErrorCode::InsufficientInitialMargin);
+
+        let deal = &mut ctx.accounts.deal;
+        init_deal(
+            deal,
+            market,
+            &NewDeal {
+                deal_id,
+                long: bid.owner,
+                short: ask.owner,
+                quote_mint: book.quote_mint,
+                strike_price: price,
+                qty_receipt_amount: qty,
+                settle_ts: book.settle_ts,
+                settlement_kind: book.settlement_kind,
+            },
+            ctx.bumps.deal,
+            ctx.bumps.vault_auth,
+        );
+
+        if required > 0 {
+            cm_transfer_signed(
+                &ctx.accounts.token_program,
+                &ctx.accounts.bid_cm_vault_ata,
+                &ctx.accounts.long_margin_vault,
+                &ctx.accounts.quote_mint,
+                &ctx.accounts.bid_cm_vault_auth,
+                &ctx.accounts.bid_cross_margin.key(),
+                ctx.accounts.bid_cross_margin.vault_bump,
+                required,
+            )?;
+            cm_transfer_signed(
+                &ctx.accounts.token_program,
+                &ctx.accounts.ask_cm_vault_ata,
+                &ctx.accounts.short_margin_vault,
+                &ctx.accounts.quote_mint,
+                &ctx.accounts.ask_cm_vault_auth,
+                &ctx.accounts.ask_cross_margin.key(),
+                ctx.accounts.ask_cross_margin.vault_bump,
+                required,
+            )?;
+        }
+        deal.long_margin = required;
+        deal.short_margin = required;
+
+        let bcm = &mut ctx.accounts.bid_cross_margin;
+        bcm.locked = bcm.locked.saturating_sub(bid_release);
+        let acm = &mut ctx.accounts.ask_cross_margin;
+        acm.locked = acm.locked.saturating_sub(ask_release);
+        for (o, release) in [(&mut ctx.accounts.bid, bid_release), (&mut ctx.accounts.ask, ask_release)] {
+            o.remaining_qty -= qty;
+            o.locked_margin -= release;
+            if o.remaining_qty == 0 {
+                o.status = OrderStatus::Filled as u8;
+            }
+        }
+        let book = &mut ctx.accounts.book;
+        book.fill_count = book.fill_count.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+        for o in [&ctx.accounts.bid, &ctx.accounts.ask] {
+            if o.status == OrderStatus::Filled as u8 {
+                book.remove(o.side, o.order_id);
+            }
+        }
+
+        emit!(OrderFilled {
+            book: book.key(),
+            deal: deal.key(),
+            bid: ctx.accounts.bid.key(),
+            ask: ctx.accounts.ask.key(),
+            price,
+            qty,
+            bid_remaining: ctx.accounts.bid.remaining_qty,
+            ask_remaining: ctx.accounts.ask.remaining_qty,
+        });
+        emit_deal_opened(deal);
+        Ok(())
+    }
+
+    /// Variation margin: moves PnL since the last mark between the deal's margin vaults at
+    /// `market.last_price`. Permissionless crank, intended to run after every `post_price`; the
+    /// print must be fresh and quoted for the deal's expiry (see `fresh_mark_price`).
+    pub fn mark_to_market(ctx: Context<MarkToMarket>) -> Result<()> {
+        require_keys_eq!(ctx.accounts.deal.market, ctx.accounts.market.key(), ErrorCode::ConstraintMismatch);
+        let market = &ctx.accounts.market;
+        require!(!ctx.accounts.deal.is_frozen, ErrorCode::DealFrozen);
+        require!(!ctx.accounts.deal.is_settled, ErrorCode::AlreadySettled);
+        require!(ctx.accounts.deal.settlement_kind == crate::SettlementKind::Cash as u8, ErrorCode::WrongSettlementKind);
+
+        let ds = DealSnapshot::from(&ctx.accounts.deal);
+        let now = Clock::get()?.unix_timestamp;
+        let mark_price = fresh_mark_price(market, ctx.accounts.deal.settle_ts, ds.price_exponent, now)?;
+        post_variation_margin(
+            &ctx.accounts.token_program,
+            &ctx.accounts.quote_mint,
+            &ctx.accounts.long_margin_vault,
+            &ctx.accounts.short_margin_vault,
+            &ctx.accounts.vault_auth,
+            &mut ctx.accounts.deal,
+            &ds,
+            mark_price,
+        )?;
+        Ok(())
+    }
+
     /// Cash settlement (full).
     pub fn settle_cash(ctx: Context<SettleCash>) -> Result<()> {
         require_keys_eq!(ctx.accounts.deal.market, ctx.accounts.market.key(), ErrorCode::ConstraintMismatch);
-        let market = &ctx.accounts.market;
         require!(!ctx.accounts.deal.is_frozen, ErrorCode::DealFrozen);
         require!(!ctx.accounts.deal.is_settled, ErrorCode::AlreadySettled);
         require!(ctx.accounts.deal.settlement_kind == crate::SettlementKind::Cash as u8, ErrorCode::WrongSettlementKind);
         let now = Clock::get()?.unix_timestamp;
         require!(now >= ctx.accounts.deal.settle_ts, ErrorCode::TooEarlyToSettle);
-        require!(market.last_price > 0, ErrorCode::NoSettlementPrice);
 
         // Immutable snapshots first (no mutable deal borrow yet)
         let ds = DealSnapshot::from(&ctx.accounts.deal);
-        let ms = MarketSnapshot::from(market);
+        let sp = &ctx.accounts.settlement_price;
+        require!(sp.finalized_ts != 0, ErrorCode::NoSettlementPrice);
+        let final_price = rescale_price(sp.price, sp.price_exponent, ds.price_exponent)?;
 
-        let pnl_long = calc_pnl_long(&ds, &ms, ds.qty_receipt_amount);
+        let pnl_long = calc_pnl_long(&ds, final_price, ds.qty_receipt_amount);
 
         // Call helper without borrowing the whole Context
         settle_cash_inner(
             &ctx.accounts.token_program,
+            &ctx.accounts.quote_mint,
             &ctx.accounts.short_margin_vault,
             &ctx.accounts.long_margin_vault,
             &ctx.accounts.long_receive_quote_ata,
@@ -568,14 +2074,183 @@ pub mod tokenized_warehouse_receipts {
 
         emit!(CashSettled {
             deal: ds.deal,
-            final_price: ms.last_price,
+            final_price,
+            pnl_long,
+        });
+        Ok(())
+    }
+
+    /// Early unwind at a price both parties sign for (in the deal's price exponent). Cash-settles
+    /// the residual PnL since the last mark like `settle_cash`, physical deals included, with the
+    /// deal's fee.
+    pub fn terminate_deal(ctx: Context<TerminateDeal>, close_price: u64) -> Result<()> {
+        require!(!ctx.accounts.deal.is_frozen, ErrorCode::DealFrozen);
+        require!(!ctx.accounts.deal.is_settled, ErrorCode::AlreadySettled);
+        require!(close_price > 0, ErrorCode::ZeroAmount);
+
+        let ds = DealSnapshot::from(&ctx.accounts.deal);
+        let pnl_long = calc_pnl_long(&ds, close_price, ds.qty_receipt_amount);
+
+        settle_cash_inner(
+            &ctx.accounts.token_program,
+            &ctx.accounts.quote_mint,
+            &ctx.accounts.short_margin_vault,
+            &ctx.accounts.long_margin_vault,
+            &ctx.accounts.long_quote_ata,
+            &ctx.accounts.short_quote_ata,
+            &ctx.accounts.fee_vault,
+            &ctx.accounts.vault_auth,
+            &ds,
+            pnl_long,
+        )?;
+
+        let deal = &mut ctx.accounts.deal;
+        deal.is_settled = true;
+
+        emit!(DealTerminated {
+            deal: ds.deal,
+            long: deal.long,
+            short: deal.short,
+            close_price,
+            pnl_long,
+            ts: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
+
+    /// Permissionless liquidation: marks the deal at `market.last_price` and force-closes the side
+    /// whose equity has fallen below maintenance margin. Counterparty receives PnL, caller a bounty.
+    /// The print must be fresh and quoted for the deal's expiry (see `fresh_mark_price`).
+    pub fn liquidate_deal(ctx: Context<LiquidateDeal>) -> Result<()> {
+        require_keys_eq!(ctx.accounts.deal.market, ctx.accounts.market.key(), ErrorCode::ConstraintMismatch);
+        let market = &ctx.accounts.market;
+        require!(!ctx.accounts.deal.is_frozen, ErrorCode::DealFrozen);
+        require!(!ctx.accounts.deal.is_settled, ErrorCode::AlreadySettled);
+
+        let ds = DealSnapshot::from(&ctx.accounts.deal);
+        let ms = MarketSnapshot::from(market);
+        let long_margin = ctx.accounts.deal.long_margin;
+        let short_margin = ctx.accounts.deal.short_margin;
+
+        let now = Clock::get()?.unix_timestamp;
+        let mark_price = fresh_mark_price(market, ctx.accounts.deal.settle_ts, ds.price_exponent, now)?;
+        let pnl_long = calc_pnl_long(&ds, mark_price, ds.qty_receipt_amount);
+        let maintenance = required_maintenance_margin(&ms, &ds) as i128;
+        let long_equity = long_margin as i128 + pnl_long;
+        let short_equity = short_margin as i128 - pnl_long;
+
+        let liquidated = if long_equity < maintenance {
+            crate::Side::Long
+        } else if short_equity < maintenance {
+            crate::Side::Short
+        } else {
+            return err!(ErrorCode::NotLiquidatable);
+        };
+
+        // 1) Losing side pays PnL to the winner, capped by what it has posted.
+        let mut long_left = long_margin;
+        let mut short_left = short_margin;
+        let mut pnl_paid: u64 = 0;
+        if pnl_long > 0 {
+            pnl_paid = pnl_long.unsigned_abs().min(short_margin as u128) as u64;
+            short_left -= pnl_paid;
+            if pnl_paid > 0 {
+                transfer_signed(
+                    &ctx.accounts.token_program,
+                    &ctx.accounts.short_margin_vault,
+                    &ctx.accounts.long_receive_quote_ata,
+                    &ctx.accounts.quote_mint,
+                    &ctx.accounts.vault_auth,
+                    &ds.deal,
+                    ds.vault_bump,
+                    pnl_paid,
+                )?;
+            }
+        } else if pnl_long < 0 {
+            pnl_paid = pnl_long.unsigned_abs().min(long_margin as u128) as u64;
+            long_left -= pnl_paid;
+            if pnl_paid > 0 {
+                transfer_signed(
+                    &ctx.accounts.token_program,
+                    &ctx.accounts.long_margin_vault,
+                    &ctx.accounts.short_receive_quote_ata,
+                    &ctx.accounts.quote_mint,
+                    &ctx.accounts.vault_auth,
+                    &ds.deal,
+                    ds.vault_bump,
+                    pnl_paid,
+                )?;
+            }
+        }
+
+        // 2) Liquidator bounty out of the liquidated side's remaining margin.
+        let bounty_target = (notional_at_strike(&ds) as u128)
+            .saturating_mul(LIQUIDATION_BOUNTY_BPS as u128)
+            / (BPS_DENOMINATOR as u128);
+        let (bounty_vault, remaining) = match liquidated {
+            crate::Side::Long => (&ctx.accounts.long_margin_vault, &mut long_left),
+            crate::Side::Short => (&ctx.accounts.short_margin_vault, &mut short_left),
+        };
+        let bounty = (bounty_target as u64).min(*remaining);
+        if bounty > 0 {
+            transfer_signed(
+                &ctx.accounts.token_program,
+                bounty_vault,
+                &ctx.accounts.liquidator_quote_ata,
+                &ctx.accounts.quote_mint,
+                &ctx.accounts.vault_auth,
+                &ds.deal,
+                ds.vault_bump,
+                bounty,
+            )?;
+            *remaining -= bounty;
+        }
+
+        // 3) Return whatever is left to each party.
+        if long_left > 0 {
+            transfer_signed(
+                &ctx.accounts.token_program,
+                &ctx.accounts.long_margin_vault,
+                &ctx.accounts.long_receive_quote_ata,
+                &ctx.accounts.quote_mint,
+                &ctx.accounts.vault_auth,
+                &ds.deal,
+                ds.vault_bump,
+                long_left,
+            )?;
+        }
+        if short_left > 0 {
+            transfer_signed(
+                &ctx.accounts.token_program,
+                &ctx.accounts.short_margin_vault,
+                &ctx.accounts.short_receive_quote_ata,
+                &ctx.accounts.quote_mint,
+                &ctx.accounts.vault_auth,
+                &ds.deal,
+                ds.vault_bump,
+                short_left,
+            )?;
+        }
+
+        let deal_mut = &mut ctx.accounts.deal;
+        deal_mut.long_margin = 0;
+        deal_mut.short_margin = 0;
+        deal_mut.is_settled = true;
+
+        emit!(DealLiquidated {
+            deal: ds.deal,
+            liquidator: ctx.accounts.liquidator.key(),
+            side: if matches!(liquidated, crate::Side::Long) { 0 } else { 1 },
+            mark_price,
             pnl_long,
+            pnl_paid,
+            bounty,
         });
         Ok(())
     }
 
     /// Physical settlement (full).
-    pub fn settle_physical(ctx: Context<SettlePhysical>) -> Result<()> {
+    pub fn settle_physical<'info>(ctx: Context<'_, '_, '_, 'info, SettlePhysical<'info>>) -> Result<()> {
         let deal = &mut ctx.accounts.deal;
         require!(!deal.is_frozen, ErrorCode::DealFrozen);
         require!(!deal.is_settled, ErrorCode::AlreadySettled);
@@ -585,43 +2260,52 @@ pub mod tokenized_warehouse_receipts {
 
         let ds = DealSnapshot::from(deal);
 
-        token::transfer(
-            CpiContext::new(
-                ctx.accounts.token_program.to_account_info(),
-                Transfer {
-                    from: ctx.accounts.short_receipt_ata.to_account_info(),
-                    to: ctx.accounts.long_receipt_ata.to_account_info(),
-                    authority: ctx.accounts.short.to_account_info(),
-                },
-            ),
+        transfer_receipts(
+            &ctx.accounts.receipt_token_program,
+            ctx.accounts.short_receipt_ata.to_account_info(),
+            &ctx.accounts.receipt_mint,
+            ctx.accounts.long_receipt_ata.to_account_info(),
+            ctx.accounts.short.to_account_info(),
+            &ctx.accounts.market,
+            &ctx.accounts.receipt_mint_auth,
+            ctx.remaining_accounts,
+            &[],
             ds.qty_receipt_amount,
         )?;
 
-        let pay_amount = notional_at_strike(&ds);
+        require!(ds.qty_receipt_amount <= ctx.accounts.lot.outstanding()?, ErrorCode::ExceedsOutstandingReceipts);
+        let grade = ctx.accounts.lot.grade;
+        let grade_diff_bps = ctx.accounts.market.grade_differential_bps(grade)?;
+        let pay_amount = apply_grade_differential(notional_at_strike(&ds), grade_diff_bps)?;
+        let storage_fee = charge_delivery_storage(ctx.accounts, &ds, ds.qty_receipt_amount, pay_amount)?;
         transfer_signed(
             &ctx.accounts.token_program,
             &ctx.accounts.long_margin_vault,
             &ctx.accounts.short_receive_quote_ata,
+            &ctx.accounts.quote_mint,
             &ctx.accounts.vault_auth,
             &ds.deal,
             ds.vault_bump,
-            pay_amount,
+            pay_amount - storage_fee,
         )?;
 
-        payout_leftovers_after_settlement(&ctx.accounts.token_program, &ctx.accounts.long_margin_vault, &ctx.accounts.long_receive_quote_ata, &ctx.accounts.vault_auth, &ds)?;
-        payout_leftovers_after_settlement(&ctx.accounts.token_program, &ctx.accounts.short_margin_vault, &ctx.accounts.short_receive_quote_ata, &ctx.accounts.vault_auth, &ds)?;
+        payout_leftovers_after_settlement(&ctx.accounts.token_program, &ctx.accounts.quote_mint, &ctx.accounts.long_margin_vault, &ctx.accounts.long_receive_quote_ata, &ctx.accounts.vault_auth, &ds)?;
+        payout_leftovers_after_settlement(&ctx.accounts.token_program, &ctx.accounts.quote_mint, &ctx.accounts.short_margin_vault, &ctx.accounts.short_receive_quote_ata, &ctx.accounts.vault_auth, &ds)?;
 
-        deal.is_settled = true;
+        ctx.accounts.deal.is_settled = true;
         emit!(PhysicalSettled {
             deal: ds.deal,
             qty_receipt_amount: ds.qty_receipt_amount,
             pay_amount,
+            storage_fee,
+            grade,
+            grade_diff_bps,
         });
         Ok(())
     }
 
     /// Partial physical settlement by `amount_receipt` (<= remaining).
-    pub fn settle_partial_physical(ctx: Context<SettlePhysical>, amount_receipt: u64) -> Result<()> {
+    pub fn settle_partial_physical<'info>(ctx: Context<'_, '_, '_, 'info, SettlePhysical<'info>>, amount_receipt: u64) -> Result<()> {
         let deal = &mut ctx.accounts.deal;
         require!(!deal.is_frozen, ErrorCode::DealFrozen);
         require!(!deal.is_settled, ErrorCode::AlreadySettled);
@@ -633,29 +2317,36 @@ pub mod tokenized_warehouse_receipts {
         let mut ds = DealSnapshot::from(deal);
         ds.qty_receipt_amount = amount_receipt;
 
-        token::transfer(
-            CpiContext::new(
-                ctx.accounts.token_program.to_account_info(),
-                Transfer {
-                    from: ctx.accounts.short_receipt_ata.to_account_info(),
-                    to: ctx.accounts.long_receipt_ata.to_account_info(),
-                    authority: ctx.accounts.short.to_account_info(),
-                },
-            ),
+        transfer_receipts(
+            &ctx.accounts.receipt_token_program,
+            ctx.accounts.short_receipt_ata.to_account_info(),
+            &ctx.accounts.receipt_mint,
+            ctx.accounts.long_receipt_ata.to_account_info(),
+            ctx.accounts.short.to_account_info(),
+            &ctx.accounts.market,
+            &ctx.accounts.receipt_mint_auth,
+            ctx.remaining_accounts,
+            &[],
             amount_receipt,
         )?;
 
-        let pay_amount = notional_at_strike(&ds);
+        require!(amount_receipt <= ctx.accounts.lot.outstanding()?, ErrorCode::ExceedsOutstandingReceipts);
+        let grade = ctx.accounts.lot.grade;
+        let grade_diff_bps = ctx.accounts.market.grade_differential_bps(grade)?;
+        let pay_amount = apply_grade_differential(notional_at_strike(&ds), grade_diff_bps)?;
+        let storage_fee = charge_delivery_storage(ctx.accounts, &ds, amount_receipt, pay_amount)?;
         transfer_signed(
             &ctx.accounts.token_program,
             &ctx.accounts.long_margin_vault,
             &ctx.accounts.short_receive_quote_ata,
+            &ctx.accounts.quote_mint,
             &ctx.accounts.vault_auth,
             &ds.deal,
             ds.vault_bump,
-            pay_amount,
+            pay_amount - storage_fee,
         )?;
 
+        let deal = &mut ctx.accounts.deal;
         deal.qty_receipt_amount = deal.qty_receipt_amount.checked_sub(amount_receipt).ok_or(ErrorCode::MathOverflow)?;
         let is_now_settled = deal.qty_receipt_amount == 0;
         deal.is_settled = is_now_settled;
@@ -664,19 +2355,15 @@ pub mod tokenized_warehouse_receipts {
             deal: ds.deal,
             amount_receipt,
             pay_amount,
+            storage_fee,
+            grade,
+            grade_diff_bps,
             fully_settled: is_now_settled,
         });
         Ok(())
     }
 
     // --- Yield (POC) ---
-    pub fn yield_set_operator(ctx: Context<AdminMarketWrite>, operator: Pubkey) -> Result<()> {
-        only_admin(&ctx.accounts.market, &ctx.accounts.signer)?;
-        ctx.accounts.market.strategy_operator = operator;
-        emit!(YieldOperatorSet { market: ctx.accounts.market.key(), operator });
-        Ok(())
-    }
-
     pub fn yield_park_from_deal(ctx: Context<YieldPark>, side: crate::Side, amount: u64) -> Result<()> {
         only_strategy_operator(&ctx.accounts.market, &ctx.accounts.operator)?;
         let deal = &ctx.accounts.deal;
@@ -688,6 +2375,7 @@ pub mod tokenized_warehouse_receipts {
             &ctx.accounts.token_program,
             src,
             &ctx.accounts.strategy_vault_ata,
+            &ctx.accounts.quote_mint,
             &ctx.accounts.vault_auth,
             &deal.key(),
             deal.vault_bump,
@@ -708,6 +2396,7 @@ pub mod tokenized_warehouse_receipts {
             &ctx.accounts.token_program,
             &ctx.accounts.strategy_vault_ata,
             dst,
+            &ctx.accounts.quote_mint,
             &ctx.accounts.vault_auth,
             &deal.key(),
             deal.vault_bump,
@@ -723,10 +2412,11 @@ pub mod tokenized_warehouse_receipts {
 // ==========
 #[derive(Accounts)]
 pub struct InitMarket<'info> {
+    /// Initial authority; also recorded as `market.creator`, which the PDA seeds are pinned to.
     #[account(mut)]
     pub authority: Signer<'info>,
-    pub quote_mint: Box<Account<'info, Mint>>,    // e.g., USDC
-    pub receipt_mint: Box<Account<'info, Mint>>,  // tokenized receipt SPL mint
+    pub quote_mint: Box<InterfaceAccount<'info, Mint>>,    // e.g., USDC
+    pub receipt_mint: Box<InterfaceAccount<'info, Mint>>,  // tokenized receipt SPL mint
     #[account(
         init,
         payer = authority,
@@ -735,6 +2425,22 @@ pub struct InitMarket<'info> {
         bump,
     )]
     pub market: Account<'info, Market>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + PriceHistory::SIZE,
+        seeds = [b"price_history", market.key().as_ref()],
+        bump,
+    )]
+    pub price_history: Box<Account<'info, PriceHistory>>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + OracleSet::SIZE,
+        seeds = [b"oracle_set", market.key().as_ref()],
+        bump,
+    )]
+    pub oracle_set: Box<Account<'info, OracleSet>>,
     pub system_program: Program<'info, System>,
 }
 
@@ -747,28 +2453,203 @@ pub struct AdminMarketWrite<'info> {
 }
 
 #[derive(Accounts)]
-pub struct PostPrice<'info> {
+pub struct ArmDeviationOverride<'info> {
+    pub signer: Signer<'info>,
     #[account(mut)]
     pub market: Account<'info, Market>,
-    /// CHECK: authority check done in handler
-    pub poster: Signer<'info>,
+    // Required once the market has a council
+    pub council: Option<Box<Account<'info, GovernanceCouncil>>>,
+    #[account(mut)]
+    pub council_proposal: Option<Account<'info, CouncilProposal>>,
 }
 
 #[derive(Accounts)]
-pub struct InitWarehouse<'info> {
-    #[account(mut)]
-    pub warehouse_authority: Signer<'info>,
+pub struct AcceptKeyRotation<'info> {
+    pub new_key: Signer<'info>,
     #[account(mut)]
     pub market: Account<'info, Market>,
-    /// CHECK: Market authority (for equality check)
-    pub authority: SystemAccount<'info>,
-    pub quote_mint: Box<Account<'info, Mint>>,
-    #[account(mut)]
-    pub receipt_mint: Box<Account<'info, Mint>>,
-    /// PDA that will become mint authority
+    #[account(
+        mut,
+        seeds = [b"oracle_set", market.key().as_ref()],
+        bump = oracle_set.bump
+    )]
+    pub oracle_set: Box<Account<'info, OracleSet>>,
+}
+
+#[derive(Accounts)]
+pub struct ProposeAction<'info> {
+    #[account(mut)]
+    pub proposer: Signer<'info>,
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+    #[account(
+        init,
+        payer = proposer,
+        space = 8 + PendingAction::SIZE,
+        seeds = [b"pending_action", market.key().as_ref(), &market.next_action_id.to_le_bytes()],
+        bump
+    )]
+    pub pending_action: Account<'info, PendingAction>,
+    // Required once the market has a council
+    pub council: Option<Box<Account<'info, GovernanceCouncil>>>,
+    #[account(mut)]
+    pub council_proposal: Option<Account<'info, CouncilProposal>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitCouncil<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+    #[account(
+        init,
+        payer = signer,
+        space = 8 + GovernanceCouncil::SIZE,
+        seeds = [b"council", market.key().as_ref()],
+        bump
+    )]
+    pub council: Box<Account<'info, GovernanceCouncil>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CouncilPropose<'info> {
+    #[account(mut)]
+    pub member: Signer<'info>,
+    #[account(mut)]
+    pub council: Box<Account<'info, GovernanceCouncil>>,
+    #[account(
+        init,
+        payer = member,
+        space = 8 + CouncilProposal::SIZE,
+        seeds = [b"council_proposal", council.key().as_ref(), &council.next_proposal_id.to_le_bytes()],
+        bump
+    )]
+    pub proposal: Account<'info, CouncilProposal>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CouncilApprove<'info> {
+    pub member: Signer<'info>,
+    pub council: Box<Account<'info, GovernanceCouncil>>,
+    #[account(mut, has_one = council)]
+    pub proposal: Account<'info, CouncilProposal>,
+}
+
+#[derive(Accounts)]
+pub struct CouncilExecute<'info> {
+    pub member: Signer<'info>,
+    #[account(mut)]
+    pub council: Box<Account<'info, GovernanceCouncil>>,
+    #[account(mut, has_one = council)]
+    pub proposal: Account<'info, CouncilProposal>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteAction<'info> {
+    pub signer: Signer<'info>,
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+    #[account(
+        mut,
+        seeds = [b"oracle_set", market.key().as_ref()],
+        bump = oracle_set.bump
+    )]
+    pub oracle_set: Box<Account<'info, OracleSet>>,
+    #[account(mut, has_one = market)]
+    pub pending_action: Account<'info, PendingAction>,
+    // Required once the market has a council
+    pub council: Option<Box<Account<'info, GovernanceCouncil>>>,
+    #[account(mut)]
+    pub council_proposal: Option<Account<'info, CouncilProposal>>,
+}
+
+#[derive(Accounts)]
+pub struct CancelAction<'info> {
+    pub signer: Signer<'info>,
+    pub market: Account<'info, Market>,
+    #[account(mut, has_one = market)]
+    pub pending_action: Account<'info, PendingAction>,
+    // Required once the market has a council
+    pub council: Option<Box<Account<'info, GovernanceCouncil>>>,
+    #[account(mut)]
+    pub council_proposal: Option<Account<'info, CouncilProposal>>,
+}
+
+#[derive(Accounts)]
+#[instruction(price: u64, exponent: i32, settle_ts: i64)]
+pub struct PostPrice<'info> {
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+    #[account(
+        mut,
+        seeds = [b"price_history", market.key().as_ref()],
+        bump = price_history.bump
+    )]
+    pub price_history: Box<Account<'info, PriceHistory>>,
+    #[account(
+        mut,
+        seeds = [b"oracle_set", market.key().as_ref()],
+        bump = oracle_set.bump
+    )]
+    pub oracle_set: Box<Account<'info, OracleSet>>,
+    #[account(
+        init_if_needed,
+        payer = poster,
+        space = 8 + TwapAccumulator::SIZE,
+        seeds = [b"twap", market.key().as_ref(), &settle_ts.to_le_bytes()],
+        bump
+    )]
+    pub twap: Box<Account<'info, TwapAccumulator>>,
+    /// CHECK: membership check done in handler
+    #[account(mut)]
+    pub poster: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(settle_ts: i64)]
+pub struct FinalizeSettlementPrice<'info> {
+    #[account(mut)]
+    pub oracle: Signer<'info>,
+    pub market: Account<'info, Market>,
+    #[account(
+        seeds = [b"twap", market.key().as_ref(), &settle_ts.to_le_bytes()],
+        bump = twap.bump
+    )]
+    pub twap: Box<Account<'info, TwapAccumulator>>,
+    #[account(
+        seeds = [b"oracle_set", market.key().as_ref()],
+        bump = oracle_set.bump
+    )]
+    pub oracle_set: Box<Account<'info, OracleSet>>,
+    #[account(
+        init_if_needed,
+        payer = oracle,
+        space = 8 + SettlementPrice::SIZE,
+        seeds = [b"settlement_price", market.key().as_ref(), &settle_ts.to_le_bytes()],
+        bump
+    )]
+    pub settlement_price: Account<'info, SettlementPrice>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitWarehouse<'info> {
+    #[account(mut)]
+    pub warehouse_authority: Signer<'info>,
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+    pub quote_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mut)]
+    pub receipt_mint: Box<InterfaceAccount<'info, Mint>>,
+    /// Market-level PDA that holds the receipt mint's authority for every warehouse
     /// CHECK: PDA only used as signer for CPI; seeds enforced
     #[account(
-        seeds = [b"receipt_auth", warehouse.key().as_ref()],
+        seeds = [b"receipt_auth", market.key().as_ref()],
         bump
     )]
     pub receipt_mint_auth: UncheckedAccount<'info>,
@@ -780,206 +2661,940 @@ pub struct InitWarehouse<'info> {
         bump
     )]
     pub warehouse: Account<'info, Warehouse>,
-    pub token_program: Program<'info, Token>,
+    /// CHECK: PDA authority for the storage fee vault
+    #[account(
+        seeds = [b"storage_auth", warehouse.key().as_ref()],
+        bump
+    )]
+    pub storage_auth: UncheckedAccount<'info>,
+    #[account(
+        init,
+        payer = warehouse_authority,
+        associated_token::mint = quote_mint,
+        associated_token::authority = storage_auth,
+    )]
+    pub storage_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    /// CHECK: PDA authority for the performance bond vault
+    #[account(
+        seeds = [b"bond_auth", warehouse.key().as_ref()],
+        bump
+    )]
+    pub bond_auth: UncheckedAccount<'info>,
+    #[account(
+        init,
+        payer = warehouse_authority,
+        associated_token::mint = quote_mint,
+        associated_token::authority = bond_auth,
+    )]
+    pub bond_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub token_program: Interface<'info, TokenInterface>,         // quote mint's program
+    pub receipt_token_program: Interface<'info, TokenInterface>, // receipt mint's program (SPL or Token-2022)
+    pub associated_token_program: Program<'info, AssociatedToken>,
     pub system_program: Program<'info, System>,
 }
 
+#[derive(Accounts)]
+pub struct PostBond<'info> {
+    pub warehouse_authority: Signer<'info>,
+    #[account(has_one = quote_mint)]
+    pub market: Account<'info, Market>,
+    #[account(
+        mut,
+        has_one = market,
+        constraint = warehouse.authority == warehouse_authority.key() @ ErrorCode::Unauthorized
+    )]
+    pub warehouse: Account<'info, Warehouse>,
+    pub quote_mint: Box<InterfaceAccount<'info, Mint>>,
+    /// CHECK: PDA authority for the performance bond vault
+    #[account(
+        seeds = [b"bond_auth", warehouse.key().as_ref()],
+        bump = warehouse.bond_auth_bump
+    )]
+    pub bond_auth: UncheckedAccount<'info>,
+    #[account(mut, associated_token::mint = quote_mint, associated_token::authority = bond_auth)]
+    pub bond_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut, constraint = from_quote_ata.mint == quote_mint.key())]
+    pub from_quote_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct RequestBondWithdrawal<'info> {
+    pub warehouse_authority: Signer<'info>,
+    pub market: Account<'info, Market>,
+    #[account(
+        mut,
+        has_one = market,
+        constraint = warehouse.authority == warehouse_authority.key() @ ErrorCode::Unauthorized
+    )]
+    pub warehouse: Account<'info, Warehouse>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawBond<'info> {
+    pub warehouse_authority: Signer<'info>,
+    #[account(has_one = quote_mint)]
+    pub market: Account<'info, Market>,
+    #[account(
+        mut,
+        has_one = market,
+        constraint = warehouse.authority == warehouse_authority.key() @ ErrorCode::Unauthorized
+    )]
+    pub warehouse: Account<'info, Warehouse>,
+    pub quote_mint: Box<InterfaceAccount<'info, Mint>>,
+    /// CHECK: PDA authority for the performance bond vault
+    #[account(
+        seeds = [b"bond_auth", warehouse.key().as_ref()],
+        bump = warehouse.bond_auth_bump
+    )]
+    pub bond_auth: UncheckedAccount<'info>,
+    #[account(mut, associated_token::mint = quote_mint, associated_token::authority = bond_auth)]
+    pub bond_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut, constraint = to_quote_ata.mint == quote_mint.key())]
+    pub to_quote_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct SlashWarehouse<'info> {
+    pub signer: Signer<'info>,
+    #[account(has_one = quote_mint)]
+    pub market: Box<Account<'info, Market>>,
+    #[account(mut, has_one = market)]
+    pub warehouse: Box<Account<'info, Warehouse>>,
+    pub quote_mint: Box<InterfaceAccount<'info, Mint>>,
+    /// CHECK: PDA authority for the performance bond vault
+    #[account(
+        seeds = [b"bond_auth", warehouse.key().as_ref()],
+        bump = warehouse.bond_auth_bump
+    )]
+    pub bond_auth: UncheckedAccount<'info>,
+    #[account(mut, associated_token::mint = quote_mint, associated_token::authority = bond_auth)]
+    pub bond_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut, constraint = recipient_quote_ata.mint == quote_mint.key())]
+    pub recipient_quote_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+    // The timed-out redemption being compensated; pays its holder and closes it
+    #[account(mut)]
+    pub redemption: Box<Account<'info, Redemption>>,
+    // Required once the market has a council
+    pub council: Option<Box<Account<'info, GovernanceCouncil>>>,
+    #[account(mut)]
+    pub council_proposal: Option<Account<'info, CouncilProposal>>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct SetStorageRate<'info> {
+    pub warehouse_authority: Signer<'info>,
+    #[account(mut, constraint = warehouse.authority == warehouse_authority.key() @ ErrorCode::Unauthorized)]
+    pub warehouse: Account<'info, Warehouse>,
+}
+
+#[derive(Accounts)]
+pub struct CollectStorageFees<'info> {
+    pub warehouse_authority: Signer<'info>,
+    #[account(has_one = quote_mint)]
+    pub market: Account<'info, Market>,
+    #[account(
+        mut,
+        has_one = market,
+        constraint = warehouse.authority == warehouse_authority.key() @ ErrorCode::Unauthorized
+    )]
+    pub warehouse: Account<'info, Warehouse>,
+    pub quote_mint: Box<InterfaceAccount<'info, Mint>>,
+    /// CHECK: PDA authority for the storage fee vault
+    #[account(
+        seeds = [b"storage_auth", warehouse.key().as_ref()],
+        bump = warehouse.storage_auth_bump
+    )]
+    pub storage_auth: UncheckedAccount<'info>,
+    #[account(mut, associated_token::mint = quote_mint, associated_token::authority = storage_auth)]
+    pub storage_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut, constraint = to_quote_ata.mint == quote_mint.key())]
+    pub to_quote_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct AdminWarehouseWrite<'info> {
+    pub signer: Signer<'info>,
+    pub market: Account<'info, Market>,
+    #[account(mut, has_one = market)]
+    pub warehouse: Account<'info, Warehouse>,
+    // Required once the market has a council
+    pub council: Option<Box<Account<'info, GovernanceCouncil>>>,
+    #[account(mut)]
+    pub council_proposal: Option<Account<'info, CouncilProposal>>,
+}
+
 #[derive(Accounts)]
 pub struct MintReceipt<'info> {
     #[account(mut)]
     pub warehouse: Account<'info, Warehouse>,
     pub market: Account<'info, Market>,
     #[account(mut)]
-    pub receipt_mint: Box<Account<'info, Mint>>,
-    /// CHECK: PDA signer for mint authority
+    pub receipt_mint: Box<InterfaceAccount<'info, Mint>>,
+    /// CHECK: PDA signer for mint authority (shared by the market's warehouses)
     #[account(
-        seeds = [b"receipt_auth", warehouse.key().as_ref()],
-        bump = warehouse.bump
+        seeds = [b"receipt_auth", market.key().as_ref()],
+        bump = market.receipt_auth_bump
     )]
     pub receipt_mint_auth: UncheckedAccount<'info>,
     #[account(mut)]
-    pub to_receipt_ata: Box<Account<'info, TokenAccount>>,
+    pub to_receipt_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut)]
     pub warehouse_authority: Signer<'info>,
-    pub token_program: Program<'info, Token>,
+    #[account(
+        init,
+        payer = warehouse_authority,
+        space = 8 + ReceiptLot::SIZE,
+        seeds = [b"lot", warehouse.key().as_ref(), &warehouse.next_lot_id.to_le_bytes()],
+        bump
+    )]
+    pub lot: Account<'info, ReceiptLot>,
+    // Required when the market's allowlist is enabled
+    pub recipient_participant: Option<Account<'info, Participant>>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct BurnReceipt<'info> {
-    pub owner: Signer<'info>,
+pub struct GetLot<'info> {
+    pub lot: Account<'info, ReceiptLot>,
+}
+
+#[derive(Accounts)]
+pub struct PostReserveSnapshot<'info> {
     #[account(mut)]
-    pub receipt_mint: Box<Account<'info, Mint>>,
+    pub poster: Signer<'info>,
+    pub market: Account<'info, Market>,
+    #[account(has_one = market)]
+    pub warehouse: Account<'info, Warehouse>,
     #[account(
-        mut,
-        constraint = from_receipt_ata.mint == receipt_mint.key(),
-        constraint = from_receipt_ata.owner == owner.key()
+        init_if_needed,
+        payer = poster,
+        space = 8 + ReserveSnapshot::SIZE,
+        seeds = [b"reserve_snapshot", warehouse.key().as_ref()],
+        bump
     )]
-    pub from_receipt_ata: Box<Account<'info, TokenAccount>>,
-    pub token_program: Program<'info, Token>,
+    pub reserve_snapshot: Account<'info, ReserveSnapshot>,
+    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct OpenDeal<'info> {
-    #[account(mut)]
+pub struct VerifyLotInclusion<'info> {
+    #[account(
+        seeds = [b"reserve_snapshot", lot.warehouse.as_ref()],
+        bump = reserve_snapshot.bump
+    )]
+    pub reserve_snapshot: Account<'info, ReserveSnapshot>,
+    pub lot: Account<'info, ReceiptLot>,
+}
+
+#[derive(Accounts)]
+pub struct AttestInventory<'info> {
+    pub auditor: Signer<'info>,
     pub market: Account<'info, Market>,
+    #[account(mut, has_one = market)]
+    pub warehouse: Account<'info, Warehouse>,
+}
 
-    /// Parties
+#[derive(Accounts)]
+pub struct RequestRedemption<'info> {
     #[account(mut)]
-    pub long: Signer<'info>,
+    pub holder: Signer<'info>,
+    #[account(has_one = quote_mint)]
+    pub market: Box<Account<'info, Market>>,
+    #[account(mut, has_one = market, has_one = receipt_mint)]
+    pub warehouse: Box<Account<'info, Warehouse>>,
+    #[account(mut, has_one = warehouse)]
+    pub lot: Box<Account<'info, ReceiptLot>>,
     #[account(mut)]
-    pub short: Signer<'info>,
+    pub receipt_mint: Box<InterfaceAccount<'info, Mint>>,
+    /// CHECK: market-level receipt mint authority; re-mints receipts whose mint is hooked to this program
+    #[account(seeds = [b"receipt_auth", market.key().as_ref()], bump = market.receipt_auth_bump)]
+    pub receipt_mint_auth: UncheckedAccount<'info>,
+    pub quote_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        constraint = holder_quote_ata.mint == quote_mint.key(),
+        constraint = holder_quote_ata.owner == holder.key()
+    )]
+    pub holder_quote_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+    /// CHECK: PDA authority for the storage fee vault
+    #[account(
+        seeds = [b"storage_auth", warehouse.key().as_ref()],
+        bump = warehouse.storage_auth_bump
+    )]
+    pub storage_auth: UncheckedAccount<'info>,
+    #[account(mut, associated_token::mint = quote_mint, associated_token::authority = storage_auth)]
+    pub storage_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = holder_receipt_ata.mint == receipt_mint.key(),
+        constraint = holder_receipt_ata.owner == holder.key()
+    )]
+    pub holder_receipt_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        init,
+        payer = holder,
+        space = 8 + Redemption::SIZE,
+        seeds = [b"redemption", warehouse.key().as_ref(), &warehouse.next_redemption_id.to_le_bytes()],
+        bump
+    )]
+    pub redemption: Box<Account<'info, Redemption>>,
+    /// CHECK: PDA authority for the escrow vault
+    #[account(
+        seeds = [b"redemption_auth", redemption.key().as_ref()],
+        bump
+    )]
+    pub redemption_auth: UncheckedAccount<'info>,
+    #[account(
+        init,
+        payer = holder,
+        associated_token::mint = receipt_mint,
+        associated_token::authority = redemption_auth,
+        associated_token::token_program = receipt_token_program,
+    )]
+    pub redemption_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    // Required iff the market's allowlist is enabled
+    #[account(
+        init,
+        payer = holder,
+        space = 8 + Participant::SIZE,
+        seeds = [b"participant", market.key().as_ref(), redemption_auth.key().as_ref()],
+        bump
+    )]
+    pub escrow_participant: Option<Box<Account<'info, Participant>>>,
+    pub token_program: Interface<'info, TokenInterface>,         // quote mint's program
+    pub receipt_token_program: Interface<'info, TokenInterface>, // receipt mint's program (SPL or Token-2022)
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ConfirmRelease<'info> {
+    pub warehouse_authority: Signer<'info>,
+    #[account(mut, has_one = receipt_mint)]
+    pub warehouse: Box<Account<'info, Warehouse>>,
+    #[account(mut, has_one = warehouse)]
+    pub lot: Box<Account<'info, ReceiptLot>>,
+    #[account(mut)]
+    pub receipt_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mut, has_one = warehouse, has_one = lot)]
+    pub redemption: Box<Account<'info, Redemption>>,
+    /// CHECK: PDA authority for the escrow vault
+    #[account(
+        seeds = [b"redemption_auth", redemption.key().as_ref()],
+        bump = redemption.auth_bump
+    )]
+    pub redemption_auth: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        associated_token::mint = receipt_mint,
+        associated_token::authority = redemption_auth,
+        associated_token::token_program = token_program,
+    )]
+    pub redemption_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    /// CHECK: redemption holder; receives the escrow participant's rent
+    #[account(mut, address = redemption.holder)]
+    pub holder: UncheckedAccount<'info>,
+    // Required iff the redemption registered one (`has_escrow_participant`)
+    #[account(
+        mut,
+        close = holder,
+        seeds = [b"participant", warehouse.market.as_ref(), redemption_auth.key().as_ref()],
+        bump = escrow_participant.bump
+    )]
+    pub escrow_participant: Option<Box<Account<'info, Participant>>>,
+    pub token_program: Interface<'info, TokenInterface>, // receipt mint's program
+}
+
+#[derive(Accounts)]
+pub struct CloseRedemption<'info> {
+    pub signer: Signer<'info>,
+    #[account(has_one = quote_mint)]
+    pub market: Box<Account<'info, Market>>,
+    #[account(has_one = market, has_one = receipt_mint)]
+    pub warehouse: Box<Account<'info, Warehouse>>,
+    #[account(mut, has_one = warehouse)]
+    pub lot: Box<Account<'info, ReceiptLot>>,
+    #[account(mut)]
+    pub receipt_mint: Box<InterfaceAccount<'info, Mint>>,
+    /// CHECK: market-level receipt mint authority; re-mints receipts whose mint is hooked to this program
+    #[account(seeds = [b"receipt_auth", market.key().as_ref()], bump = market.receipt_auth_bump)]
+    pub receipt_mint_auth: UncheckedAccount<'info>,
+    pub quote_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mut, has_one = warehouse, has_one = lot)]
+    pub redemption: Box<Account<'info, Redemption>>,
+    /// CHECK: PDA authority for the escrow vault
+    #[account(
+        seeds = [b"redemption_auth", redemption.key().as_ref()],
+        bump = redemption.auth_bump
+    )]
+    pub redemption_auth: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        associated_token::mint = receipt_mint,
+        associated_token::authority = redemption_auth,
+        associated_token::token_program = receipt_token_program,
+    )]
+    pub redemption_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = holder_receipt_ata.mint == receipt_mint.key(),
+        constraint = holder_receipt_ata.owner == redemption.holder
+    )]
+    pub holder_receipt_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = holder_quote_ata.mint == quote_mint.key(),
+        constraint = holder_quote_ata.owner == redemption.holder
+    )]
+    pub holder_quote_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+    /// CHECK: PDA authority for the storage fee vault
+    #[account(
+        seeds = [b"storage_auth", warehouse.key().as_ref()],
+        bump = warehouse.storage_auth_bump
+    )]
+    pub storage_auth: UncheckedAccount<'info>,
+    #[account(mut, associated_token::mint = quote_mint, associated_token::authority = storage_auth)]
+    pub storage_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    /// CHECK: redemption holder; receives the escrow participant's rent
+    #[account(mut, address = redemption.holder)]
+    pub holder: UncheckedAccount<'info>,
+    // Required iff the redemption registered one (`has_escrow_participant`)
+    #[account(
+        mut,
+        close = holder,
+        seeds = [b"participant", warehouse.market.as_ref(), redemption_auth.key().as_ref()],
+        bump = escrow_participant.bump
+    )]
+    pub escrow_participant: Option<Box<Account<'info, Participant>>>,
+    pub token_program: Interface<'info, TokenInterface>,         // quote mint's program
+    pub receipt_token_program: Interface<'info, TokenInterface>, // receipt mint's program (SPL or Token-2022)
+}
+
+#[derive(Accounts)]
+#[instruction(owner: Pubkey)]
+pub struct SetParticipant<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+    pub market: Account<'info, Market>,
+    #[account(
+        init_if_needed,
+        payer = signer,
+        space = 8 + Participant::SIZE,
+        seeds = [b"participant", market.key().as_ref(), owner.as_ref()],
+        bump
+    )]
+    pub participant: Account<'info, Participant>,
+    // Required once the market has a council
+    pub council: Option<Box<Account<'info, GovernanceCouncil>>>,
+    #[account(mut)]
+    pub council_proposal: Option<Account<'info, CouncilProposal>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitReceiptHook<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+    #[account(has_one = receipt_mint)]
+    pub market: Account<'info, Market>,
+    pub receipt_mint: Box<InterfaceAccount<'info, Mint>>,
+    /// CHECK: TLV extra-account-metas list read by Token-2022; written in the handler
+    #[account(
+        init,
+        payer = signer,
+        space = ExtraAccountMetaList::size_of(3)?,
+        seeds = [b"extra-account-metas", receipt_mint.key().as_ref()],
+        bump
+    )]
+    pub extra_account_meta_list: UncheckedAccount<'info>,
+    // Required once the market has a council
+    pub council: Option<Box<Account<'info, GovernanceCouncil>>>,
+    #[account(mut)]
+    pub council_proposal: Option<Account<'info, CouncilProposal>>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts Token-2022 passes to the hook: the transfer's own four, the meta list, then the
+/// extras from `receipt_hook_metas`.
+#[derive(Accounts)]
+pub struct ExecuteTransferHook<'info> {
+    #[account(token::mint = mint)]
+    pub source_token: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(token::mint = mint)]
+    pub destination_token: Box<InterfaceAccount<'info, TokenAccount>>,
+    /// CHECK: source owner or delegate, already authorized by Token-2022
+    pub owner: UncheckedAccount<'info>,
+    /// CHECK: extra-account-metas list
+    #[account(seeds = [b"extra-account-metas", mint.key().as_ref()], bump)]
+    pub extra_account_meta_list: UncheckedAccount<'info>,
+    #[account(constraint = market.receipt_mint == mint.key() @ ErrorCode::ConstraintMismatch)]
+    pub market: Box<Account<'info, Market>>,
+    /// CHECK: source owner's Participant PDA; may not exist, checked in the handler
+    pub source_participant: UncheckedAccount<'info>,
+    /// CHECK: destination owner's Participant PDA; may not exist, checked in the handler
+    pub destination_participant: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(deal_id: u64)]
+pub struct OpenDeal<'info> {
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+
+    /// Parties
+    #[account(mut)]
+    pub long: Signer<'info>,
+    #[account(mut)]
+    pub short: Signer<'info>,
+
+    // Quote token mint and ATAs for initial margin funding
+    pub quote_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(
+        mut,
+        constraint = long_quote_ata.owner == long.key(),
+        constraint = long_quote_ata.mint == quote_mint.key()
+    )]
+    pub long_quote_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = short_quote_ata.owner == short.key(),
+        constraint = short_quote_ata.mint == quote_mint.key()
+    )]
+    pub short_quote_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    // Deal account; `deal_id` is part of the address so a pair can hold many deals
+    #[account(
+        init,
+        payer = long,
+        space = 8 + Deal::SIZE,
+        seeds = [b"deal", market.key().as_ref(), long.key().as_ref(), short.key().as_ref(), &deal_id.to_le_bytes()],
+        bump
+    )]
+    pub deal: Account<'info, Deal>,
+
+    /// Margin vaults (PDAs) that hold quote tokens for each side
+    #[account(
+        init,
+        payer = long,
+        associated_token::mint = quote_mint,
+        associated_token::authority = vault_auth,
+    )]
+    pub long_margin_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        init,
+        payer = short,
+        associated_token::mint = quote_mint,
+        associated_token::authority = vault_auth,
+    )]
+    pub short_margin_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// Vault authority PDA shared by both margin vaults
+    /// CHECK: Seeds used for signing CPIs
+    #[account(
+        seeds = [b"vault_auth", deal.key().as_ref()],
+        bump
+    )]
+    pub vault_auth: UncheckedAccount<'info>,
+
+    /// Fee vault owned by market account (ATA)
+    #[account(
+        init_if_needed,
+        payer = long,
+        associated_token::mint = quote_mint,
+        associated_token::authority = market,
+    )]
+    pub fee_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    // SPL Programs
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(offer_id: u64)]
+pub struct ProposeDeal<'info> {
+    pub market: Account<'info, Market>,
+    #[account(mut)]
+    pub maker: Signer<'info>,
+    pub quote_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        constraint = maker_quote_ata.owner == maker.key(),
+        constraint = maker_quote_ata.mint == quote_mint.key()
+    )]
+    pub maker_quote_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        init,
+        payer = maker,
+        space = 8 + DealOffer::SIZE,
+        seeds = [b"deal_offer", market.key().as_ref(), maker.key().as_ref(), &offer_id.to_le_bytes()],
+        bump
+    )]
+    pub offer: Account<'info, DealOffer>,
+    /// CHECK: PDA authority over the offer escrow
+    #[account(seeds = [b"offer_auth", offer.key().as_ref()], bump)]
+    pub offer_auth: UncheckedAccount<'info>,
+    #[account(
+        init,
+        payer = maker,
+        associated_token::mint = quote_mint,
+        associated_token::authority = offer_auth,
+    )]
+    pub offer_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptDeal<'info> {
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+    #[account(mut, has_one = market, has_one = quote_mint)]
+    pub offer: Account<'info, DealOffer>,
+    #[account(mut)]
+    pub taker: Signer<'info>,
+    /// CHECK: must equal the offer's long party (maker or taker); checked in the handler
+    pub long: UncheckedAccount<'info>,
+    /// CHECK: must equal the offer's short party (maker or taker); checked in the handler
+    pub short: UncheckedAccount<'info>,
+    pub quote_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        constraint = taker_quote_ata.owner == taker.key(),
+        constraint = taker_quote_ata.mint == quote_mint.key()
+    )]
+    pub taker_quote_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+    /// CHECK: PDA authority over the offer escrow
+    #[account(seeds = [b"offer_auth", offer.key().as_ref()], bump = offer.auth_bump)]
+    pub offer_auth: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        associated_token::mint = quote_mint,
+        associated_token::authority = offer_auth,
+    )]
+    pub offer_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        init,
+        payer = taker,
+        space = 8 + Deal::SIZE,
+        seeds = [b"deal", market.key().as_ref(), long.key().as_ref(), short.key().as_ref(), &offer.deal_id.to_le_bytes()],
+        bump
+    )]
+    pub deal: Account<'info, Deal>,
+    #[account(
+        init,
+        payer = taker,
+        associated_token::mint = quote_mint,
+        associated_token::authority = vault_auth,
+    )]
+    pub long_margin_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = quote_mint,
+        associated_token::authority = vault_auth,
+    )]
+    pub short_margin_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    /// CHECK: Seeds used for signing CPIs
+    #[account(seeds = [b"vault_auth", deal.key().as_ref()], bump)]
+    pub vault_auth: UncheckedAccount<'info>,
+    #[account(
+        init_if_needed,
+        payer = taker,
+        associated_token::mint = quote_mint,
+        associated_token::authority = market,
+    )]
+    pub fee_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelOffer<'info> {
+    #[account(mut)]
+    pub maker: Signer<'info>,
+    #[account(mut, has_one = maker, has_one = quote_mint)]
+    pub offer: Account<'info, DealOffer>,
+    pub quote_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        constraint = maker_quote_ata.owner == maker.key(),
+        constraint = maker_quote_ata.mint == quote_mint.key()
+    )]
+    pub maker_quote_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+    /// CHECK: PDA authority over the offer escrow
+    #[account(seeds = [b"offer_auth", offer.key().as_ref()], bump = offer.auth_bump)]
+    pub offer_auth: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        associated_token::mint = quote_mint,
+        associated_token::authority = offer_auth,
+    )]
+    pub offer_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct AdminDealWrite<'info> {
+    pub signer: Signer<'info>,
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+    #[account(mut, has_one = market)]
+    pub deal: Account<'info, Deal>,
+    // Required once the market has a council
+    pub council: Option<Box<Account<'info, GovernanceCouncil>>>,
+    #[account(mut)]
+    pub council_proposal: Option<Account<'info, CouncilProposal>>,
+}
+
+#[derive(Accounts)]
+pub struct DepositMargin<'info> {
+    #[account(mut)]
+    pub deal: Account<'info, Deal>,
+    pub quote_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    #[account(
+        mut,
+        constraint = payer_quote_ata.owner == payer.key(),
+        constraint = payer_quote_ata.mint == quote_mint.key()
+    )]
+    pub payer_quote_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// Vault authority and side-specific margin vaults
+    /// CHECK:
+    #[account(
+        seeds = [b"vault_auth", deal.key().as_ref()],
+        bump
+    )]
+    pub vault_auth: UncheckedAccount<'info>,
+
+    #[account(mut, associated_token::mint = quote_mint, associated_token::authority = vault_auth)]
+    pub long_margin_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut, associated_token::mint = quote_mint, associated_token::authority = vault_auth)]
+    pub short_margin_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+#[derive(Accounts)]
+pub struct NovateDeal<'info> {
+    pub market: Account<'info, Market>,
+    #[account(mut, has_one = market, has_one = quote_mint)]
+    pub deal: Account<'info, Deal>,
+    pub quote_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    /// Current holder of the side being transferred
+    pub outgoing: Signer<'info>,
+    #[account(
+        mut,
+        constraint = outgoing_quote_ata.owner == outgoing.key(),
+        constraint = outgoing_quote_ata.mint == quote_mint.key()
+    )]
+    pub outgoing_quote_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+    /// New holder; funds the side's margin
+    pub incoming: Signer<'info>,
+    #[account(
+        mut,
+        constraint = incoming_quote_ata.owner == incoming.key(),
+        constraint = incoming_quote_ata.mint == quote_mint.key()
+    )]
+    pub incoming_quote_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// CHECK: Seeds used for signing CPIs
+    #[account(seeds = [b"vault_auth", deal.key().as_ref()], bump = deal.vault_bump)]
+    pub vault_auth: UncheckedAccount<'info>,
+    #[account(mut, associated_token::mint = quote_mint, associated_token::authority = vault_auth)]
+    pub long_margin_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut, associated_token::mint = quote_mint, associated_token::authority = vault_auth)]
+    pub short_margin_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct SettleCash<'info> {
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+
+    #[account(mut)]
+    pub deal: Account<'info, Deal>,
+
+    /// Immutable price for this deal's expiry
+    #[account(
+        seeds = [b"settlement_price", market.key().as_ref(), &deal.settle_ts.to_le_bytes()],
+        bump = settlement_price.bump
+    )]
+    pub settlement_price: Account<'info, SettlementPrice>,
+
+    pub quote_mint: Box<InterfaceAccount<'info, Mint>>,
+    pub receipt_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    /// CHECK: vault auth PDA
+    #[account(
+        seeds = [b"vault_auth", deal.key().as_ref()],
+        bump
+    )]
+    pub vault_auth: UncheckedAccount<'info>,
+
+    #[account(mut, associated_token::mint = quote_mint, associated_token::authority = vault_auth)]
+    pub long_margin_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut, associated_token::mint = quote_mint, associated_token::authority = vault_auth)]
+    pub short_margin_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    // recipients
+    #[account(mut, constraint = long_receive_quote_ata.mint == quote_mint.key())]
+    pub long_receive_quote_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut, constraint = short_receive_quote_ata.mint == quote_mint.key())]
+    pub short_receive_quote_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// Fee destination: ATA owned by market account
+    #[account(mut, associated_token::mint = quote_mint, associated_token::authority = market)]
+    pub fee_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+#[derive(Accounts)]
+pub struct TerminateDeal<'info> {
+    pub market: Account<'info, Market>,
+
+    #[account(mut, has_one = market, has_one = quote_mint, has_one = long, has_one = short)]
+    pub deal: Account<'info, Deal>,
+
+    pub long: Signer<'info>,
+    pub short: Signer<'info>,
+
+    pub quote_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    /// CHECK: vault auth PDA
+    #[account(
+        seeds = [b"vault_auth", deal.key().as_ref()],
+        bump = deal.vault_bump
+    )]
+    pub vault_auth: UncheckedAccount<'info>,
 
-    // Quote token mint and ATAs for initial margin funding
-    pub quote_mint: Box<Account<'info, Mint>>,
+    #[account(mut, associated_token::mint = quote_mint, associated_token::authority = vault_auth)]
+    pub long_margin_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut, associated_token::mint = quote_mint, associated_token::authority = vault_auth)]
+    pub short_margin_vault: Box<InterfaceAccount<'info, TokenAccount>>,
 
     #[account(
         mut,
         constraint = long_quote_ata.owner == long.key(),
         constraint = long_quote_ata.mint == quote_mint.key()
     )]
-    pub long_quote_ata: Box<Account<'info, TokenAccount>>,
+    pub long_quote_ata: Box<InterfaceAccount<'info, TokenAccount>>,
     #[account(
         mut,
         constraint = short_quote_ata.owner == short.key(),
         constraint = short_quote_ata.mint == quote_mint.key()
     )]
-    pub short_quote_ata: Box<Account<'info, TokenAccount>>,
-
-    // Deal account
-    #[account(
-        init,
-        payer = long,
-        space = 8 + Deal::SIZE,
-        seeds = [b"deal", market.key().as_ref(), long.key().as_ref(), short.key().as_ref()],
-        bump
-    )]
-    pub deal: Account<'info, Deal>,
-
-    /// Margin vaults (PDAs) that hold quote tokens for each side
-    #[account(
-        init,
-        payer = long,
-        associated_token::mint = quote_mint,
-        associated_token::authority = vault_auth,
-    )]
-    pub long_margin_vault: Box<Account<'info, TokenAccount>>,
-
-    #[account(
-        init,
-        payer = short,
-        associated_token::mint = quote_mint,
-        associated_token::authority = vault_auth,
-    )]
-    pub short_margin_vault: Box<Account<'info, TokenAccount>>,
+    pub short_quote_ata: Box<InterfaceAccount<'info, TokenAccount>>,
 
-    /// Vault authority PDA shared by both margin vaults
-    /// CHECK: Seeds used for signing CPIs
-    #[account(
-        seeds = [b"vault_auth", deal.key().as_ref()],
-        bump
-    )]
-    pub vault_auth: UncheckedAccount<'info>,
-
-    /// Fee vault owned by market account (ATA)
-    #[account(
-        init_if_needed,
-        payer = long,
-        associated_token::mint = quote_mint,
-        associated_token::authority = market,
-    )]
-    pub fee_vault: Box<Account<'info, TokenAccount>>,
+    /// Fee destination: ATA owned by market account
+    #[account(mut, associated_token::mint = quote_mint, associated_token::authority = market)]
+    pub fee_vault: Box<InterfaceAccount<'info, TokenAccount>>,
 
-    // SPL Programs
-    pub token_program: Program<'info, Token>,
+    pub token_program: Interface<'info, TokenInterface>,
     pub associated_token_program: Program<'info, AssociatedToken>,
-    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct AdminDealWrite<'info> {
-    pub signer: Signer<'info>,
-    #[account(mut)]
+pub struct MarkToMarket<'info> {
     pub market: Account<'info, Market>,
-    #[account(mut)]
-    pub deal: Account<'info, Deal>,
-}
 
-#[derive(Accounts)]
-pub struct DepositMargin<'info> {
     #[account(mut)]
     pub deal: Account<'info, Deal>,
-    pub quote_mint: Box<Account<'info, Mint>>,
 
-    #[account(mut)]
-    pub payer: Signer<'info>,
-    #[account(
-        mut,
-        constraint = payer_quote_ata.owner == payer.key(),
-        constraint = payer_quote_ata.mint == quote_mint.key()
-    )]
-    pub payer_quote_ata: Box<Account<'info, TokenAccount>>,
+    #[account(address = deal.quote_mint)]
+    pub quote_mint: Box<InterfaceAccount<'info, Mint>>,
 
-    /// Vault authority and side-specific margin vaults
-    /// CHECK:
+    /// CHECK: vault auth PDA
     #[account(
         seeds = [b"vault_auth", deal.key().as_ref()],
-        bump
+        bump = deal.vault_bump
     )]
     pub vault_auth: UncheckedAccount<'info>,
 
     #[account(mut, associated_token::mint = quote_mint, associated_token::authority = vault_auth)]
-    pub long_margin_vault: Box<Account<'info, TokenAccount>>,
+    pub long_margin_vault: Box<InterfaceAccount<'info, TokenAccount>>,
     #[account(mut, associated_token::mint = quote_mint, associated_token::authority = vault_auth)]
-    pub short_margin_vault: Box<Account<'info, TokenAccount>>,
+    pub short_margin_vault: Box<InterfaceAccount<'info, TokenAccount>>,
 
-    pub token_program: Program<'info, Token>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub token_program: Interface<'info, TokenInterface>,
 }
 
 #[derive(Accounts)]
-pub struct SettleCash<'info> {
-    #[account(mut)]
+pub struct LiquidateDeal<'info> {
+    pub liquidator: Signer<'info>,
     pub market: Account<'info, Market>,
 
     #[account(mut)]
     pub deal: Account<'info, Deal>,
 
-    pub quote_mint: Box<Account<'info, Mint>>,
-    pub receipt_mint: Box<Account<'info, Mint>>,
+    #[account(address = deal.quote_mint)]
+    pub quote_mint: Box<InterfaceAccount<'info, Mint>>,
 
     /// CHECK: vault auth PDA
     #[account(
         seeds = [b"vault_auth", deal.key().as_ref()],
-        bump
+        bump = deal.vault_bump
     )]
     pub vault_auth: UncheckedAccount<'info>,
 
     #[account(mut, associated_token::mint = quote_mint, associated_token::authority = vault_auth)]
-    pub long_margin_vault: Box<Account<'info, TokenAccount>>,
+    pub long_margin_vault: Box<InterfaceAccount<'info, TokenAccount>>,
     #[account(mut, associated_token::mint = quote_mint, associated_token::authority = vault_auth)]
-    pub short_margin_vault: Box<Account<'info, TokenAccount>>,
-
-    // recipients
-    #[account(mut, constraint = long_receive_quote_ata.mint == quote_mint.key())]
-    pub long_receive_quote_ata: Box<Account<'info, TokenAccount>>,
-    #[account(mut, constraint = short_receive_quote_ata.mint == quote_mint.key())]
-    pub short_receive_quote_ata: Box<Account<'info, TokenAccount>>,
+    pub short_margin_vault: Box<InterfaceAccount<'info, TokenAccount>>,
 
-    /// Fee destination: ATA owned by market account
-    #[account(mut, associated_token::mint = quote_mint, associated_token::authority = market)]
-    pub fee_vault: Box<Account<'info, TokenAccount>>,
+    // recipients (pinned to the deal parties since anyone can call this)
+    #[account(
+        mut,
+        constraint = long_receive_quote_ata.mint == quote_mint.key(),
+        constraint = long_receive_quote_ata.owner == deal.long
+    )]
+    pub long_receive_quote_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = short_receive_quote_ata.mint == quote_mint.key(),
+        constraint = short_receive_quote_ata.owner == deal.short
+    )]
+    pub short_receive_quote_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut, constraint = liquidator_quote_ata.mint == quote_mint.key())]
+    pub liquidator_quote_ata: Box<InterfaceAccount<'info, TokenAccount>>,
 
-    pub token_program: Program<'info, Token>,
+    pub token_program: Interface<'info, TokenInterface>,
     pub associated_token_program: Program<'info, AssociatedToken>,
 }
 
 #[derive(Accounts)]
 pub struct SettlePhysical<'info> {
-    #[account(mut)]
+    #[account(mut, has_one = market)]
     pub deal: Account<'info, Deal>,
     pub market: Account<'info, Market>,
 
-    pub quote_mint: Box<Account<'info, Mint>>,
-    pub receipt_mint: Box<Account<'info, Mint>>,
+    pub quote_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mut)]
+    pub receipt_mint: Box<InterfaceAccount<'info, Mint>>,
+    /// CHECK: market-level receipt mint authority; re-mints receipts whose mint is hooked to this program
+    #[account(seeds = [b"receipt_auth", market.key().as_ref()], bump = market.receipt_auth_bump)]
+    pub receipt_mint_auth: UncheckedAccount<'info>,
 
     /// CHECK: vault auth PDA
     #[account(
@@ -989,9 +3604,9 @@ pub struct SettlePhysical<'info> {
     pub vault_auth: UncheckedAccount<'info>,
 
     #[account(mut, associated_token::mint = quote_mint, associated_token::authority = vault_auth)]
-    pub long_margin_vault: Box<Account<'info, TokenAccount>>,
+    pub long_margin_vault: Box<InterfaceAccount<'info, TokenAccount>>,
     #[account(mut, associated_token::mint = quote_mint, associated_token::authority = vault_auth)]
-    pub short_margin_vault: Box<Account<'info, TokenAccount>>,
+    pub short_margin_vault: Box<InterfaceAccount<'info, TokenAccount>>,
 
     // Parties
     #[account(mut, address = deal.long)]
@@ -1005,21 +3620,39 @@ pub struct SettlePhysical<'info> {
         constraint = long_receipt_ata.mint == receipt_mint.key(),
         constraint = long_receipt_ata.owner == long.key()
     )]
-    pub long_receipt_ata: Box<Account<'info, TokenAccount>>,
+    pub long_receipt_ata: Box<InterfaceAccount<'info, TokenAccount>>,
     #[account(
         mut,
         constraint = short_receipt_ata.mint == receipt_mint.key(),
         constraint = short_receipt_ata.owner == short.key()
     )]
-    pub short_receipt_ata: Box<Account<'info, TokenAccount>>,
+    pub short_receipt_ata: Box<InterfaceAccount<'info, TokenAccount>>,
 
     // Quote recipients
     #[account(mut, constraint = long_receive_quote_ata.mint == quote_mint.key())]
-    pub long_receive_quote_ata: Box<Account<'info, TokenAccount>>,
+    pub long_receive_quote_ata: Box<InterfaceAccount<'info, TokenAccount>>,
     #[account(mut, constraint = short_receive_quote_ata.mint == quote_mint.key())]
-    pub short_receive_quote_ata: Box<Account<'info, TokenAccount>>,
+    pub short_receive_quote_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    // Storage owed on the delivered receipts (lot the short delivers from). Receipts are fungible
+    // within the mint, so the lot can't be traced from the tokens: both parties sign and thereby
+    // attest the lot, whose grade prices the delivery. It must still cover the delivered quantity.
+    #[account(mut, constraint = warehouse.market == market.key() @ ErrorCode::ConstraintMismatch)]
+    pub warehouse: Box<Account<'info, Warehouse>>,
+    #[account(mut, has_one = warehouse)]
+    pub lot: Box<Account<'info, ReceiptLot>>,
+    /// CHECK: PDA authority for the storage fee vault
+    #[account(
+        seeds = [b"storage_auth", warehouse.key().as_ref()],
+        bump = warehouse.storage_auth_bump
+    )]
+    pub storage_auth: UncheckedAccount<'info>,
+    // Storage is only charged in the market's quote mint
+    #[account(mut, associated_token::mint = market.quote_mint, associated_token::authority = storage_auth)]
+    pub storage_vault: Box<InterfaceAccount<'info, TokenAccount>>,
 
-    pub token_program: Program<'info, Token>,
+    pub token_program: Interface<'info, TokenInterface>,         // quote mint's program
+    pub receipt_token_program: Interface<'info, TokenInterface>, // receipt mint's program (SPL or Token-2022)
     pub associated_token_program: Program<'info, AssociatedToken>,
 }
 
@@ -1028,7 +3661,7 @@ pub struct CmCreate<'info> {
     #[account(mut)]
     pub owner: Signer<'info>,
     pub market: Account<'info, Market>,
-    pub quote_mint: Box<Account<'info, Mint>>,
+    pub quote_mint: Box<InterfaceAccount<'info, Mint>>,
 
     #[account(
         init,
@@ -1052,9 +3685,9 @@ pub struct CmCreate<'info> {
         associated_token::mint = quote_mint,
         associated_token::authority = cm_vault_auth,
     )]
-    pub cm_vault_ata: Box<Account<'info, TokenAccount>>,
+    pub cm_vault_ata: Box<InterfaceAccount<'info, TokenAccount>>,
 
-    pub token_program: Program<'info, Token>,
+    pub token_program: Interface<'info, TokenInterface>,
     pub associated_token_program: Program<'info, AssociatedToken>,
     pub system_program: Program<'info, System>,
 }
@@ -1064,7 +3697,7 @@ pub struct CmDeposit<'info> {
     #[account(mut)]
     pub owner: Signer<'info>,
     pub market: Account<'info, Market>,
-    pub quote_mint: Box<Account<'info, Mint>>,
+    pub quote_mint: Box<InterfaceAccount<'info, Mint>>,
     #[account(mut, has_one = market, has_one = quote_mint)]
     pub cross_margin: Account<'info, CrossMargin>,
     /// CHECK: PDA authority for CM vault
@@ -1074,16 +3707,16 @@ pub struct CmDeposit<'info> {
     )]
     pub cm_vault_auth: UncheckedAccount<'info>,
     #[account(mut, associated_token::mint = quote_mint, associated_token::authority = cm_vault_auth)]
-    pub cm_vault_ata: Box<Account<'info, TokenAccount>>,
+    pub cm_vault_ata: Box<InterfaceAccount<'info, TokenAccount>>,
 
     #[account(
         mut,
         constraint = owner_quote_ata.owner == owner.key(),
         constraint = owner_quote_ata.mint == quote_mint.key()
     )]
-    pub owner_quote_ata: Box<Account<'info, TokenAccount>>,
+    pub owner_quote_ata: Box<InterfaceAccount<'info, TokenAccount>>,
 
-    pub token_program: Program<'info, Token>,
+    pub token_program: Interface<'info, TokenInterface>,
     pub associated_token_program: Program<'info, AssociatedToken>,
 }
 
@@ -1092,8 +3725,8 @@ pub struct CmWithdraw<'info> {
     #[account(mut)]
     pub owner: Signer<'info>,
     pub market: Account<'info, Market>,
-    pub quote_mint: Box<Account<'info, Mint>>,
-    #[account(mut, has_one = market, has_one = quote_mint)]
+    pub quote_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mut, has_one = market, has_one = quote_mint, has_one = owner)]
     pub cross_margin: Account<'info, CrossMargin>,
     /// CHECK
     #[account(
@@ -1102,16 +3735,16 @@ pub struct CmWithdraw<'info> {
     )]
     pub cm_vault_auth: UncheckedAccount<'info>,
     #[account(mut, associated_token::mint = quote_mint, associated_token::authority = cm_vault_auth)]
-    pub cm_vault_ata: Box<Account<'info, TokenAccount>>,
+    pub cm_vault_ata: Box<InterfaceAccount<'info, TokenAccount>>,
 
     #[account(
         mut,
         constraint = owner_quote_ata.owner == owner.key(),
         constraint = owner_quote_ata.mint == quote_mint.key()
     )]
-    pub owner_quote_ata: Box<Account<'info, TokenAccount>>,
+    pub owner_quote_ata: Box<InterfaceAccount<'info, TokenAccount>>,
 
-    pub token_program: Program<'info, Token>,
+    pub token_program: Interface<'info, TokenInterface>,
     pub associated_token_program: Program<'info, AssociatedToken>,
 }
 
@@ -1120,7 +3753,7 @@ pub struct CmMoveToDeal<'info> {
     #[account(mut)]
     pub owner: Signer<'info>,
     pub market: Account<'info, Market>,
-    pub quote_mint: Box<Account<'info, Mint>>,
+    pub quote_mint: Box<InterfaceAccount<'info, Mint>>,
     #[account(mut)]
     pub deal: Account<'info, Deal>,
     #[account(
@@ -1130,7 +3763,7 @@ pub struct CmMoveToDeal<'info> {
     /// CHECK
     pub vault_auth: UncheckedAccount<'info>,
 
-    #[account(mut, has_one = market, has_one = quote_mint)]
+    #[account(mut, has_one = market, has_one = quote_mint, has_one = owner)]
     pub cross_margin: Account<'info, CrossMargin>,
     /// CHECK
     #[account(
@@ -1139,14 +3772,14 @@ pub struct CmMoveToDeal<'info> {
     )]
     pub cm_vault_auth: UncheckedAccount<'info>,
     #[account(mut, associated_token::mint = quote_mint, associated_token::authority = cm_vault_auth)]
-    pub cm_vault_ata: Box<Account<'info, TokenAccount>>,
+    pub cm_vault_ata: Box<InterfaceAccount<'info, TokenAccount>>,
 
     #[account(mut, associated_token::mint = quote_mint, associated_token::authority = vault_auth)]
-    pub long_margin_vault: Box<Account<'info, TokenAccount>>,
+    pub long_margin_vault: Box<InterfaceAccount<'info, TokenAccount>>,
     #[account(mut, associated_token::mint = quote_mint, associated_token::authority = vault_auth)]
-    pub short_margin_vault: Box<Account<'info, TokenAccount>>,
+    pub short_margin_vault: Box<InterfaceAccount<'info, TokenAccount>>,
 
-    pub token_program: Program<'info, Token>,
+    pub token_program: Interface<'info, TokenInterface>,
 }
 
 #[derive(Accounts)]
@@ -1154,7 +3787,7 @@ pub struct CmMoveFromDeal<'info> {
     #[account(mut)]
     pub owner: Signer<'info>,
     pub market: Account<'info, Market>,
-    pub quote_mint: Box<Account<'info, Mint>>,
+    pub quote_mint: Box<InterfaceAccount<'info, Mint>>,
     #[account(mut)]
     pub deal: Account<'info, Deal>,
     /// CHECK
@@ -1173,14 +3806,153 @@ pub struct CmMoveFromDeal<'info> {
     )]
     pub cm_vault_auth: UncheckedAccount<'info>,
     #[account(mut, associated_token::mint = quote_mint, associated_token::authority = cm_vault_auth)]
-    pub cm_vault_ata: Box<Account<'info, TokenAccount>>,
+    pub cm_vault_ata: Box<InterfaceAccount<'info, TokenAccount>>,
 
     #[account(mut, associated_token::mint = quote_mint, associated_token::authority = vault_auth)]
-    pub long_margin_vault: Box<Account<'info, TokenAccount>>,
+    pub long_margin_vault: Box<InterfaceAccount<'info, TokenAccount>>,
     #[account(mut, associated_token::mint = quote_mint, associated_token::authority = vault_auth)]
-    pub short_margin_vault: Box<Account<'info, TokenAccount>>,
+    pub short_margin_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+#[instruction(settle_ts: i64, settlement_kind: crate::SettlementKind)]
+pub struct InitOrderBook<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub market: Account<'info, Market>,
+    pub quote_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + OrderBook::SIZE,
+        seeds = [
+            b"order_book",
+            market.key().as_ref(),
+            quote_mint.key().as_ref(),
+            &settle_ts.to_le_bytes(),
+            &[settlement_kind as u8],
+        ],
+        bump
+    )]
+    pub book: Box<Account<'info, OrderBook>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct PlaceOrder<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub market: Account<'info, Market>,
+    #[account(mut, has_one = market)]
+    pub book: Box<Account<'info, OrderBook>>,
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + Order::SIZE,
+        seeds = [b"order", book.key().as_ref(), &book.next_order_id.to_le_bytes()],
+        bump
+    )]
+    pub order: Account<'info, Order>,
+    #[account(
+        mut,
+        has_one = market,
+        has_one = owner,
+        constraint = cross_margin.quote_mint == book.quote_mint @ ErrorCode::ConstraintMismatch
+    )]
+    pub cross_margin: Account<'info, CrossMargin>,
+    /// CHECK: PDA authority for CM vault
+    #[account(seeds = [b"cm_vault_auth", cross_margin.key().as_ref()], bump = cross_margin.vault_bump)]
+    pub cm_vault_auth: UncheckedAccount<'info>,
+    #[account(associated_token::mint = cross_margin.quote_mint, associated_token::authority = cm_vault_auth)]
+    pub cm_vault_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelOrder<'info> {
+    pub owner: Signer<'info>,
+    #[account(mut, has_one = owner, has_one = cross_margin, has_one = book)]
+    pub order: Account<'info, Order>,
+    #[account(mut)]
+    pub cross_margin: Account<'info, CrossMargin>,
+    #[account(mut)]
+    pub book: Box<Account<'info, OrderBook>>,
+}
+
+#[derive(Accounts)]
+#[instruction(deal_id: u64)]
+pub struct MatchOrders<'info> {
+    /// Crank; pays rent for the new deal
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub market: Box<Account<'info, Market>>,
+    #[account(mut, has_one = market, has_one = quote_mint)]
+    pub book: Box<Account<'info, OrderBook>>,
+    pub quote_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(mut, constraint = bid.book == book.key() @ ErrorCode::ConstraintMismatch)]
+    pub bid: Box<Account<'info, Order>>,
+    #[account(mut, constraint = ask.book == book.key() @ ErrorCode::ConstraintMismatch)]
+    pub ask: Box<Account<'info, Order>>,
+    /// CHECK: bid owner; becomes the deal's long
+    #[account(address = bid.owner)]
+    pub long: UncheckedAccount<'info>,
+    /// CHECK: ask owner; becomes the deal's short
+    #[account(address = ask.owner)]
+    pub short: UncheckedAccount<'info>,
+
+    #[account(mut, address = bid.cross_margin)]
+    pub bid_cross_margin: Box<Account<'info, CrossMargin>>,
+    /// CHECK: PDA authority for the bid owner's CM vault
+    #[account(seeds = [b"cm_vault_auth", bid_cross_margin.key().as_ref()], bump = bid_cross_margin.vault_bump)]
+    pub bid_cm_vault_auth: UncheckedAccount<'info>,
+    #[account(mut, associated_token::mint = quote_mint, associated_token::authority = bid_cm_vault_auth)]
+    pub bid_cm_vault_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut, address = ask.cross_margin)]
+    pub ask_cross_margin: Box<Account<'info, CrossMargin>>,
+    /// CHECK: PDA authority for the ask owner's CM vault
+    #[account(seeds = [b"cm_vault_auth", ask_cross_margin.key().as_ref()], bump = ask_cross_margin.vault_bump)]
+    pub ask_cm_vault_auth: UncheckedAccount<'info>,
+    #[account(mut, associated_token::mint = quote_mint, associated_token::authority = ask_cm_vault_auth)]
+    pub ask_cm_vault_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Deal::SIZE,
+        seeds = [b"deal", market.key().as_ref(), long.key().as_ref(), short.key().as_ref(), &deal_id.to_le_bytes()],
+        bump
+    )]
+    pub deal: Box<Account<'info, Deal>>,
+    #[account(
+        init,
+        payer = payer,
+        associated_token::mint = quote_mint,
+        associated_token::authority = vault_auth,
+    )]
+    pub long_margin_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = quote_mint,
+        associated_token::authority = vault_auth,
+    )]
+    pub short_margin_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    /// CHECK: Seeds used for signing CPIs
+    #[account(seeds = [b"vault_auth", deal.key().as_ref()], bump)]
+    pub vault_auth: UncheckedAccount<'info>,
+    #[account(
+        init_if_needed,
+        payer = payer,
+        associated_token::mint = quote_mint,
+        associated_token::authority = market,
+    )]
+    pub fee_vault: Box<InterfaceAccount<'info, TokenAccount>>,
 
-    pub token_program: Program<'info, Token>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
@@ -1197,19 +3969,19 @@ pub struct YieldPark<'info> {
     )]
     pub vault_auth: UncheckedAccount<'info>,
 
-    pub quote_mint: Box<Account<'info, Mint>>,
+    pub quote_mint: Box<InterfaceAccount<'info, Mint>>,
 
     // deal margin vaults
     #[account(mut, associated_token::mint = quote_mint, associated_token::authority = vault_auth)]
-    pub long_margin_vault: Box<Account<'info, TokenAccount>>,
+    pub long_margin_vault: Box<InterfaceAccount<'info, TokenAccount>>,
     #[account(mut, associated_token::mint = quote_mint, associated_token::authority = vault_auth)]
-    pub short_margin_vault: Box<Account<'info, TokenAccount>>,
+    pub short_margin_vault: Box<InterfaceAccount<'info, TokenAccount>>,
 
     // strategy vault (same authority PDA for simplicity)
     #[account(mut, associated_token::mint = quote_mint, associated_token::authority = vault_auth)]
-    pub strategy_vault_ata: Box<Account<'info, TokenAccount>>,
+    pub strategy_vault_ata: Box<InterfaceAccount<'info, TokenAccount>>,
 
-    pub token_program: Program<'info, Token>,
+    pub token_program: Interface<'info, TokenInterface>,
 }
 
 // ==========
@@ -1222,7 +3994,7 @@ pub struct Market {
     pub governance_authority: Pubkey,
     pub quote_mint: Pubkey,
     pub receipt_mint: Pubkey,
-    pub oracle_authority: Pubkey,
+    pub oracle_authority: Pubkey, // bootstrap oracle; quorum membership lives in OracleSet
     pub fee_bps: u16,
     pub is_paused: bool,
     pub last_price: u64,     // e.g., 123_456_789 with exponent -6 => 123456.789 quote per unit
@@ -1238,20 +4010,466 @@ pub struct Market {
     pub allowed_count: u8,
     // Strategy operator for yield POC
     pub strategy_operator: Pubkey,
+    // Settlement price source
+    pub settlement_price_mode: u8, // 0=last, 1=twap
+    pub twap_window_secs: i64,
+    // Price guards
+    pub last_price_ts: i64,         // on-chain time the last round finalized
+    pub max_staleness_secs: i64,    // allowed gap between last_price_ts and settle_ts (0 = off)
+    pub max_deviation_bps: u16,     // max move per finalized round (0 = off)
+    pub deviation_override_armed: bool,
+    // Governance timelock
+    pub timelock_delay_secs: i64,
+    pub next_action_id: u64,
+    // PDA identity: seeds use `creator`, so `authority` can rotate without moving the market
+    pub creator: Pubkey,
+    pub bump: u8,
+    // Key rotation nominees (Pubkey::default() = none pending)
+    pub pending_authority: Pubkey,
+    pub pending_governance_authority: Pubkey,
+    pub pending_oracle_authority: Pubkey,
+    pub pending_strategy_operator: Pubkey,
+    // M-of-N council (Pubkey::default() = single-key admin)
+    pub council: Pubkey,
+    // Inventory auditor for attest_inventory (defaults to governance_authority)
+    pub auditor: Pubkey,
+    // Market-level receipt mint authority PDA (`[b"receipt_auth", market]`)
+    pub receipt_auth_bump: u8,
+    // Warehouse performance bonds (quote mint)
+    pub min_warehouse_bond: u64,
+    pub bond_withdraw_delay_secs: i64,
+    // Receipt mint is Token-2022 with this program as its transfer hook (participants only)
+    pub allowlist_enabled: bool,
+    // Physical delivery grade schedule (empty = every grade delivers at par)
+    pub grade_codes: [u16; MAX_GRADES],
+    pub grade_diff_bps: [i16; MAX_GRADES],
+    pub grade_count: u8,
+}
+impl Market {
+    fn params(&self) -> MarketParams {
+        MarketParams {
+            fee_bps: self.fee_bps,
+            base_initial_margin_bps: self.base_initial_margin_bps,
+            maintenance_margin_bps: self.maintenance_margin_bps,
+            vol_multiplier_bps: self.vol_multiplier_bps,
+            price_exponent: self.price_exponent,
+            oracle_authority: self.oracle_authority,
+        }
+    }
+
+    /// Premium (+) / discount (-) in bps for delivering `grade`. Without a schedule every grade is
+    /// par; once one is set, unlisted grades are not deliverable.
+    fn grade_differential_bps(&self, grade: u16) -> Result<i16> {
+        if self.grade_count == 0 {
+            return Ok(0);
+        }
+        let i = (0..self.grade_count as usize)
+            .find(|&i| self.grade_codes[i] == grade)
+            .ok_or(ErrorCode::GradeNotDeliverable)?;
+        Ok(self.grade_diff_bps[i])
+    }
+
+    /// (current, pending) slots for a rotatable key.
+    fn key_slots(&mut self, role: KeyRole) -> (&mut Pubkey, &mut Pubkey) {
+        match role {
+            KeyRole::Authority => (&mut self.authority, &mut self.pending_authority),
+            KeyRole::GovernanceAuthority => (&mut self.governance_authority, &mut self.pending_governance_authority),
+            KeyRole::OracleAuthority => (&mut self.oracle_authority, &mut self.pending_oracle_authority),
+            KeyRole::StrategyOperator => (&mut self.strategy_operator, &mut self.pending_strategy_operator),
+        }
+    }
+
+    pub const SIZE: usize =
+        1 + 32 + 32 + 32 + 32 + 32 + 2 + 1 + 8 + 4 + 8 + 2 + 2 + 2 + 2 + (32 * MAX_COLLATERALS) + 1 + 32
+        + 1 + 8
+        + 8 + 8 + 2 + 1
+        + 8 + 8
+        + 32 + 1
+        + 32 + 32 + 32 + 32
+        + 32
+        + 32
+        + 1
+        + 8 + 8
+        + 1
+        + (2 * MAX_GRADES) + (2 * MAX_GRADES) + 1;
+}
+
+/// Queued admin action awaiting its timelock (kept after execution/cancel as an audit record).
+#[account]
+pub struct PendingAction {
+    pub market: Pubkey,
+    pub action_id: u64,
+    pub action: AdminAction,
+    pub proposer: Pubkey,
+    pub proposed_ts: i64,
+    pub eta: i64,
+    pub status: u8, // ActionStatus
+    pub bump: u8,
+}
+impl PendingAction {
+    pub const SIZE: usize = 32 + 8 + AdminAction::SIZE + 32 + 8 + 8 + 1 + 1;
+}
+
+/// M-of-N governance council for a market (`[b"council", market]`).
+#[account]
+pub struct GovernanceCouncil {
+    pub market: Pubkey,
+    pub members: [Pubkey; MAX_COUNCIL_MEMBERS],
+    pub count: u8,
+    pub threshold: u8,
+    pub epoch: u32, // bumped on every membership/threshold change
+    pub next_proposal_id: u64,
+    pub bump: u8,
+}
+impl GovernanceCouncil {
+    pub const SIZE: usize = 32 + (32 * MAX_COUNCIL_MEMBERS) + 1 + 1 + 4 + 8 + 1;
+
+    fn member_index(&self, key: &Pubkey) -> Option<usize> {
+        (0..self.count as usize).find(|&i| self.members[i] == *key)
+    }
+}
+
+#[account]
+pub struct CouncilProposal {
+    pub council: Pubkey,
+    pub proposal_id: u64,
+    pub action: CouncilAction,
+    pub proposer: Pubkey,
+    pub epoch: u32,     // council epoch at creation; approvals die with it
+    pub approvals: u16, // bit i set => council.members[i] approved
+    pub executed: bool,
+    pub created_ts: i64,
+    pub bump: u8,
+}
+impl CouncilProposal {
+    pub const SIZE: usize = 32 + 8 + CouncilAction::SIZE + 32 + 4 + 2 + 1 + 8 + 1;
+}
+
+/// Registered oracles plus the currently open submission round.
+#[account]
+pub struct OracleSet {
+    pub market: Pubkey,
+    pub members: [Pubkey; MAX_ORACLES],
+    pub count: u8,
+    pub quorum: u8,
+    // Open round
+    pub round_id: u64,
+    pub round_open_ts: i64,
+    pub round_exponent: i32,
+    pub round_settle_ts: i64,
+    pub submitted_mask: u16, // bit i set => members[i] has submitted
+    pub prices: [u64; MAX_ORACLES],
+    pub vols: [u16; MAX_ORACLES],
+    pub bump: u8,
+    pub displaced_mask: u16, // openers whose round was taken over since the last fresh round
+}
+impl OracleSet {
+    pub const SIZE: usize =
+        32 + (32 * MAX_ORACLES) + 1 + 1 + 8 + 8 + 4 + 8 + 2 + (8 * MAX_ORACLES) + (2 * MAX_ORACLES) + 1
+        + 2;
+
+    fn member_index(&self, key: &Pubkey) -> Option<usize> {
+        (0..self.count as usize).find(|&i| self.members[i] == *key)
+    }
+
+    fn open_round(&mut self, now: i64, exponent: i32, settle_ts: i64) {
+        self.round_id = self.round_id.wrapping_add(1);
+        self.round_open_ts = now;
+        self.round_exponent = exponent;
+        self.round_settle_ts = settle_ts;
+        self.submitted_mask = 0;
+    }
+
+    /// Record members[idx]'s submission. A round still backed only by its opener can be taken
+    /// over by a member quoting a different (exponent, settle_ts); the displaced opener can't take
+    /// it back until a fresh round opens, so one member can't stall rounds with a bad settle_ts.
+    fn submit(&mut self, idx: usize, now: i64, exponent: i32, settle_ts: i64, price: u64, vol_bps: u16) -> Result<()> {
+        let bit = 1u16 << idx;
+        if self.submitted_mask == 0 || now - self.round_open_ts > ORACLE_ROUND_TIMEOUT_SECS {
+            self.displaced_mask = 0;
+            self.open_round(now, exponent, settle_ts);
+        } else if self.round_exponent != exponent || self.round_settle_ts != settle_ts {
+            require!(
+                self.submitted_mask.count_ones() == 1 && self.displaced_mask & bit == 0,
+                ErrorCode::OracleRoundMismatch
+            );
+            self.displaced_mask |= self.submitted_mask;
+            self.open_round(now, exponent, settle_ts);
+        }
+        require!(self.submitted_mask & bit == 0, ErrorCode::DuplicateOracleSubmission);
+        self.submitted_mask |= bit;
+        self.prices[idx] = price;
+        self.vols[idx] = vol_bps;
+        Ok(())
+    }
+
+    fn has_quorum(&self) -> bool {
+        self.submitted_mask.count_ones() >= self.quorum as u32
+    }
+
+    /// Median price and vol across the submissions in the open round.
+    fn round_median(&self) -> (u64, u16) {
+        let mut prices = [0u64; MAX_ORACLES];
+        let mut vols = [0u64; MAX_ORACLES];
+        let mut n = 0;
+        for i in 0..self.count as usize {
+            if self.submitted_mask & (1u16 << i) != 0 {
+                prices[n] = self.prices[i];
+                vols[n] = self.vols[i] as u64;
+                n += 1;
+            }
+        }
+        (median_u64(&mut prices[..n]), median_u64(&mut vols[..n]) as u16)
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct PricePoint {
+    pub price: u64,
+    pub ts: i64,        // on-chain time the print was posted
+    pub settle_ts: i64, // expiry the print was quoted for
+}
+
+/// Ring buffer of posted prints, one per market.
+#[account]
+pub struct PriceHistory {
+    pub market: Pubkey,
+    pub head: u16,  // next write index
+    pub count: u16, // number of valid entries (<= PRICE_HISTORY_LEN)
+    pub entries: [PricePoint; PRICE_HISTORY_LEN],
+    pub bump: u8,
+}
+impl PriceHistory {
+    pub const SIZE: usize = 32 + 2 + 2 + (24 * PRICE_HISTORY_LEN) + 1;
+
+    fn push(&mut self, price: u64, ts: i64, settle_ts: i64) {
+        self.entries[self.head as usize] = PricePoint { price, ts, settle_ts };
+        self.head = ((self.head as usize + 1) % PRICE_HISTORY_LEN) as u16;
+        if (self.count as usize) < PRICE_HISTORY_LEN {
+            self.count += 1;
+        }
+    }
+}
+
+/// Settlement price for one (market, settle_ts). Pending until `quorum` oracles approve it through
+/// `finalize_settlement_price`, then frozen (`finalized_ts != 0`).
+#[account]
+pub struct SettlementPrice {
+    pub market: Pubkey,
+    pub settle_ts: i64,
+    pub price: u64,
+    pub price_exponent: i32,
+    pub mode: u8, // SettlementPriceMode used to derive `price`
+    pub finalized_ts: i64, // 0 while approvals are pending
+    pub bump: u8,
+    pub approvals_mask: u16, // bit i set => oracle_set.members[i] approved `price`
+}
+impl SettlementPrice {
+    pub const SIZE: usize = 32 + 8 + 8 + 4 + 1 + 8 + 1
+        + 2;
+}
+
+/// Running time-weighted average for one expiry (`[b"twap", market, settle_ts]`). Every finalized
+/// round quoted for the expiry is folded in as it lands, so the TWAP no longer depends on the
+/// prints still being in the `PriceHistory` ring. The window start is fixed by the first print.
+#[account]
+pub struct TwapAccumulator {
+    pub market: Pubkey,
+    pub settle_ts: i64,
+    pub start_ts: i64,   // settle_ts - twap_window_secs when the first print arrived
+    pub last_price: u64, // latest print at or before settle_ts (0 = none yet)
+    pub last_ts: i64,
+    pub weighted: u128,  // Σ price × seconds held inside [start_ts, last_ts]
+    pub secs: u64,
+    pub bump: u8,
+}
+impl TwapAccumulator {
+    pub const SIZE: usize = 32 + 8 + 8 + 8 + 8 + 16 + 8 + 1;
+
+    /// Fold in a print posted at `ts`. Prints after the expiry are outside every window.
+    fn record(&mut self, price: u64, ts: i64) {
+        if ts > self.settle_ts {
+            return;
+        }
+        self.accrue(ts);
+        self.last_price = price;
+        self.last_ts = ts;
+    }
+
+    /// Credit the price in force since `last_ts` up to `to_ts`, clipped to the window; a print
+    /// from before the window is the opening price at `start_ts`.
+    fn accrue(&mut self, to_ts: i64) {
+        let from_ts = self.last_ts.max(self.start_ts);
+        if self.last_price == 0 || to_ts <= from_ts {
+            return;
+        }
+        let dt = (to_ts - from_ts) as u64;
+        self.weighted = self.weighted.saturating_add(self.last_price as u128 * dt as u128);
+        self.secs = self.secs.saturating_add(dt);
+    }
+
+    /// TWAP over [start_ts, settle_ts], the last print holding until settle_ts. None before any print.
+    fn twap(&self) -> Option<u64> {
+        if self.last_price == 0 {
+            return None;
+        }
+        let mut acc = self.clone();
+        acc.accrue(self.settle_ts);
+        if acc.secs == 0 {
+            return Some(acc.last_price);
+        }
+        Some((acc.weighted / acc.secs as u128) as u64)
+    }
+}
+
+#[account]
+pub struct Warehouse {
+    pub market: Pubkey,
+    pub authority: Pubkey,        // certified warehouse signer
+    pub receipt_mint: Pubkey,
+    pub bump: u8,                 // warehouse PDA
+    // Inventory ledger (receipt base units)
+    pub attested_quantity: u64,   // latest auditor attestation
+    pub total_minted: u64,
+    pub total_burned: u64,
+    pub last_attested_ts: i64,
+    pub next_lot_id: u64,
+    // Certification
+    pub status: u8, // WarehouseStatus
+    pub certified_until: i64,
+    pub certified_by: Pubkey,
+    pub next_redemption_id: u64,
+    // Storage fees (quote mint of the market)
+    pub storage_rate: u64,           // quote base units per whole receipt unit per day
+    pub storage_fees_accrued: u64,   // earned, not yet collected
+    pub storage_fees_collected: u64,
+    pub storage_auth_bump: u8,
+    pub mint_quota: u64,             // cap on outstanding receipts, set at approval
+    // Performance bond (quote mint, held by `[b"bond_auth", warehouse]`)
+    pub bond_amount: u64,
+    pub bond_auth_bump: u8,
+    pub pending_bond_withdrawal: u64,
+    pub bond_withdraw_eta: i64,
+    pub total_slashed: u64,
+    pub pending_storage_rate: u64,   // queued increase, in force from `storage_rate_eta`
+    pub storage_rate_eta: i64,       // 0 = no increase queued
+}
+impl Warehouse {
+    pub const SIZE: usize = 32 + 32 + 32 + 1
+        + 8 + 8 + 8 + 8
+        + 8
+        + 1 + 8 + 32
+        + 8
+        + 8 + 8 + 8 + 1
+        + 8
+        + 8 + 1 + 8 + 8 + 8
+        + 8 + 8;
+
+    /// Storage rate in force at `now`, including a queued increase whose notice has run out.
+    fn storage_rate_at(&self, now: i64) -> u64 {
+        if self.storage_rate_eta != 0 && now >= self.storage_rate_eta {
+            self.pending_storage_rate
+        } else {
+            self.storage_rate
+        }
+    }
+
+    /// Receipts minted by this warehouse and not yet burned.
+    fn outstanding(&self) -> Result<u64> {
+        let outstanding = self.total_minted.checked_sub(self.total_burned).ok_or(ErrorCode::MathOverflow)?;
+        Ok(outstanding)
+    }
 }
-impl Market {
-    pub const SIZE: usize =
-        1 + 32 + 32 + 32 + 32 + 32 + 2 + 1 + 8 + 4 + 8 + 2 + 2 + 2 + 2 + (32 * MAX_COLLATERALS) + 1 + 32;
+
+/// One physical lot backing a `mint_receipt` call (`[b"lot", warehouse, lot_id]`).
+#[account]
+pub struct ReceiptLot {
+    pub warehouse: Pubkey,
+    pub lot_id: u64,
+    pub grade: u16,
+    pub gross_weight: u64,
+    pub net_weight: u64,
+    pub location: [u8; 16],
+    pub intake_ts: i64,
+    pub certificate_hash: [u8; 32],
+    pub quantity_minted: u64,  // receipt base units
+    pub quantity_burned: u64,
+    pub bump: u8,
+    pub storage_credit: u64,   // storage prepaid by shorts at physical delivery, offsets redemption fees
+    pub minted_ts: i64,        // on-chain time of the mint; storage accrual starts here at the earliest
+}
+impl ReceiptLot {
+    pub const SIZE: usize = 32 + 8 + 2 + 8 + 8 + 16 + 8 + 32 + 8 + 8 + 1
+        + 8
+        + 8;
+
+    /// Storage accrues from intake but never from before the mint: `intake_ts` is supplied by the
+    /// warehouse and could otherwise be backdated to charge for days that never happened.
+    fn storage_start_ts(&self) -> i64 {
+        self.intake_ts.max(self.minted_ts)
+    }
+
+    fn outstanding(&self) -> Result<u64> {
+        let outstanding = self.quantity_minted.checked_sub(self.quantity_burned).ok_or(ErrorCode::MathOverflow)?;
+        Ok(outstanding)
+    }
 }
 
+/// Receipts escrowed for physical delivery (`[b"redemption", warehouse, redemption_id]`).
+/// Kept after release/cancel as the record tying each burn to a release.
 #[account]
-pub struct Warehouse {
+pub struct Redemption {
+    pub warehouse: Pubkey,
+    pub lot: Pubkey,
+    pub redemption_id: u64,
+    pub holder: Pubkey,
+    pub amount: u64,
+    pub delivery_instructions: [u8; 64], // consignee / address reference, zero-padded
+    pub status: u8,                      // RedemptionStatus
+    pub requested_ts: i64,
+    pub expires_ts: i64,
+    pub closed_ts: i64,
+    pub bump: u8,
+    pub auth_bump: u8,                   // redemption_auth PDA
+    pub storage_fee: u64,                // escrowed in the storage vault until release
+    pub storage_credit_used: u64,        // lot credit consumed (restored on cancel)
+    pub has_escrow_participant: bool,    // allowlisted market: escrow Participant PDA to close at the end
+}
+impl Redemption {
+    pub const SIZE: usize = 32 + 32 + 8 + 32 + 8 + 64 + 1 + 8 + 8 + 8 + 1 + 1 + 8 + 8
+        + 1;
+}
+
+/// Latest proof-of-reserve posted for a warehouse (`[b"reserve_snapshot", warehouse]`).
+#[account]
+pub struct ReserveSnapshot {
+    pub warehouse: Pubkey,
+    pub merkle_root: [u8; 32],
+    pub total_quantity: u64,   // receipt base units physically on hand
+    pub lot_count: u32,
+    pub as_of_ts: i64,         // when the count was taken
+    pub posted_ts: i64,
+    pub posted_by: Pubkey,
+    pub seq: u64,              // increments with every snapshot
+    pub bump: u8,
+}
+impl ReserveSnapshot {
+    pub const SIZE: usize = 32 + 32 + 8 + 4 + 8 + 8 + 32 + 8 + 1;
+}
+
+/// Onboarded holder of a hooked receipt mint (`[b"participant", market, owner]`).
+#[account]
+pub struct Participant {
     pub market: Pubkey,
-    pub authority: Pubkey,        // certified warehouse signer
-    pub receipt_mint: Pubkey,
-    pub bump: u8,                 // for receipt_mint_auth PDA
+    pub owner: Pubkey,      // wallet (or escrow PDA) owning receipt token accounts
+    pub is_active: bool,
+    pub updated_by: Pubkey,
+    pub updated_ts: i64,
+    pub bump: u8,
+}
+impl Participant {
+    pub const SIZE: usize = 32 + 32 + 1 + 32 + 8 + 1;
 }
-impl Warehouse { pub const SIZE: usize = 32 + 32 + 32 + 1; }
 
 #[account]
 pub struct Deal {
@@ -1270,6 +4488,7 @@ pub struct Deal {
     pub settlement_kind: u8,     // 0=cash, 1=physical
     pub long_margin: u64,
     pub short_margin: u64,
+    pub last_mark_price: u64,    // variation margin has been exchanged up to this price
     pub fee_bps: u16,
     pub is_settled: bool,
     pub is_frozen: bool,
@@ -1278,7 +4497,33 @@ pub struct Deal {
 }
 impl Deal {
     pub const SIZE: usize =
-        1 + 1 + 32 + 8 + 32 + 32 + 32 + 32 + 8 + 4 + 8 + 8 + 1 + 8 + 8 + 2 + 1 + 1 + 1 + 1;
+        1 + 1 + 32 + 8 + 32 + 32 + 32 + 32 + 8 + 4 + 8 + 8 + 1 + 8 + 8 + 8 + 2 + 1 + 1 + 1 + 1;
+}
+
+/// Maker's standing offer for one deal (`[b"deal_offer", market, maker, offer_id]`). The maker's
+/// initial margin sits in the offer vault until `accept_deal` or `cancel_offer`.
+#[account]
+pub struct DealOffer {
+    pub market: Pubkey,
+    pub maker: Pubkey,
+    pub offer_id: u64,
+    pub maker_side: u8,          // 0=long, 1=short
+    pub taker: Pubkey,           // Pubkey::default() = open to anyone
+    pub quote_mint: Pubkey,
+    pub deal_id: u64,            // id of the Deal created on acceptance
+    pub strike_price: u64,
+    pub qty_receipt_amount: u64,
+    pub settle_ts: i64,
+    pub settlement_kind: u8,
+    pub maker_margin: u64,
+    pub expires_ts: i64,
+    pub status: u8,              // OfferStatus
+    pub deal: Pubkey,            // set on acceptance
+    pub bump: u8,
+    pub auth_bump: u8,           // offer_auth PDA
+}
+impl DealOffer {
+    pub const SIZE: usize = 32 + 32 + 8 + 1 + 32 + 32 + 8 + 8 + 8 + 8 + 1 + 8 + 8 + 1 + 32 + 1 + 1;
 }
 
 #[account]
@@ -1287,9 +4532,99 @@ pub struct CrossMargin {
     pub owner: Pubkey,
     pub quote_mint: Pubkey,
     pub vault_bump: u8,
+    pub locked: u64,             // margin reserved by resting orders
 }
 impl CrossMargin {
-    pub const SIZE: usize = 32 + 32 + 32 + 1;
+    pub const SIZE: usize = 32 + 32 + 32 + 1
+        + 8;
+}
+
+/// One book per market, collateral mint and expiry
+/// (`[b"order_book", market, quote_mint, settle_ts, settlement_kind]`).
+#[account]
+pub struct OrderBook {
+    pub market: Pubkey,
+    pub quote_mint: Pubkey,
+    pub settle_ts: i64,
+    pub settlement_kind: u8,
+    pub next_order_id: u64,
+    pub fill_count: u64,
+    pub bump: u8,
+    // Open orders per side, unsorted; `best` scans for price-time priority
+    pub bid_count: u8,
+    pub ask_count: u8,
+    pub bids: [BookEntry; MAX_BOOK_ORDERS],
+    pub asks: [BookEntry; MAX_BOOK_ORDERS],
+}
+impl OrderBook {
+    pub const SIZE: usize = 32 + 32 + 8 + 1 + 8 + 8 + 1
+        + 1 + 1 + (48 * MAX_BOOK_ORDERS * 2);
+
+    fn side_mut(&mut self, side: u8) -> (&mut [BookEntry; MAX_BOOK_ORDERS], &mut u8) {
+        if side == crate::Side::Long as u8 {
+            (&mut self.bids, &mut self.bid_count)
+        } else {
+            (&mut self.asks, &mut self.ask_count)
+        }
+    }
+
+    fn add(&mut self, side: u8, entry: BookEntry) -> Result<()> {
+        let (entries, count) = self.side_mut(side);
+        require!((*count as usize) < MAX_BOOK_ORDERS, ErrorCode::OrderBookFull);
+        entries[*count as usize] = entry;
+        *count += 1;
+        Ok(())
+    }
+
+    fn remove(&mut self, side: u8, order_id: u64) {
+        let (entries, count) = self.side_mut(side);
+        let n = *count as usize;
+        if let Some(i) = (0..n).find(|&i| entries[i].order_id == order_id) {
+            entries[i] = entries[n - 1];
+            entries[n - 1] = BookEntry::default();
+            *count -= 1;
+        }
+    }
+
+    /// Highest bid / lowest ask, oldest first at the same price, skipping `exclude_owner`'s
+    /// orders so a self-crossed pair can't hold up the rest of the book.
+    fn best(&self, side: u8, exclude_owner: &Pubkey) -> Option<u64> {
+        let is_bid = side == crate::Side::Long as u8;
+        let entries = if is_bid { &self.bids[..self.bid_count as usize] } else { &self.asks[..self.ask_count as usize] };
+        entries
+            .iter()
+            .filter(|e| e.owner != *exclude_owner)
+            .min_by_key(|e| (if is_bid { u64::MAX - e.strike_price } else { e.strike_price }, e.order_id))
+            .map(|e| e.order_id)
+    }
+}
+
+/// An open order in its book's index.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct BookEntry {
+    pub order_id: u64,
+    pub owner: Pubkey,
+    pub strike_price: u64,
+}
+
+/// Resting order (`[b"order", book, order_id]`); its margin stays in the owner's cross-margin vault.
+#[account]
+pub struct Order {
+    pub book: Pubkey,
+    pub owner: Pubkey,
+    pub cross_margin: Pubkey,
+    pub order_id: u64,
+    pub side: u8,                // 0=bid (long), 1=ask (short)
+    pub strike_price: u64,
+    pub qty_receipt_amount: u64,
+    pub remaining_qty: u64,
+    pub locked_margin: u64,      // share of cross_margin.locked still held for remaining_qty
+    pub status: u8,              // OrderStatus
+    pub created_ts: i64,
+    pub bump: u8,
+}
+impl Order {
+    pub const SIZE: usize = 32 + 32 + 32 + 8 + 1 + 8 + 8 + 8 + 8 + 1 + 8 + 1;
 }
 
 // ==========
@@ -1305,14 +4640,57 @@ pub struct MarketInitialized {
     pub fee_bps: u16,
 }
 #[event] pub struct MarketPaused { pub market: Pubkey }
+#[event] pub struct ActionProposed { pub market: Pubkey, pub action_id: u64, pub proposer: Pubkey, pub eta: i64 }
+#[event] pub struct ActionExecuted { pub market: Pubkey, pub action_id: u64 }
+#[event] pub struct ActionCancelled { pub market: Pubkey, pub action_id: u64 }
+#[event] pub struct TimelockDelaySet { pub market: Pubkey, pub delay_secs: i64 }
+#[event] pub struct MarketParamsUpdated { pub market: Pubkey, pub old: MarketParams, pub new: MarketParams }
 #[event] pub struct MarketUnpaused { pub market: Pubkey }
-#[event] pub struct PricePosted { pub market: Pubkey, pub price: u64, pub exponent: i32, pub settle_ts: i64, pub vol_bps: u16 }
+#[event] pub struct CouncilInitialized { pub market: Pubkey, pub council: Pubkey, pub count: u8, pub threshold: u8 }
+#[event] pub struct CouncilProposalCreated { pub council: Pubkey, pub proposal_id: u64, pub proposer: Pubkey }
+#[event] pub struct CouncilProposalApproved { pub council: Pubkey, pub proposal_id: u64, pub member: Pubkey, pub approvals: u8 }
+#[event] pub struct CouncilProposalExecuted { pub council: Pubkey, pub proposal_id: u64 }
+#[event] pub struct CouncilMembershipChanged { pub council: Pubkey, pub count: u8, pub threshold: u8, pub epoch: u32 }
+#[event] pub struct KeyRotationProposed { pub market: Pubkey, pub role: u8, pub proposer: Pubkey, pub pending: Pubkey }
+#[event] pub struct KeyRotated { pub market: Pubkey, pub role: u8, pub old: Pubkey, pub new: Pubkey }
+#[event] pub struct SettlementModeSet { pub market: Pubkey, pub mode: u8, pub twap_window_secs: i64 }
+#[event] pub struct PricePosted { pub market: Pubkey, pub round_id: u64, pub price: u64, pub exponent: i32, pub settle_ts: i64, pub vol_bps: u16 }
+#[event] pub struct PriceGuardsSet { pub market: Pubkey, pub max_staleness_secs: i64, pub max_deviation_bps: u16 }
+#[event] pub struct DeviationOverrideArmed { pub market: Pubkey }
+#[event] pub struct DeviationOverrideUsed { pub market: Pubkey, pub round_id: u64, pub prev_price: u64, pub price: u64 }
+#[event] pub struct SettlementPriceApproved { pub market: Pubkey, pub settle_ts: i64, pub oracle: Pubkey, pub price: u64 }
+#[event] pub struct SettlementPriceFinalized { pub market: Pubkey, pub settle_ts: i64, pub price: u64, pub exponent: i32, pub mode: u8 }
+#[event] pub struct OraclePriceSubmitted { pub market: Pubkey, pub oracle: Pubkey, pub round_id: u64, pub price: u64, pub vol_bps: u16 }
+#[event] pub struct OracleAdded { pub market: Pubkey, pub oracle: Pubkey }
+#[event] pub struct OracleRemoved { pub market: Pubkey, pub oracle: Pubkey }
+#[event] pub struct OracleQuorumSet { pub market: Pubkey, pub quorum: u8 }
 #[event] pub struct CollateralAdded { pub market: Pubkey, pub collateral_mint: Pubkey }
 #[event] pub struct CollateralRemoved { pub market: Pubkey, pub collateral_mint: Pubkey }
 
 #[event] pub struct WarehouseInitialized { pub market: Pubkey, pub warehouse: Pubkey, pub warehouse_authority: Pubkey, pub receipt_mint: Pubkey }
-#[event] pub struct ReceiptMinted { pub warehouse: Pubkey, pub to: Pubkey, pub amount: u64 }
-#[event] pub struct ReceiptBurned { pub owner: Pubkey, pub amount: u64 }
+#[event] pub struct WarehouseApproved { pub warehouse: Pubkey, pub approver: Pubkey, pub certified_until: i64, pub mint_quota: u64 }
+#[event] pub struct WarehouseRevoked { pub warehouse: Pubkey, pub revoker: Pubkey }
+#[event] pub struct GradeDifferentialSet { pub market: Pubkey, pub grade: u16, pub diff_bps: i16 }
+#[event] pub struct GradeRemoved { pub market: Pubkey, pub grade: u16 }
+#[event] pub struct BondParamsSet { pub market: Pubkey, pub min_bond: u64, pub withdraw_delay_secs: i64 }
+#[event] pub struct BondPosted { pub warehouse: Pubkey, pub amount: u64, pub bond_amount: u64 }
+#[event] pub struct BondWithdrawalRequested { pub warehouse: Pubkey, pub amount: u64, pub eta: i64 }
+#[event] pub struct BondWithdrawn { pub warehouse: Pubkey, pub amount: u64, pub bond_amount: u64 }
+#[event] pub struct WarehouseSlashed { pub warehouse: Pubkey, pub recipient: Pubkey, pub redemption: Pubkey, pub amount: u64, pub bond_amount: u64 }
+#[event] pub struct ParticipantSet { pub market: Pubkey, pub owner: Pubkey, pub is_active: bool }
+#[event] pub struct ReceiptHookInitialized { pub market: Pubkey, pub receipt_mint: Pubkey }
+#[event] pub struct StorageRateSet { pub warehouse: Pubkey, pub storage_rate: u64 }
+#[event] pub struct StorageRateIncreaseQueued { pub warehouse: Pubkey, pub storage_rate: u64, pub eta: i64 }
+#[event] pub struct StorageFeesCollected { pub warehouse: Pubkey, pub amount: u64 }
+#[event] pub struct ReserveSnapshotPosted { pub warehouse: Pubkey, pub seq: u64, pub merkle_root: [u8; 32], pub total_quantity: u64, pub lot_count: u32, pub as_of_ts: i64 }
+#[event] pub struct ReserveShortfall { pub warehouse: Pubkey, pub seq: u64, pub outstanding: u64, pub total_quantity: u64 }
+#[event] pub struct LotInclusionVerified { pub warehouse: Pubkey, pub lot: Pubkey, pub seq: u64, pub quantity: u64, pub outstanding: u64 }
+#[event] pub struct AuditorSet { pub market: Pubkey, pub auditor: Pubkey }
+#[event] pub struct InventoryAttested { pub warehouse: Pubkey, pub auditor: Pubkey, pub quantity: u64, pub outstanding: u64 }
+#[event] pub struct ReceiptMinted { pub warehouse: Pubkey, pub lot_id: u64, pub to: Pubkey, pub amount: u64 }
+#[event] pub struct RedemptionRequested { pub warehouse: Pubkey, pub redemption: Pubkey, pub lot_id: u64, pub holder: Pubkey, pub amount: u64, pub storage_fee: u64, pub expires_ts: i64 }
+#[event] pub struct RedemptionReleased { pub warehouse: Pubkey, pub redemption: Pubkey, pub lot_id: u64, pub holder: Pubkey, pub amount: u64 }
+#[event] pub struct RedemptionCancelled { pub warehouse: Pubkey, pub redemption: Pubkey, pub holder: Pubkey, pub amount: u64, pub expired: bool }
 
 #[event]
 pub struct DealOpened {
@@ -1330,19 +4708,82 @@ pub struct DealOpened {
     pub fee_bps: u16,
 }
 
+#[event]
+pub struct DealOfferProposed {
+    pub market: Pubkey,
+    pub offer: Pubkey,
+    pub maker: Pubkey,
+    pub side: u8,
+    pub taker: Pubkey,
+    pub deal_id: u64,
+    pub strike_price: u64,
+    pub qty_receipt_amount: u64,
+    pub settle_ts: i64,
+    pub margin: u64,
+    pub expires_ts: i64,
+}
+#[event] pub struct DealOfferAccepted { pub offer: Pubkey, pub deal: Pubkey, pub taker: Pubkey, pub taker_margin: u64 }
+#[event] pub struct DealOfferCancelled { pub offer: Pubkey, pub maker: Pubkey, pub refunded: u64 }
+
+#[event]
+pub struct DealNovated {
+    pub deal: Pubkey,
+    pub side: u8,
+    pub outgoing: Pubkey,
+    pub incoming: Pubkey,
+    pub returned: u64,
+    pub incoming_margin: u64,
+}
+
 #[event] pub struct DealFrozen { pub deal: Pubkey }
 #[event] pub struct DealUnfrozen { pub deal: Pubkey }
 
 #[event] pub struct MarginDeposited { pub deal: Pubkey, pub side: u8, pub amount: u64 }
 #[event] pub struct CashSettled { pub deal: Pubkey, pub final_price: u64, pub pnl_long: i128 }
-#[event] pub struct PhysicalSettled { pub deal: Pubkey, pub qty_receipt_amount: u64, pub pay_amount: u64 }
-#[event] pub struct PartialPhysicalSettled { pub deal: Pubkey, pub amount_receipt: u64, pub pay_amount: u64, pub fully_settled: bool }
+#[event] pub struct DealTerminated { pub deal: Pubkey, pub long: Pubkey, pub short: Pubkey, pub close_price: u64, pub pnl_long: i128, pub ts: i64 }
+#[event] pub struct PhysicalSettled { pub deal: Pubkey, pub qty_receipt_amount: u64, pub pay_amount: u64, pub storage_fee: u64, pub grade: u16, pub grade_diff_bps: i16 }
+#[event] pub struct PartialPhysicalSettled { pub deal: Pubkey, pub amount_receipt: u64, pub pay_amount: u64, pub storage_fee: u64, pub grade: u16, pub grade_diff_bps: i16, pub fully_settled: bool }
+#[event] pub struct VariationMarginPosted { pub deal: Pubkey, pub prev_mark_price: u64, pub mark_price: u64, pub pnl_long: i128 }
+#[event]
+pub struct DealLiquidated {
+    pub deal: Pubkey,
+    pub liquidator: Pubkey,
+    pub side: u8, // liquidated side: 0=long, 1=short
+    pub mark_price: u64,
+    pub pnl_long: i128,
+    pub pnl_paid: u64,
+    pub bounty: u64,
+}
 
 #[event] pub struct CrossMarginCreated { pub market: Pubkey, pub owner: Pubkey, pub quote_mint: Pubkey, pub vault: Pubkey }
 #[event] pub struct CrossMarginDeposited { pub market: Pubkey, pub owner: Pubkey, pub amount: u64 }
 #[event] pub struct CrossMarginWithdrawn { pub market: Pubkey, pub owner: Pubkey, pub amount: u64 }
 #[event] pub struct CrossMarginToDeal { pub deal: Pubkey, pub side: u8, pub amount: u64 }
 #[event] pub struct DealToCrossMargin { pub deal: Pubkey, pub side: u8, pub amount: u64 }
+#[event] pub struct OrderBookCreated { pub market: Pubkey, pub book: Pubkey, pub quote_mint: Pubkey, pub settle_ts: i64, pub kind: u8 }
+#[event]
+pub struct OrderPlaced {
+    pub book: Pubkey,
+    pub order: Pubkey,
+    pub order_id: u64,
+    pub owner: Pubkey,
+    pub side: u8,
+    pub strike_price: u64,
+    pub qty_receipt_amount: u64,
+    pub locked_margin: u64,
+}
+#[event] pub struct OrderCancelled { pub book: Pubkey, pub order: Pubkey, pub owner: Pubkey, pub remaining_qty: u64, pub released: u64 }
+#[event]
+pub struct OrderFilled {
+    pub book: Pubkey,
+    pub deal: Pubkey,
+    pub bid: Pubkey,
+    pub ask: Pubkey,
+    pub price: u64,
+    pub qty: u64,
+    pub bid_remaining: u64,
+    pub ask_remaining: u64,
+}
 
 #[event] pub struct YieldOperatorSet { pub market: Pubkey, pub operator: Pubkey }
 #[event] pub struct YieldParked { pub deal: Pubkey, pub side: u8, pub amount: u64 }
@@ -1357,6 +4798,7 @@ struct DealSnapshot {
     pub quote_mint: Pubkey,
     pub receipt_mint: Pubkey,
     pub strike_price: u64,
+    pub last_mark_price: u64,
     pub price_exponent: i32,
     pub qty_receipt_amount: u64,
     pub fee_bps: u16,
@@ -1369,6 +4811,7 @@ impl DealSnapshot {
             quote_mint: d.quote_mint,
             receipt_mint: d.receipt_mint,
             strike_price: d.strike_price,
+            last_mark_price: d.last_mark_price,
             price_exponent: d.price_exponent,
             qty_receipt_amount: d.qty_receipt_amount,
             fee_bps: d.fee_bps,
@@ -1399,9 +4842,71 @@ impl MarketSnapshot {
     }
 }
 
+/// Terms of a new deal, shared by `open_deal` and `accept_deal`.
+#[derive(Clone, Copy)]
+struct NewDeal {
+    pub deal_id: u64,
+    pub long: Pubkey,
+    pub short: Pubkey,
+    pub quote_mint: Pubkey,
+    pub strike_price: u64,
+    pub qty_receipt_amount: u64,
+    pub settle_ts: i64,
+    pub settlement_kind: u8,
+}
+
 // ==========
 // Helpers & Math
 // ==========
+/// Market-level checks every new deal passes, however it is opened.
+fn check_new_deal(market: &Market, quote_mint: &Pubkey, settle_ts: i64) -> Result<()> {
+    require!(!market.is_paused, ErrorCode::MarketPaused);
+    require!(settle_ts > Clock::get()?.unix_timestamp, ErrorCode::InvalidSettlementTime);
+    require!(is_allowed_collateral(market, quote_mint), ErrorCode::CollateralNotAllowed);
+    Ok(())
+}
+
+/// Fill a freshly created deal; margins start at zero and are credited as they are funded.
+fn init_deal(deal: &mut Account<Deal>, market: &Account<Market>, t: &NewDeal, bump: u8, vault_bump: u8) {
+    deal.version = VERSION;
+    deal.deal_version = DEAL_VERSION;
+    deal.market = market.key();
+    deal.deal_id = t.deal_id;
+    deal.long = t.long;
+    deal.short = t.short;
+    deal.quote_mint = t.quote_mint;
+    deal.receipt_mint = market.receipt_mint;
+    deal.strike_price = t.strike_price;
+    deal.price_exponent = market.price_exponent;
+    deal.qty_receipt_amount = t.qty_receipt_amount;
+    deal.settle_ts = t.settle_ts;
+    deal.settlement_kind = t.settlement_kind;
+    deal.long_margin = 0;
+    deal.short_margin = 0;
+    deal.last_mark_price = t.strike_price;
+    deal.fee_bps = market.fee_bps;
+    deal.is_settled = false;
+    deal.is_frozen = false;
+    deal.bump = bump;
+    deal.vault_bump = vault_bump;
+}
+
+fn emit_deal_opened(deal: &Account<Deal>) {
+    emit!(DealOpened {
+        market: deal.market,
+        deal: deal.key(),
+        deal_id: deal.deal_id,
+        long: deal.long,
+        short: deal.short,
+        quote_mint: deal.quote_mint,
+        receipt_mint: deal.receipt_mint,
+        strike_price: deal.strike_price,
+        qty_receipt_amount: deal.qty_receipt_amount,
+        settle_ts: deal.settle_ts,
+        kind: deal.settlement_kind,
+        fee_bps: deal.fee_bps,
+    });
+}
 fn only_admin(market: &Market, signer: &Signer) -> Result<()> {
     require!(
         signer.key() == market.authority || signer.key() == market.governance_authority,
@@ -1410,6 +4915,253 @@ fn only_admin(market: &Market, signer: &Signer) -> Result<()> {
     Ok(())
 }
 
+/// Single admin key until a council is installed; after that an approved, unexecuted proposal
+/// for exactly `expected` is required and consumed.
+fn authorize_admin(
+    market: &Market,
+    signer: &Signer,
+    council: &Option<Box<Account<GovernanceCouncil>>>,
+    proposal: &mut Option<Account<CouncilProposal>>,
+    expected: CouncilAction,
+) -> Result<()> {
+    if market.council == Pubkey::default() {
+        return only_admin(market, signer);
+    }
+    let council = council.as_ref().ok_or(ErrorCode::CouncilApprovalRequired)?;
+    let proposal = proposal.as_mut().ok_or(ErrorCode::CouncilApprovalRequired)?;
+    require_keys_eq!(council.key(), market.council, ErrorCode::ConstraintMismatch);
+    require_keys_eq!(proposal.council, council.key(), ErrorCode::ConstraintMismatch);
+    check_proposal_ready(council, proposal)?;
+    require!(proposal.action == expected, ErrorCode::CouncilActionMismatch);
+    proposal.executed = true;
+    emit!(CouncilProposalExecuted { council: proposal.council, proposal_id: proposal.proposal_id });
+    Ok(())
+}
+
+fn check_proposal_ready(council: &GovernanceCouncil, proposal: &CouncilProposal) -> Result<()> {
+    require!(!proposal.executed, ErrorCode::ProposalAlreadyExecuted);
+    require!(proposal.epoch == council.epoch, ErrorCode::StaleProposal);
+    require!(proposal.approvals.count_ones() >= council.threshold as u32, ErrorCode::ThresholdNotReached);
+    Ok(())
+}
+
+/// Static parameter checks, run at propose time so bad actions never enter the queue.
+fn check_admin_action_params(action: &AdminAction) -> Result<()> {
+    match *action {
+        AdminAction::SetSettlementMode { mode, twap_window_secs } => {
+            if mode == SettlementPriceMode::Twap {
+                require!(twap_window_secs > 0, ErrorCode::InvalidTwapWindow);
+            }
+        }
+        AdminAction::SetPriceGuards { max_staleness_secs, max_deviation_bps } => {
+            require!(max_staleness_secs >= 0, ErrorCode::InvalidPriceGuard);
+            require!(max_deviation_bps as u64 <= BPS_DENOMINATOR, ErrorCode::InvalidPriceGuard);
+        }
+        AdminAction::SetOracleQuorum { quorum } => {
+            require!(quorum > 0 && quorum as usize <= MAX_ORACLES, ErrorCode::InvalidQuorum);
+        }
+        AdminAction::SetTimelockDelay { delay_secs } => {
+            require!((0..=MAX_TIMELOCK_DELAY_SECS).contains(&delay_secs), ErrorCode::InvalidTimelockDelay);
+        }
+        AdminAction::UpdateMarketParams { params } => validate_market_params(&params)?,
+        AdminAction::SetBondParams { withdraw_delay_secs, .. } => {
+            require!((0..=MAX_TIMELOCK_DELAY_SECS).contains(&withdraw_delay_secs), ErrorCode::InvalidTimelockDelay);
+        }
+        AdminAction::SetGradeDifferential { diff_bps, .. } => {
+            require!(diff_bps.unsigned_abs() as u64 <= MAX_GRADE_DIFF_BPS, ErrorCode::InvalidGradeDifferential);
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// State changes for a matured timelock action; emits the same events the direct setters did.
+fn apply_admin_action(market: &mut Account<Market>, oracle_set: &mut OracleSet, action: &AdminAction) -> Result<()> {
+    check_admin_action_params(action)?;
+    let market_key = market.key();
+    match *action {
+        AdminAction::AddAllowedCollateral { mint } => {
+            require!(!market.is_paused, ErrorCode::MarketPaused);
+            let already = (0..market.allowed_count as usize).any(|i| market.allowed_collaterals[i] == mint);
+            if !already {
+                require!((market.allowed_count as usize) < MAX_COLLATERALS, ErrorCode::TooManyCollaterals);
+                let idx = market.allowed_count as usize;
+                market.allowed_collaterals[idx] = mint;
+                market.allowed_count += 1;
+                emit!(CollateralAdded { market: market_key, collateral_mint: mint });
+            }
+        }
+        AdminAction::RemoveAllowedCollateral { mint } => {
+            require!(!market.is_paused, ErrorCode::MarketPaused);
+            let i = (0..market.allowed_count as usize)
+                .find(|&i| market.allowed_collaterals[i] == mint)
+                .ok_or(ErrorCode::CollateralNotFound)?;
+            let last = (market.allowed_count - 1) as usize;
+            market.allowed_collaterals[i] = market.allowed_collaterals[last];
+            market.allowed_collaterals[last] = Pubkey::default();
+            market.allowed_count -= 1;
+            emit!(CollateralRemoved { market: market_key, collateral_mint: mint });
+        }
+        AdminAction::SetStrategyOperator { operator } => {
+            if operator == Pubkey::default() {
+                // Revocation needs no acceptance.
+                market.strategy_operator = operator;
+                market.pending_strategy_operator = Pubkey::default();
+                emit!(YieldOperatorSet { market: market_key, operator });
+            } else {
+                market.pending_strategy_operator = operator;
+                emit!(KeyRotationProposed {
+                    market: market_key,
+                    role: KeyRole::StrategyOperator as u8,
+                    proposer: market.authority,
+                    pending: operator,
+                });
+            }
+        }
+        AdminAction::UnpauseMarket => {
+            market.is_paused = false;
+            emit!(MarketUnpaused { market: market_key });
+        }
+        AdminAction::SetSettlementMode { mode, twap_window_secs } => {
+            market.settlement_price_mode = mode as u8;
+            market.twap_window_secs = twap_window_secs;
+            emit!(SettlementModeSet { market: market_key, mode: market.settlement_price_mode, twap_window_secs });
+        }
+        AdminAction::SetPriceGuards { max_staleness_secs, max_deviation_bps } => {
+            market.max_staleness_secs = max_staleness_secs;
+            market.max_deviation_bps = max_deviation_bps;
+            emit!(PriceGuardsSet { market: market_key, max_staleness_secs, max_deviation_bps });
+        }
+        AdminAction::AddOracle { oracle } => {
+            require!(oracle_set.member_index(&oracle).is_none(), ErrorCode::OracleAlreadyRegistered);
+            require!((oracle_set.count as usize) < MAX_ORACLES, ErrorCode::TooManyOracles);
+            let idx = oracle_set.count as usize;
+            oracle_set.members[idx] = oracle;
+            oracle_set.count += 1;
+            oracle_set.submitted_mask = 0; // membership changed: discard the open round
+            emit!(OracleAdded { market: market_key, oracle });
+        }
+        AdminAction::RemoveOracle { oracle } => {
+            let i = oracle_set.member_index(&oracle).ok_or(ErrorCode::OracleNotFound)?;
+            require!(oracle_set.count - 1 >= oracle_set.quorum, ErrorCode::InvalidQuorum);
+            let last = (oracle_set.count - 1) as usize;
+            oracle_set.members[i] = oracle_set.members[last];
+            oracle_set.members[last] = Pubkey::default();
+            oracle_set.count -= 1;
+            oracle_set.submitted_mask = 0;
+            emit!(OracleRemoved { market: market_key, oracle });
+        }
+        AdminAction::SetOracleQuorum { quorum } => {
+            require!(quorum <= oracle_set.count, ErrorCode::InvalidQuorum);
+            oracle_set.quorum = quorum;
+            oracle_set.submitted_mask = 0;
+            emit!(OracleQuorumSet { market: market_key, quorum });
+        }
+        AdminAction::SetTimelockDelay { delay_secs } => {
+            market.timelock_delay_secs = delay_secs;
+            emit!(TimelockDelaySet { market: market_key, delay_secs });
+        }
+        AdminAction::SetAuditor { auditor } => {
+            market.auditor = auditor;
+            emit!(AuditorSet { market: market_key, auditor });
+        }
+        AdminAction::SetBondParams { min_bond, withdraw_delay_secs } => {
+            market.min_warehouse_bond = min_bond;
+            market.bond_withdraw_delay_secs = withdraw_delay_secs;
+            emit!(BondParamsSet { market: market_key, min_bond, withdraw_delay_secs });
+        }
+        AdminAction::SetGradeDifferential { grade, diff_bps } => {
+            let count = market.grade_count as usize;
+            let i = match (0..count).find(|&i| market.grade_codes[i] == grade) {
+                Some(i) => i,
+                None => {
+                    require!(count < MAX_GRADES, ErrorCode::TooManyGrades);
+                    market.grade_codes[count] = grade;
+                    market.grade_count += 1;
+                    count
+                }
+            };
+            market.grade_diff_bps[i] = diff_bps;
+            emit!(GradeDifferentialSet { market: market_key, grade, diff_bps });
+        }
+        AdminAction::RemoveGrade { grade } => {
+            let i = (0..market.grade_count as usize)
+                .find(|&i| market.grade_codes[i] == grade)
+                .ok_or(ErrorCode::GradeNotDeliverable)?;
+            let last = (market.grade_count - 1) as usize;
+            market.grade_codes[i] = market.grade_codes[last];
+            market.grade_diff_bps[i] = market.grade_diff_bps[last];
+            market.grade_codes[last] = 0;
+            market.grade_diff_bps[last] = 0;
+            market.grade_count -= 1;
+            emit!(GradeRemoved { market: market_key, grade });
+        }
+        AdminAction::UpdateMarketParams { params } => {
+            let old = market.params();
+            if params.oracle_authority != old.oracle_authority {
+                // A new oracle key is only nominated; it takes over via accept_key_rotation.
+                market.pending_oracle_authority = params.oracle_authority;
+                emit!(KeyRotationProposed {
+                    market: market_key,
+                    role: KeyRole::OracleAuthority as u8,
+                    proposer: market.authority,
+                    pending: params.oracle_authority,
+                });
+            }
+            market.fee_bps = params.fee_bps;
+            market.base_initial_margin_bps = params.base_initial_margin_bps;
+            market.maintenance_margin_bps = params.maintenance_margin_bps;
+            market.vol_multiplier_bps = params.vol_multiplier_bps;
+            if params.price_exponent != old.price_exponent {
+                // Posted prints, history and deals are all scaled by the current exponent.
+                require!(market.last_price == 0, ErrorCode::PriceExponentLocked);
+                market.price_exponent = params.price_exponent;
+                oracle_set.submitted_mask = 0; // the open round was quoted at the old exponent
+            }
+            emit!(MarketParamsUpdated { market: market_key, old, new: market.params() });
+        }
+    }
+    Ok(())
+}
+
+/// Write the pending action for `action` and bump the market's action counter.
+fn queue_admin_action(accs: &mut ProposeAction, bump: u8, action: AdminAction) -> Result<()> {
+    check_admin_action_params(&action)?;
+    let now = Clock::get()?.unix_timestamp;
+    let market = &mut accs.market;
+    let action_id = market.next_action_id;
+    market.next_action_id = action_id.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+
+    let pa = &mut accs.pending_action;
+    pa.market = market.key();
+    pa.action_id = action_id;
+    pa.action = action;
+    pa.proposer = accs.proposer.key();
+    pa.proposed_ts = now;
+    pa.eta = now.checked_add(market.timelock_delay_secs).ok_or(ErrorCode::MathOverflow)?;
+    pa.status = ActionStatus::Queued as u8;
+    pa.bump = bump;
+
+    emit!(ActionProposed {
+        market: pa.market,
+        action_id,
+        proposer: pa.proposer,
+        eta: pa.eta,
+    });
+    Ok(())
+}
+
+fn validate_market_params(params: &MarketParams) -> Result<()> {
+    require!(params.fee_bps <= MAX_FEE_BPS, ErrorCode::FeeTooHigh);
+    require!(params.base_initial_margin_bps as u64 <= BPS_DENOMINATOR, ErrorCode::InvalidMarginParams);
+    require!(params.maintenance_margin_bps <= params.base_initial_margin_bps, ErrorCode::InvalidMarginParams);
+    require!(
+        (MIN_PRICE_EXPONENT..=0).contains(&params.price_exponent),
+        ErrorCode::InvalidPriceExponent
+    );
+    Ok(())
+}
+
 fn only_strategy_operator(market: &Market, operator: &Signer) -> Result<()> {
     require!(operator.key() == market.strategy_operator, ErrorCode::Unauthorized);
     Ok(())
@@ -1427,7 +5179,30 @@ fn is_allowed_collateral(market: &Market, mint: &Pubkey) -> bool {
     false
 }
 
+/// Median of a non-empty slice (mean of the middle pair for even lengths).
+fn median_u64(values: &mut [u64]) -> u64 {
+    values.sort_unstable();
+    let n = values.len();
+    if n % 2 == 1 {
+        values[n / 2]
+    } else {
+        ((values[n / 2 - 1] as u128 + values[n / 2] as u128) / 2) as u64
+    }
+}
+
 fn pow10_u128(p: u32) -> u128 { (10u128).pow(p) }
+
+/// Re-express a price quoted at `from_exp` in `to_exp` (deals keep the exponent they opened with).
+fn rescale_price(price: u64, from_exp: i32, to_exp: i32) -> Result<u64> {
+    if from_exp >= to_exp {
+        let scaled = (price as u128)
+            .checked_mul(pow10_u128((from_exp - to_exp) as u32))
+            .ok_or(ErrorCode::MathOverflow)?;
+        u64::try_from(scaled).map_err(|_| ErrorCode::MathOverflow.into())
+    } else {
+        Ok((price as u128 / pow10_u128((to_exp - from_exp) as u32)) as u64)
+    }
+}
 fn int_pow10_i128(p: u32) -> i128 { (10i128).pow(p) }
 
 fn notional_at_strike(ds: &DealSnapshot) -> u64 {
@@ -1437,11 +5212,59 @@ fn notional_at_strike(ds: &DealSnapshot) -> u64 {
     n as u64
 }
 
-fn calc_pnl_long(ds: &DealSnapshot, ms: &MarketSnapshot, qty: u64) -> i128 {
-    let strike = ds.strike_price as i128;
-    let final_price = ms.last_price as i128;
+/// Strike notional adjusted by the delivered grade's premium (+) or discount (-).
+fn apply_grade_differential(notional: u64, diff_bps: i16) -> Result<u64> {
+    let adjusted = (notional as i128) * (BPS_DENOMINATOR as i128 + diff_bps as i128) / BPS_DENOMINATOR as i128;
+    u64::try_from(adjusted).map_err(|_| ErrorCode::MathOverflow.into())
+}
+
+/// PnL for the long since the last variation-margin mark (the strike if never marked).
+fn calc_pnl_long(ds: &DealSnapshot, final_price: u64, qty: u64) -> i128 {
+    let reference = ds.last_mark_price as i128;
+    let final_price = final_price as i128;
     let qty_i = qty as i128;
-    (final_price - strike) * qty_i / int_pow10_i128(ds.price_exponent.abs() as u32)
+    (final_price - reference) * qty_i / int_pow10_i128(ds.price_exponent.abs() as u32)
+}
+
+/// Price an expiry settles at: the last print, or the TWAP over the window ending at `settle_ts`.
+fn settlement_price(market: &Market, twap: &TwapAccumulator, settle_ts: i64) -> Result<u64> {
+    if market.settlement_price_mode == SettlementPriceMode::Twap as u8 {
+        let price = twap.twap().ok_or_else(|| error!(ErrorCode::NoSettlementPrice))?;
+        // The last print is carried forward to settle_ts; refuse if it went quiet too long before.
+        if market.max_staleness_secs > 0 {
+            require!(settle_ts - twap.last_ts <= market.max_staleness_secs, ErrorCode::StaleSettlementPrice);
+        }
+        return Ok(price);
+    }
+    require!(market.last_price > 0, ErrorCode::NoSettlementPrice);
+    if market.max_staleness_secs > 0 {
+        require!(
+            (market.last_price_ts - settle_ts).abs() <= market.max_staleness_secs,
+            ErrorCode::StaleSettlementPrice
+        );
+    }
+    Ok(market.last_price)
+}
+
+/// Last finalized price in `price_exponent`, for marking a deal expiring at `settle_ts`. As in
+/// `settlement_price`, the print must be quoted for that expiry and within `max_staleness_secs`
+/// (of now, since the deal is marked before it settles).
+fn fresh_mark_price(market: &Market, settle_ts: i64, price_exponent: i32, now: i64) -> Result<u64> {
+    require!(market.last_price > 0, ErrorCode::NoSettlementPrice);
+    require!(market.settle_ts == settle_ts, ErrorCode::SettlementExpiryMismatch);
+    if market.max_staleness_secs > 0 {
+        require!(now - market.last_price_ts <= market.max_staleness_secs, ErrorCode::StaleSettlementPrice);
+    }
+    rescale_price(market.last_price, market.price_exponent, price_exponent)
+}
+
+/// True if `next` is within `max_bps` of `prev` (always true for the first print or when disabled).
+fn within_deviation_band(prev: u64, next: u64, max_bps: u16) -> bool {
+    if prev == 0 || max_bps == 0 {
+        return true;
+    }
+    let diff = (prev as i128 - next as i128).unsigned_abs();
+    diff.saturating_mul(BPS_DENOMINATOR as u128) <= (prev as u128).saturating_mul(max_bps as u128)
 }
 
 /// Dynamic initial margin requirement:
@@ -1460,53 +5283,350 @@ fn required_initial_margin(ms: &MarketSnapshot, strike_price: u64, qty: u64) ->
     (notional.saturating_mul(total_bps) / (BPS_DENOMINATOR as u128)) as u64
 }
 
+/// Maintenance requirement: strike notional (in the deal's own exponent), without the vol add-on.
+fn required_maintenance_margin(ms: &MarketSnapshot, ds: &DealSnapshot) -> u64 {
+    let notional = notional_at_strike(ds) as u128;
+    (notional.saturating_mul(ms.maintenance_margin_bps as u128) / (BPS_DENOMINATOR as u128)) as u64
+}
+
 // Transfer using PDA signer (generic lifetime to satisfy invariance)
 fn transfer_signed<'info>(
-    token_program: &Program<'info, Token>,
-    from: &Account<'info, TokenAccount>,
-    to: &Account<'info, TokenAccount>,
+    token_program: &Interface<'info, TokenInterface>,
+    from: &InterfaceAccount<'info, TokenAccount>,
+    to: &InterfaceAccount<'info, TokenAccount>,
+    mint: &InterfaceAccount<'info, Mint>,
     vault_auth: &UncheckedAccount<'info>,
     seed_key: &Pubkey,
     vault_bump: u8,
     amount: u64,
 ) -> Result<()> {
-    token::transfer(
+    token::transfer_checked(
         CpiContext::new_with_signer(
             token_program.to_account_info(),
-            Transfer {
+            TransferChecked {
                 from: from.to_account_info(),
+                mint: mint.to_account_info(),
                 to: to.to_account_info(),
                 authority: vault_auth.to_account_info(),
             },
             &[&[b"vault_auth", seed_key.as_ref(), &[vault_bump]]],
         ),
         amount,
+        mint.decimals,
+    )
+}
+
+/// Like `transfer_signed`, for a cross-margin vault (`cm_vault_auth` seeds).
+fn cm_transfer_signed<'info>(
+    token_program: &Interface<'info, TokenInterface>,
+    from: &InterfaceAccount<'info, TokenAccount>,
+    to: &InterfaceAccount<'info, TokenAccount>,
+    mint: &InterfaceAccount<'info, Mint>,
+    cm_vault_auth: &UncheckedAccount<'info>,
+    cross_margin: &Pubkey,
+    vault_bump: u8,
+    amount: u64,
+) -> Result<()> {
+    token::transfer_checked(
+        CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            TransferChecked {
+                from: from.to_account_info(),
+                mint: mint.to_account_info(),
+                to: to.to_account_info(),
+                authority: cm_vault_auth.to_account_info(),
+            },
+            &[&[b"cm_vault_auth", cross_margin.as_ref(), &[vault_bump]]],
+        ),
+        amount,
+        mint.decimals,
     )
 }
 
+/// Cross-margin balance not reserved by resting orders.
+fn cm_free_margin(cm: &CrossMargin, vault: &TokenAccount) -> u64 {
+    vault.amount.saturating_sub(cm.locked)
+}
+
+/// Share of an order's locked margin released by filling `qty` of it (all of it on the last fill).
+fn order_lock_release(o: &Order, qty: u64) -> Result<u64> {
+    if qty >= o.remaining_qty {
+        return Ok(o.locked_margin);
+    }
+    let r = (o.locked_margin as u128)
+        .checked_mul(qty as u128).ok_or(ErrorCode::MathOverflow)?
+        / o.remaining_qty as u128;
+    Ok(r as u64)
+}
+
+/// Receipt transfers use `transfer_checked` so a Token-2022 receipt mint's transfer hook runs.
+/// The hook's extra accounts (meta list, hook program, market, participants) arrive as
+/// remaining accounts; classic SPL receipt mints need none. Mints hooked to this program are
+/// checked against the participants here and moved by burn + re-mint instead.
+fn transfer_receipts<'info>(
+    token_program: &Interface<'info, TokenInterface>,
+    from: AccountInfo<'info>,
+    mint: &InterfaceAccount<'info, Mint>,
+    to: AccountInfo<'info>,
+    authority: AccountInfo<'info>,
+    market: &Account<'info, Market>,
+    receipt_mint_auth: &UncheckedAccount<'info>,
+    hook_accounts: &[AccountInfo<'info>],
+    signer_seeds: &[&[&[u8]]],
+    amount: u64,
+) -> Result<()> {
+    // A transfer of a mint hooked to this program would re-enter it from Token-2022, which the
+    // runtime rejects. Apply the hook's allowlist check here and move the units by burn + re-mint.
+    if mint_transfer_hook_program(&mint.to_account_info())? == Some(crate::ID) {
+        let market_key = market.key();
+        for token_account in [&from, &to] {
+            let owner = Pubkey::try_from(&token_account.try_borrow_data()?[32..64]).map_err(|_| ErrorCode::ConstraintMismatch)?;
+            let (participant, _) = Pubkey::find_program_address(&[b"participant", market_key.as_ref(), owner.as_ref()], &crate::ID);
+            let info = hook_accounts.iter().find(|a| a.key == &participant).ok_or(ErrorCode::NotParticipant)?;
+            check_participant(info, &market_key, &owner)?;
+        }
+        token::burn(
+            CpiContext::new_with_signer(
+                token_program.to_account_info(),
+                Burn { mint: mint.to_account_info(), from, authority },
+                signer_seeds,
+            ),
+            amount,
+        )?;
+        token::mint_to(
+            CpiContext::new_with_signer(
+                token_program.to_account_info(),
+                MintTo { mint: mint.to_account_info(), to, authority: receipt_mint_auth.to_account_info() },
+                &[&[b"receipt_auth", market_key.as_ref(), &[market.receipt_auth_bump]]],
+            ),
+            amount,
+        )?;
+        return Ok(());
+    }
+    invoke_transfer_checked(
+        &token_program.key(),
+        from,
+        mint.to_account_info(),
+        to,
+        authority,
+        hook_accounts,
+        amount,
+        mint.decimals,
+        signer_seeds,
+    )?;
+    Ok(())
+}
+
+/// Transfer-hook program configured on a Token-2022 mint, if any.
+fn mint_transfer_hook_program(mint: &AccountInfo) -> Result<Option<Pubkey>> {
+    if *mint.owner != spl_token_2022::ID {
+        return Ok(None);
+    }
+    let data = mint.try_borrow_data()?;
+    let state = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&data)?;
+    Ok(state.get_extension::<TransferHook>().ok().and_then(|hook| Option::<Pubkey>::from(hook.program_id)))
+}
+
+/// Extra accounts for `transfer_hook`, in order after the extra-account-metas list (index 4):
+/// market (5), source owner's Participant (6), destination owner's Participant (7). Owners are read
+/// from bytes 32..64 of the source (0) and destination (2) token accounts.
+fn receipt_hook_metas(market: &Pubkey) -> Result<[ExtraAccountMeta; 3]> {
+    let participant = |token_account_index: u8| {
+        ExtraAccountMeta::new_with_seeds(
+            &[
+                Seed::Literal { bytes: b"participant".to_vec() },
+                Seed::AccountKey { index: 5 },
+                Seed::AccountData { account_index: token_account_index, data_index: 32, length: 32 },
+            ],
+            false,
+            false,
+        )
+    };
+    Ok([ExtraAccountMeta::new_with_pubkey(market, false, false)?, participant(0)?, participant(2)?])
+}
+
+/// `info` must be an active `Participant` of `market` for `owner`.
+fn check_participant(info: &AccountInfo, market: &Pubkey, owner: &Pubkey) -> Result<()> {
+    require!(info.owner == &crate::ID && !info.data_is_empty(), ErrorCode::NotParticipant);
+    let data = info.try_borrow_data()?;
+    let p = Participant::try_deserialize(&mut &data[..])?;
+    require!(p.market == *market && p.owner == *owner && p.is_active, ErrorCode::NotParticipant);
+    Ok(())
+}
+
+/// Sorted-pair Merkle verification (no left/right flags needed in the proof).
+fn verify_merkle_proof(leaf: [u8; 32], proof: &[[u8; 32]], root: [u8; 32]) -> bool {
+    let mut node = leaf;
+    for sibling in proof {
+        node = if node <= *sibling {
+            hashv(&[&node, sibling]).to_bytes()
+        } else {
+            hashv(&[sibling, &node]).to_bytes()
+        };
+    }
+    node == root
+}
+
+/// Storage on `amount` receipt base units at `rate` per whole unit per day over [from_ts, to_ts).
+fn storage_fee_for(amount: u64, rate: u64, from_ts: i64, to_ts: i64, receipt_decimals: u8) -> Result<u64> {
+    if rate == 0 || to_ts <= from_ts {
+        return Ok(0);
+    }
+    let fee = (amount as u128)
+        .checked_mul(rate as u128)
+        .and_then(|v| v.checked_mul((to_ts - from_ts) as u128))
+        .ok_or(ErrorCode::MathOverflow)?
+        / (SECONDS_PER_DAY as u128 * pow10_u128(receipt_decimals as u32));
+    u64::try_from(fee).map_err(|_| ErrorCode::MathOverflow.into())
+}
+
+/// The delivering short pays storage accrued since intake on `qty`, out of its proceeds. The
+/// payment is earned by the warehouse and credited to the lot so the eventual redeemer isn't
+/// charged for the same days again. Deals margined in another collateral pay nothing here; the
+/// storage stays owed by the redeemer, in the market's quote mint.
+fn charge_delivery_storage(accs: &mut SettlePhysical, ds: &DealSnapshot, qty: u64, pay_amount: u64) -> Result<u64> {
+    if accs.quote_mint.key() != accs.market.quote_mint {
+        return Ok(0);
+    }
+    let now = Clock::get()?.unix_timestamp;
+    let accrued = storage_fee_for(qty, accs.warehouse.storage_rate_at(now), accs.lot.storage_start_ts(), now, accs.receipt_mint.decimals)?;
+    let fee = accrued.min(pay_amount);
+    if fee == 0 {
+        return Ok(0);
+    }
+    transfer_signed(
+        &accs.token_program,
+        &accs.long_margin_vault,
+        &accs.storage_vault,
+        &accs.quote_mint,
+        &accs.vault_auth,
+        &ds.deal,
+        ds.vault_bump,
+        fee,
+    )?;
+    let w = &mut accs.warehouse;
+    w.storage_fees_accrued = w.storage_fees_accrued.checked_add(fee).ok_or(ErrorCode::MathOverflow)?;
+    let l = &mut accs.lot;
+    l.storage_credit = l.storage_credit.checked_add(fee).ok_or(ErrorCode::MathOverflow)?;
+    Ok(fee)
+}
+
+/// Send an open redemption's escrow back to its holder and close it with `status`.
+fn return_redemption_escrow<'info>(
+    accs: &mut CloseRedemption<'info>,
+    hook_accounts: &[AccountInfo<'info>],
+    status: RedemptionStatus,
+) -> Result<()> {
+    let r = &mut accs.redemption;
+    require!(r.status == RedemptionStatus::Requested as u8, ErrorCode::RedemptionNotOpen);
+    require!(accs.escrow_participant.is_some() == r.has_escrow_participant, ErrorCode::ConstraintMismatch);
+    let redemption_key = r.key();
+    transfer_receipts(
+        &accs.receipt_token_program,
+        accs.redemption_vault.to_account_info(),
+        &accs.receipt_mint,
+        accs.holder_receipt_ata.to_account_info(),
+        accs.redemption_auth.to_account_info(),
+        &accs.market,
+        &accs.receipt_mint_auth,
+        hook_accounts,
+        &[&[b"redemption_auth", redemption_key.as_ref(), &[r.auth_bump]]],
+        r.amount,
+    )?;
+    if r.storage_fee > 0 {
+        let warehouse_key = accs.warehouse.key();
+        token::transfer_checked(
+            CpiContext::new_with_signer(
+                accs.token_program.to_account_info(),
+                TransferChecked {
+                    from: accs.storage_vault.to_account_info(),
+                    mint: accs.quote_mint.to_account_info(),
+                    to: accs.holder_quote_ata.to_account_info(),
+                    authority: accs.storage_auth.to_account_info(),
+                },
+                &[&[b"storage_auth", warehouse_key.as_ref(), &[accs.warehouse.storage_auth_bump]]],
+            ),
+            r.storage_fee,
+            accs.quote_mint.decimals,
+        )?;
+    }
+    let l = &mut accs.lot;
+    l.storage_credit = l.storage_credit.checked_add(r.storage_credit_used).ok_or(ErrorCode::MathOverflow)?;
+    r.status = status as u8;
+    r.closed_ts = Clock::get()?.unix_timestamp;
+    emit!(RedemptionCancelled {
+        warehouse: r.warehouse,
+        redemption: redemption_key,
+        holder: r.holder,
+        amount: r.amount,
+        expired: status == RedemptionStatus::Expired,
+    });
+    Ok(())
+}
+
+/// Move the PnL since `deal.last_mark_price` from the losing side's margin vault to the winner's
+/// and advance the mark. Fails if the loser's recorded margin can't cover it.
+fn post_variation_margin<'info>(
+    token_program: &Interface<'info, TokenInterface>,
+    quote_mint: &InterfaceAccount<'info, Mint>,
+    long_margin_vault: &InterfaceAccount<'info, TokenAccount>,
+    short_margin_vault: &InterfaceAccount<'info, TokenAccount>,
+    vault_auth: &UncheckedAccount<'info>,
+    deal: &mut Deal,
+    ds: &DealSnapshot,
+    mark_price: u64,
+) -> Result<()> {
+    let pnl_long = calc_pnl_long(ds, mark_price, ds.qty_receipt_amount);
+    if pnl_long > 0 {
+        let amount = u64::try_from(pnl_long).map_err(|_| ErrorCode::MathOverflow)?;
+        require!(deal.short_margin >= amount, ErrorCode::InsufficientVariationMargin);
+        transfer_signed(token_program, short_margin_vault, long_margin_vault, quote_mint, vault_auth, &ds.deal, ds.vault_bump, amount)?;
+        deal.short_margin -= amount;
+        deal.long_margin = deal.long_margin.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+    } else if pnl_long < 0 {
+        let amount = u64::try_from(-pnl_long).map_err(|_| ErrorCode::MathOverflow)?;
+        require!(deal.long_margin >= amount, ErrorCode::InsufficientVariationMargin);
+        transfer_signed(token_program, long_margin_vault, short_margin_vault, quote_mint, vault_auth, &ds.deal, ds.vault_bump, amount)?;
+        deal.long_margin -= amount;
+        deal.short_margin = deal.short_margin.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+    }
+
+    let prev_mark_price = deal.last_mark_price;
+    deal.last_mark_price = mark_price;
+    emit!(VariationMarginPosted {
+        deal: ds.deal,
+        prev_mark_price,
+        mark_price,
+        pnl_long,
+    });
+    Ok(())
+}
+
 // Return remaining funds from a vault to its party after settlement
 fn payout_leftovers_after_settlement<'info>(
-    token_program: &Program<'info, Token>,
-    vault: &Account<'info, TokenAccount>,
-    recipient: &Account<'info, TokenAccount>,
+    token_program: &Interface<'info, TokenInterface>,
+    quote_mint: &InterfaceAccount<'info, Mint>,
+    vault: &InterfaceAccount<'info, TokenAccount>,
+    recipient: &InterfaceAccount<'info, TokenAccount>,
     vault_auth: &UncheckedAccount<'info>,
     ds: &DealSnapshot,
 ) -> Result<()> {
     let amt = vault.amount;
     if amt > 0 {
-        transfer_signed(token_program, vault, recipient, vault_auth, &ds.deal, ds.vault_bump, amt)?;
+        transfer_signed(token_program, vault, recipient, quote_mint, vault_auth, &ds.deal, ds.vault_bump, amt)?;
     }
     Ok(())
 }
 
 // Cash settlement internal: moves PnL + fees, then returns leftovers (no &Context borrow).
 fn settle_cash_inner<'info>(
-    token_program: &Program<'info, Token>,
-    short_margin_vault: &Account<'info, TokenAccount>,
-    long_margin_vault: &Account<'info, TokenAccount>,
-    long_receive_quote_ata: &Account<'info, TokenAccount>,
-    short_receive_quote_ata: &Account<'info, TokenAccount>,
-    fee_vault: &Account<'info, TokenAccount>,
+    token_program: &Interface<'info, TokenInterface>,
+    quote_mint: &InterfaceAccount<'info, Mint>,
+    short_margin_vault: &InterfaceAccount<'info, TokenAccount>,
+    long_margin_vault: &InterfaceAccount<'info, TokenAccount>,
+    long_receive_quote_ata: &InterfaceAccount<'info, TokenAccount>,
+    short_receive_quote_ata: &InterfaceAccount<'info, TokenAccount>,
+    fee_vault: &InterfaceAccount<'info, TokenAccount>,
     vault_auth: &UncheckedAccount<'info>,
     ds: &DealSnapshot,
     pnl_long: i128,
@@ -1519,6 +5639,7 @@ fn settle_cash_inner<'info>(
             token_program,
             short_margin_vault,
             long_receive_quote_ata,
+            quote_mint,
             vault_auth,
             &ds.deal,
             ds.vault_bump,
@@ -1529,6 +5650,7 @@ fn settle_cash_inner<'info>(
                 token_program,
                 short_margin_vault,
                 fee_vault,
+                quote_mint,
                 vault_auth,
                 &ds.deal,
                 ds.vault_bump,
@@ -1543,6 +5665,7 @@ fn settle_cash_inner<'info>(
             token_program,
             long_margin_vault,
             short_receive_quote_ata,
+            quote_mint,
             vault_auth,
             &ds.deal,
             ds.vault_bump,
@@ -1553,6 +5676,7 @@ fn settle_cash_inner<'info>(
                 token_program,
                 long_margin_vault,
                 fee_vault,
+                quote_mint,
                 vault_auth,
                 &ds.deal,
                 ds.vault_bump,
@@ -1563,6 +5687,7 @@ fn settle_cash_inner<'info>(
 
     payout_leftovers_after_settlement(
         token_program,
+        quote_mint,
         long_margin_vault,
         long_receive_quote_ata,
         vault_auth,
@@ -1570,6 +5695,7 @@ fn settle_cash_inner<'info>(
     )?;
     payout_leftovers_after_settlement(
         token_program,
+        quote_mint,
         short_margin_vault,
         short_receive_quote_ata,
         vault_auth,
@@ -1601,6 +5727,135 @@ pub enum ErrorCode {
     #[msg("Deal version mismatch")] DealVersionMismatch,
     #[msg("Deal is frozen")] DealFrozen,
     #[msg("Invalid partial amount")] InvalidPartialAmount,
+    #[msg("Deal is above maintenance margin")] NotLiquidatable,
+    #[msg("Insufficient margin to pay variation margin; liquidate instead")] InsufficientVariationMargin,
+    #[msg("TWAP window must be positive")] InvalidTwapWindow,
+    #[msg("Oracle already registered")] OracleAlreadyRegistered,
+    #[msg("Too many oracles")] TooManyOracles,
+    #[msg("Oracle not found")] OracleNotFound,
+    #[msg("Quorum must be between 1 and the number of oracles")] InvalidQuorum,
+    #[msg("Submission does not match the open oracle round")] OracleRoundMismatch,
+    #[msg("Oracle already submitted in this round")] DuplicateOracleSubmission,
+    #[msg("Invalid price guard parameter")] InvalidPriceGuard,
+    #[msg("Price moved beyond the deviation band")] PriceDeviationTooLarge,
+    #[msg("Settlement price is too far from the deal's settle_ts")] StaleSettlementPrice,
+    #[msg("Latest price was not quoted for this expiry")] SettlementExpiryMismatch,
+    #[msg("Action is not queued")] ActionNotQueued,
+    #[msg("Timelock delay has not elapsed")] TimelockNotElapsed,
+    #[msg("Invalid timelock delay")] InvalidTimelockDelay,
+    #[msg("Maintenance margin must not exceed initial margin")] InvalidMarginParams,
+    #[msg("Price exponent out of range")] InvalidPriceExponent,
+    #[msg("Price exponent does not match the market")] PriceExponentMismatch,
+    #[msg("Price exponent cannot change once a price has been posted")] PriceExponentLocked,
+    #[msg("No key rotation pending for this role")] NoPendingRotation,
+    #[msg("Invalid council membership or threshold")] InvalidCouncil,
+    #[msg("Signer is not a council member")] NotCouncilMember,
+    #[msg("An approved council proposal is required")] CouncilApprovalRequired,
+    #[msg("Council proposal does not authorize this action")] CouncilActionMismatch,
+    #[msg("Council approval threshold not reached")] ThresholdNotReached,
+    #[msg("Council proposal already executed")] ProposalAlreadyExecuted,
+    #[msg("Council membership changed since this proposal was created")] StaleProposal,
+    #[msg("Member already approved this proposal")] AlreadyApproved,
+    #[msg("Outstanding receipts would exceed attested inventory")] ExceedsAttestedInventory,
+    #[msg("Amount exceeds the warehouse's outstanding receipts")] ExceedsOutstandingReceipts,
+    #[msg("Invalid lot metadata")] InvalidLotMetadata,
+    #[msg("Outstanding receipts would exceed the warehouse's mint quota")] ExceedsMintQuota,
+    #[msg("Warehouse is not certified")] WarehouseNotCertified,
+    #[msg("Warehouse certification has expired")] CertificationExpired,
+    #[msg("Certification expiry must be in the future")] InvalidCertificationExpiry,
+    #[msg("Redemption is not open")] RedemptionNotOpen,
+    #[msg("Redemption has not timed out yet")] RedemptionNotExpired,
+    #[msg("Snapshot time must not be in the future or older than the last snapshot")] InvalidSnapshotTime,
+    #[msg("Merkle proof does not match the reserve snapshot")] InvalidMerkleProof,
+    #[msg("Warehouse bond is below the market minimum")] BondBelowMinimum,
+    #[msg("Amount exceeds the warehouse bond")] InsufficientBond,
+    #[msg("No bond withdrawal pending")] NoPendingBondWithdrawal,
+    #[msg("Receipt holder is not an active market participant")] NotParticipant,
+    #[msg("Transfer hook called outside a token transfer")] NotTransferring,
+    #[msg("Grade differential out of range")] InvalidGradeDifferential,
+    #[msg("Too many grades in the delivery schedule")] TooManyGrades,
+    #[msg("Grade is not in the market's delivery schedule")] GradeNotDeliverable,
+    #[msg("Offer is not open")] OfferNotOpen,
+    #[msg("Offer has expired")] OfferExpired,
+    #[msg("Offer expiry must be in the future and no later than settle_ts")] InvalidOfferExpiry,
+    #[msg("Cross-margin balance is reserved by resting orders")] InsufficientFreeMargin,
+    #[msg("Order is not open")] OrderNotOpen,
+    #[msg("Bid is below ask")] OrdersDoNotCross,
+    #[msg("Bid and ask belong to the same owner")] SelfTrade,
+    #[msg("Order is not the best-priced, oldest open order on its side")] NotBestOrder,
+    #[msg("Order book side is full")] OrderBookFull,
+    #[msg("Storage rate exceeds the maximum")] StorageRateTooHigh,
+    #[msg("Settlement price is already final")] SettlementPriceAlreadyFinal,
+    #[msg("Slash exceeds the value of the redeemed receipts")] SlashExceedsRedemptionValue,
+    #[msg("Outgoing side is at a loss against the current price")] NovationAtLoss,
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Adding oracles is timelocked, so multi-oracle rounds are exercised here rather than on-chain.
+    fn oracle_set(count: u8, quorum: u8) -> OracleSet {
+        let mut members = [Pubkey::default(); MAX_ORACLES];
+        for m in members.iter_mut().take(count as usize) {
+            *m = Pubkey::new_unique();
+        }
+        OracleSet {
+            market: Pubkey::new_unique(),
+            members,
+            count,
+            quorum,
+            round_id: 0,
+            round_open_ts: 0,
+            round_exponent: 0,
+            round_settle_ts: 0,
+            submitted_mask: 0,
+            prices: [0; MAX_ORACLES],
+            vols: [0; MAX_ORACLES],
+            bump: 0,
+            displaced_mask: 0,
+        }
+    }
+
+    #[test]
+    fn round_finalizes_to_median_at_quorum() {
+        let mut set = oracle_set(3, 3);
+        set.submit(0, 100, -6, 1_000, 100, 500).unwrap();
+        set.submit(1, 101, -6, 1_000, 130, 700).unwrap();
+        assert!(!set.has_quorum());
+        assert!(set.submit(1, 102, -6, 1_000, 120, 700).is_err()); // one submission per round
+        set.submit(2, 102, -6, 1_000, 110, 600).unwrap();
+        assert!(set.has_quorum());
+        assert_eq!(set.round_median(), (110, 600));
+
+        // an outlier can't drag a 2-of-3 round off the median
+        let mut set = oracle_set(3, 2);
+        set.submit(0, 100, -6, 1_000, 100, 500).unwrap();
+        assert!(!set.has_quorum());
+        set.submit(2, 101, -6, 1_000, 104, 500).unwrap();
+        assert!(set.has_quorum());
+        assert_eq!(set.round_median(), (102, 500));
+    }
 
+    #[test]
+    fn conflicting_opener_is_displaced_once() {
+        let mut set = oracle_set(3, 2);
+        // member 0 opens with a bad settle_ts; member 1 takes the round over
+        set.submit(0, 100, -6, 9_999, 100, 0).unwrap();
+        set.submit(1, 101, -6, 1_000, 100, 0).unwrap();
+        assert_eq!((set.round_settle_ts, set.submitted_mask), (1_000, 0b010));
+        // the displaced opener can't take it back...
+        assert!(set.submit(0, 102, -6, 9_999, 100, 0).is_err());
+        // ...and a round with two backers can't be taken over at all
+        set.submit(2, 103, -6, 1_000, 102, 0).unwrap();
+        assert!(set.has_quorum());
+        assert_eq!(set.round_median(), (101, 0));
+
+        // a fresh round (after finalization or timeout) forgets who was displaced
+        set.submitted_mask = 0;
+        set.submit(0, 200, -6, 2_000, 100, 0).unwrap();
+        assert_eq!(set.displaced_mask, 0);
+        set.submit(1, 200 + ORACLE_ROUND_TIMEOUT_SECS + 1, -6, 3_000, 100, 0).unwrap();
+        assert_eq!((set.round_settle_ts, set.submitted_mask, set.displaced_mask), (3_000, 0b010, 0));
+    }
+}
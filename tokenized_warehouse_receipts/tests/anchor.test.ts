@@ -3,13 +3,30 @@
 // Key changes covered:
 // - init_market(authority, fee_bps, oracle_authority, governance_authority,
 //   base_initial_margin_bps, maintenance_margin_bps, vol_multiplier_bps)
-// - post_price(price:u64, exponent:i32, settle_ts:i64, vol_bps:u16) with accounts {market, poster}
+// - post_price(price:u64, exponent:i32, settle_ts:i64, vol_bps:u16) with accounts {market, priceHistory, oracleSet, poster}
 // - open_deal(..., deal_version:u8, ...) requires DEAL_VERSION=1 and allowed collateral check
 // - dynamic initial margin (required_initial_margin) enforced
 // - deposit_margin(side enum object)
-// - settle_cash / settle_physical / settle_partial_physical
+// - finalize_settlement_price(settle_ts) → settle_cash / settle_physical / settle_partial_physical
 // - cross-margin (cm_create, cm_deposit, cm_withdraw, cm_move_to_deal, cm_move_from_deal)
 // - freeze/unfreeze guards (light touch via happy-path usage)
+// - liquidate_deal against maintenance margin
+// - two-step deal offers (propose_deal / accept_deal / cancel_offer)
+// - order book per expiry (init_order_book / place_order / cancel_order / match_orders) on cross-margin
+// - novate_deal (outgoing and incoming holder co-sign)
+// - terminate_deal at an agreed close price (both parties sign)
+// - governance timelock (propose_action / execute_action / cancel_action, update_market_params)
+// - two-step key rotation (propose_key_rotation / accept_key_rotation)
+// - init_warehouse applies; approve_warehouse / revoke_warehouse certify with an expiry
+// - attest_inventory caps mint_receipt at audited stock; each mint records a ReceiptLot
+// - redemption workflow (request_redemption / cancel_redemption / confirm_release)
+// - storage fees (set_storage_rate, escrow at redemption, deduction at physical delivery, collect_storage_fees)
+// - proof of reserve (post_reserve_snapshot / verify_lot_inclusion)
+// - warehouse performance bond (post_bond / request_bond_withdrawal / withdraw_bond / slash_warehouse)
+// - grade premium/discount schedule for physical delivery (SetGradeDifferential / RemoveGrade)
+// - Token-2022 receipt mints: transfer hook checks the Participant allowlist (set_participant)
+//   and the program's own receipt moves (mint_receipt, redemption escrow, settle_physical) apply it too
+// - M-of-N governance council (init_council / council_propose / council_approve) gating freeze_deal
 //
 // Assumes globals: web3, anchor, pg, BN, assert
 // Tries both `splToken` and `spl` for SPL helpers.
@@ -47,7 +64,14 @@ describe("tokenized_warehouse_receipts ()", () => {
 
   // PDAs
   let marketPda: web3.PublicKey;
+  let priceHistoryPda: web3.PublicKey;
+  let oracleSetPda: web3.PublicKey;
   let warehousePda: web3.PublicKey;
+  let storageAuthPda: web3.PublicKey;
+  let storageVault: web3.PublicKey;
+  let bondAuthPda: web3.PublicKey;
+  let bondVault: web3.PublicKey;
+  let lot0Pda: web3.PublicKey; // first lot minted by the warehouse
   let receiptMintAuthPda: web3.PublicKey;
   let dealPda: web3.PublicKey;
   let vaultAuthPda: web3.PublicKey;
@@ -60,6 +84,10 @@ describe("tokenized_warehouse_receipts ()", () => {
   let cmVaultAuthPda: web3.PublicKey;
   let cmVaultAta: web3.PublicKey;
 
+  // Token-2022 receipt mint hooked to this program, and its allowlisted market
+  let hookMint: web3.Keypair;
+  let hookMarket: web3.PublicKey;
+
   // constants
   const DECIMALS = 6;
   const PRICE_EXPONENT = -6;
@@ -67,9 +95,9 @@ describe("tokenized_warehouse_receipts ()", () => {
   // market params (new)
   const FEE_BPS = 50; // <= 1000
   const BASE_IM_BPS = 500; // 5% base initial margin
-  const MAINT_BPS = 300; // 3% maintenance (not enforced in tests but set)
+  const MAINT_BPS = 300; // 3% maintenance (checked by liquidate_deal)
   const VOL_MULT_BPS = 200; // scales vol → extra margin
-  const ORACLE = () => wallet.publicKey; // poster == oracle or authority or governance
+  const ORACLE = () => wallet.publicKey; // sole member of the oracle set (quorum 1)
   const GOVERNANCE = () => wallet.publicKey;
 
   // helpers
@@ -87,6 +115,13 @@ describe("tokenized_warehouse_receipts ()", () => {
   async function sleep(ms: number) {
     await new Promise((r) => setTimeout(r, ms));
   }
+  // per-expiry TWAP accumulator, fed by post_price and read by finalize_settlement_price
+  function twapPda(market: web3.PublicKey, settleTs: any): web3.PublicKey {
+    return web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("twap"), market.toBuffer(), new BN(settleTs).toArrayLike(Buffer, "le", 8)],
+      program.programId
+    )[0];
+  }
 
   // mirrors Rust required_initial_margin for tests
   function pow10u128(p: number): BN {
@@ -140,6 +175,14 @@ describe("tokenized_warehouse_receipts ()", () => {
       ],
       program.programId
     );
+    [priceHistoryPda] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("price_history"), marketPda.toBuffer()],
+      program.programId
+    );
+    [oracleSetPda] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("oracle_set"), marketPda.toBuffer()],
+      program.programId
+    );
 
     // Warehouse PDAs
     [warehousePda] = web3.PublicKey.findProgramAddressSync(
@@ -151,7 +194,21 @@ describe("tokenized_warehouse_receipts ()", () => {
       program.programId
     );
     [receiptMintAuthPda] = web3.PublicKey.findProgramAddressSync(
-      [Buffer.from("receipt_auth"), warehousePda.toBuffer()],
+      [Buffer.from("receipt_auth"), marketPda.toBuffer()], // shared by all warehouses of the market
+      program.programId
+    );
+    [storageAuthPda] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("storage_auth"), warehousePda.toBuffer()],
+      program.programId
+    );
+    storageVault = spl.getAssociatedTokenAddressSync(quoteMint, storageAuthPda, true);
+    [bondAuthPda] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("bond_auth"), warehousePda.toBuffer()],
+      program.programId
+    );
+    bondVault = spl.getAssociatedTokenAddressSync(quoteMint, bondAuthPda, true);
+    [lot0Pda] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("lot"), warehousePda.toBuffer(), new BN(0).toArrayLike(Buffer, "le", 8)],
       program.programId
     );
 
@@ -207,6 +264,8 @@ describe("tokenized_warehouse_receipts ()", () => {
         quoteMint,
         receiptMint,
         market: marketPda,
+        priceHistory: priceHistoryPda,
+        oracleSet: oracleSetPda,
         systemProgram: web3.SystemProgram.programId,
       })
       .rpc();
@@ -217,6 +276,11 @@ describe("tokenized_warehouse_receipts ()", () => {
     assert.equal(m.baseInitialMarginBps, BASE_IM_BPS);
     assert.equal(m.volMultiplierBps, VOL_MULT_BPS);
     assert.equal(new web3.PublicKey(m.authority).toBase58(), wallet.publicKey.toBase58());
+
+    const os = await program.account.oracleSet.fetch(oracleSetPda);
+    assert.equal(os.count, 1);
+    assert.equal(os.quorum, 1);
+    assert.equal(new web3.PublicKey(os.members[0]).toBase58(), ORACLE().toBase58());
   });
 
   it("post_price (with vol_bps)", async () => {
@@ -228,7 +292,11 @@ describe("tokenized_warehouse_receipts ()", () => {
       .postPrice(priceBN, PRICE_EXPONENT, new BN(settleTs), volBps)
       .accounts({
         market: marketPda,
+        priceHistory: priceHistoryPda,
+        oracleSet: oracleSetPda,
+        twap: twapPda(marketPda, settleTs),
         poster: wallet.publicKey,
+        systemProgram: web3.SystemProgram.programId,
       })
       .rpc();
     await connection.confirmTransaction(tx, "confirmed");
@@ -237,27 +305,364 @@ describe("tokenized_warehouse_receipts ()", () => {
     assert.equal(m.lastPrice.toString(), priceBN.toString());
     assert.equal(m.priceExponent, PRICE_EXPONENT);
     assert.equal(m.lastVolBps, volBps);
+
+    // print is appended to the ring buffer
+    const h = await program.account.priceHistory.fetch(priceHistoryPda);
+    assert.equal(h.count, 1);
+    assert.equal(h.entries[0].price.toString(), priceBN.toString());
+
+    // and folded into the expiry's TWAP accumulator; the window is still ahead, so it opens it
+    const tw = await program.account.twapAccumulator.fetch(twapPda(marketPda, settleTs));
+    assert.equal(tw.lastPrice.toString(), priceBN.toString());
+    assert.equal(Number(tw.secs), 0);
+  });
+
+  it("price guards: deviating round rejected, one-shot override consumed, stale settlement price rejected", async () => {
+    // fresh market so the prints below don't disturb the shared one
+    const gReceiptMint = await spl.createMint(connection, mintAuthority, mintAuthority.publicKey, null, DECIMALS);
+    const [gMarket] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("market"), wallet.publicKey.toBuffer(), gReceiptMint.toBuffer(), quoteMint.toBuffer()],
+      program.programId
+    );
+    const pda = (tag: string, key: web3.PublicKey) =>
+      web3.PublicKey.findProgramAddressSync([Buffer.from(tag), key.toBuffer()], program.programId)[0];
+    let tx = await program.methods
+      .initMarket(FEE_BPS, ORACLE(), GOVERNANCE(), BASE_IM_BPS, MAINT_BPS, VOL_MULT_BPS)
+      .accounts({
+        authority: wallet.publicKey,
+        quoteMint,
+        receiptMint: gReceiptMint,
+        market: gMarket,
+        priceHistory: pda("price_history", gMarket),
+        oracleSet: pda("oracle_set", gMarket),
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .rpc();
+    await connection.confirmTransaction(tx, "confirmed");
+
+    // quoted for an expiry that passed more than max_staleness_secs (1h) before the print
+    const settleTs = new BN(Math.floor(Date.now() / 1000) - 4000);
+    const post = (price: number) =>
+      program.methods
+        .postPrice(toUnitsBN(price), PRICE_EXPONENT, settleTs, 0)
+        .accounts({
+          market: gMarket,
+          priceHistory: pda("price_history", gMarket),
+          oracleSet: pda("oracle_set", gMarket),
+          twap: twapPda(gMarket, settleTs),
+          poster: wallet.publicKey,
+          systemProgram: web3.SystemProgram.programId,
+        })
+        .rpc();
+
+    tx = await post(100);
+    await connection.confirmTransaction(tx, "confirmed");
+
+    // +50% is outside the 20% band
+    let posted = true;
+    try {
+      await post(150);
+    } catch (e) {
+      posted = false;
+    }
+    assert.equal(posted, false);
+    assert.equal((await program.account.market.fetch(gMarket)).lastPrice.toString(), toUnitsBN(100).toString());
+
+    // governance arms the override: the next jump goes through and disarms it
+    tx = await program.methods
+      .armDeviationOverride()
+      .accounts({ signer: wallet.publicKey, market: gMarket, council: null, councilProposal: null })
+      .rpc();
+    await connection.confirmTransaction(tx, "confirmed");
+    assert.equal((await program.account.market.fetch(gMarket)).deviationOverrideArmed, true);
+
+    tx = await post(150);
+    await connection.confirmTransaction(tx, "confirmed");
+    let m = await program.account.market.fetch(gMarket);
+    assert.equal(m.lastPrice.toString(), toUnitsBN(150).toString());
+    assert.equal(m.deviationOverrideArmed, false);
+
+    // one-shot: the next jump is rejected again
+    posted = true;
+    try {
+      await post(250);
+    } catch (e) {
+      posted = false;
+    }
+    assert.equal(posted, false);
+
+    // the last print is ~4000s away from settle_ts: finalize refuses it
+    const [settlementPricePda] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("settlement_price"), gMarket.toBuffer(), settleTs.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    let finalized = true;
+    try {
+      await program.methods
+        .finalizeSettlementPrice(settleTs)
+        .accounts({
+          oracle: wallet.publicKey,
+          market: gMarket,
+          twap: twapPda(gMarket, settleTs),
+          oracleSet: pda("oracle_set", gMarket),
+          settlementPrice: settlementPricePda,
+          systemProgram: web3.SystemProgram.programId,
+        })
+        .rpc();
+    } catch (e) {
+      finalized = false;
+      assert.include(String(e), "StaleSettlementPrice");
+    }
+    assert.equal(finalized, false);
+  });
+
+  it("timelock: propose_action → execute before eta rejected → cancel_action", async () => {
+    const m0 = await program.account.market.fetch(marketPda);
+    const actionId = new BN(m0.nextActionId);
+    const [pendingPda] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("pending_action"), marketPda.toBuffer(), actionId.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    const extraCollateral = web3.Keypair.generate().publicKey;
+
+    let tx = await program.methods
+      .proposeAction({ addAllowedCollateral: { mint: extraCollateral } })
+      .accounts({
+        proposer: wallet.publicKey,
+        market: marketPda,
+        pendingAction: pendingPda,
+        council: null,
+        councilProposal: null,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .rpc();
+    await connection.confirmTransaction(tx, "confirmed");
+
+    const pa = await program.account.pendingAction.fetch(pendingPda);
+    assert.equal(pa.status, 0); // queued
+    assert.equal(Number(pa.eta) - Number(pa.proposedTs), Number(m0.timelockDelaySecs));
+
+    let executed = true;
+    try {
+      await program.methods
+        .executeAction()
+        .accounts({
+          signer: wallet.publicKey,
+          market: marketPda,
+          oracleSet: oracleSetPda,
+          pendingAction: pendingPda,
+          council: null,
+          councilProposal: null,
+        })
+        .rpc();
+    } catch (e) {
+      executed = false;
+    }
+    assert.equal(executed, false);
+
+    tx = await program.methods
+      .cancelAction()
+      .accounts({ signer: wallet.publicKey, market: marketPda, pendingAction: pendingPda, council: null, councilProposal: null })
+      .rpc();
+    await connection.confirmTransaction(tx, "confirmed");
+
+    const after = await program.account.pendingAction.fetch(pendingPda);
+    assert.equal(after.status, 2); // cancelled
+    const m1 = await program.account.market.fetch(marketPda);
+    assert.equal(m1.allowedCount, m0.allowedCount);
+  });
+
+  it("key rotation: propose_key_rotation → stranger rejected → nominee accepts → rotate back", async () => {
+    const nominee = web3.Keypair.generate();
+    const stranger = web3.Keypair.generate();
+
+    let tx = await program.methods
+      .proposeKeyRotation({ authority: {} }, nominee.publicKey)
+      .accounts({ signer: wallet.publicKey, market: marketPda })
+      .rpc();
+    await connection.confirmTransaction(tx, "confirmed");
+
+    let m = await program.account.market.fetch(marketPda);
+    assert.equal(new web3.PublicKey(m.pendingAuthority).toBase58(), nominee.publicKey.toBase58());
+    assert.equal(new web3.PublicKey(m.authority).toBase58(), wallet.publicKey.toBase58());
+
+    let accepted = true;
+    try {
+      await program.methods
+        .acceptKeyRotation({ authority: {} })
+        .accounts({ newKey: stranger.publicKey, market: marketPda, oracleSet: oracleSetPda })
+        .signers([stranger])
+        .rpc();
+    } catch (e) {
+      accepted = false;
+    }
+    assert.equal(accepted, false);
+
+    tx = await program.methods
+      .acceptKeyRotation({ authority: {} })
+      .accounts({ newKey: nominee.publicKey, market: marketPda, oracleSet: oracleSetPda })
+      .signers([nominee])
+      .rpc();
+    await connection.confirmTransaction(tx, "confirmed");
+
+    m = await program.account.market.fetch(marketPda);
+    assert.equal(new web3.PublicKey(m.authority).toBase58(), nominee.publicKey.toBase58());
+    assert.equal(new web3.PublicKey(m.creator).toBase58(), wallet.publicKey.toBase58()); // PDA identity unchanged
+
+    // hand it back so the remaining tests keep using the wallet
+    tx = await program.methods
+      .proposeKeyRotation({ authority: {} }, wallet.publicKey)
+      .accounts({ signer: nominee.publicKey, market: marketPda })
+      .signers([nominee])
+      .rpc();
+    await connection.confirmTransaction(tx, "confirmed");
+    tx = await program.methods
+      .acceptKeyRotation({ authority: {} })
+      .accounts({ newKey: wallet.publicKey, market: marketPda, oracleSet: oracleSetPda })
+      .rpc();
+    await connection.confirmTransaction(tx, "confirmed");
+
+    m = await program.account.market.fetch(marketPda);
+    assert.equal(new web3.PublicKey(m.authority).toBase58(), wallet.publicKey.toBase58());
   });
 
-  it("init_warehouse → mint_receipt", async () => {
+  it("update_market_params rejects bad bounds and queues valid ones", async () => {
+    const pendingFor = (id: any) =>
+      web3.PublicKey.findProgramAddressSync(
+        [Buffer.from("pending_action"), marketPda.toBuffer(), new BN(id).toArrayLike(Buffer, "le", 8)],
+        program.programId
+      )[0];
+
+    const base = {
+      feeBps: FEE_BPS,
+      baseInitialMarginBps: BASE_IM_BPS,
+      maintenanceMarginBps: MAINT_BPS,
+      volMultiplierBps: VOL_MULT_BPS,
+      priceExponent: PRICE_EXPONENT,
+      oracleAuthority: ORACLE(),
+    };
+
+    // maintenance above initial margin is refused up front
+    let m = await program.account.market.fetch(marketPda);
+    let rejected = false;
+    try {
+      await program.methods
+        .updateMarketParams({ ...base, maintenanceMarginBps: BASE_IM_BPS + 1 })
+        .accounts({
+          proposer: wallet.publicKey,
+          market: marketPda,
+          pendingAction: pendingFor(m.nextActionId),
+          council: null,
+          councilProposal: null,
+          systemProgram: web3.SystemProgram.programId,
+        })
+        .rpc();
+    } catch (e) {
+      rejected = true;
+    }
+    assert.equal(rejected, true);
+
+    // a valid fee change is queued, not applied
+    m = await program.account.market.fetch(marketPda);
+    const pendingPda = pendingFor(m.nextActionId);
+    const tx = await program.methods
+      .updateMarketParams({ ...base, feeBps: FEE_BPS + 10 })
+      .accounts({
+        proposer: wallet.publicKey,
+        market: marketPda,
+        pendingAction: pendingPda,
+        council: null,
+        councilProposal: null,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .rpc();
+    await connection.confirmTransaction(tx, "confirmed");
+
+    const pa = await program.account.pendingAction.fetch(pendingPda);
+    assert.equal(pa.status, 0);
+    assert.equal((await program.account.market.fetch(marketPda)).feeBps, FEE_BPS);
+
+    await program.methods
+      .cancelAction()
+      .accounts({ signer: wallet.publicKey, market: marketPda, pendingAction: pendingPda, council: null, councilProposal: null })
+      .rpc();
+  });
+
+  it("grade schedule: out-of-range differential rejected, valid one queued", async () => {
+    const pendingFor = (id: any) =>
+      web3.PublicKey.findProgramAddressSync(
+        [Buffer.from("pending_action"), marketPda.toBuffer(), new BN(id).toArrayLike(Buffer, "le", 8)],
+        program.programId
+      )[0];
+    const propose = async (action: any) => {
+      const m = await program.account.market.fetch(marketPda);
+      const pendingAction = pendingFor(m.nextActionId);
+      const t = await program.methods
+        .proposeAction(action)
+        .accounts({
+          proposer: wallet.publicKey,
+          market: marketPda,
+          pendingAction,
+          council: null,
+          councilProposal: null,
+          systemProgram: web3.SystemProgram.programId,
+        })
+        .rpc();
+      await connection.confirmTransaction(t, "confirmed");
+      return pendingAction;
+    };
+
+    // a 60% discount is beyond the ±50% band
+    let rejected = false;
+    try {
+      await propose({ setGradeDifferential: { grade: 3, diffBps: -6_000 } });
+    } catch (e) {
+      rejected = true;
+    }
+    assert.equal(rejected, true);
+
+    // grade 3 delivers at a 2.5% discount once the timelock passes; until then the table is empty
+    const pendingPda = await propose({ setGradeDifferential: { grade: 3, diffBps: -250 } });
+    assert.equal((await program.account.pendingAction.fetch(pendingPda)).status, 0);
+    assert.equal((await program.account.market.fetch(marketPda)).gradeCount, 0);
+    await program.methods
+      .cancelAction()
+      .accounts({ signer: wallet.publicKey, market: marketPda, pendingAction: pendingPda, council: null, councilProposal: null })
+      .rpc();
+  });
+
+  it("init_warehouse → approve_warehouse → attest_inventory → mint_receipt capped by attested stock", async () => {
     // init_warehouse
     let tx = await program.methods
       .initWarehouse()
       .accounts({
         warehouseAuthority: warehouseAuthority.publicKey,
         market: marketPda,
-        authority: wallet.publicKey,
         quoteMint,
         receiptMint,
         receiptMintAuth: receiptMintAuthPda,
         warehouse: warehousePda,
+        storageAuth: storageAuthPda,
+        storageVault,
+        bondAuth: bondAuthPda,
+        bondVault,
         tokenProgram: spl.TOKEN_PROGRAM_ID,
+        receiptTokenProgram: spl.TOKEN_PROGRAM_ID,
+        associatedTokenProgram: spl.ASSOCIATED_TOKEN_PROGRAM_ID,
         systemProgram: web3.SystemProgram.programId,
       })
       .signers([warehouseAuthority])
       .rpc();
     await connection.confirmTransaction(tx, "confirmed");
 
+    // nothing outstanding yet, so the storage rate applies at once
+    tx = await program.methods
+      .setStorageRate(new BN(Math.round(0.01 * 10 ** DECIMALS)))
+      .accounts({ warehouseAuthority: warehouseAuthority.publicKey, warehouse: warehousePda })
+      .signers([warehouseAuthority])
+      .rpc();
+    await connection.confirmTransaction(tx, "confirmed");
+
     // mint_receipt to a recipient
     const recipient = web3.Keypair.generate();
     await airdrop(recipient.publicKey);
@@ -271,33 +676,407 @@ describe("tokenized_warehouse_receipts ()", () => {
     ).address;
 
     const amount = Math.round(250 * 10 ** DECIMALS);
+    const lotPda = (id: number) =>
+      web3.PublicKey.findProgramAddressSync(
+        [Buffer.from("lot"), warehousePda.toBuffer(), new BN(id).toArrayLike(Buffer, "le", 8)],
+        program.programId
+      )[0];
+    const mintAccounts = (lotId: number) => ({
+      warehouse: warehousePda,
+      market: marketPda,
+      receiptMint,
+      receiptMintAuth: receiptMintAuthPda,
+      toReceiptAta,
+      warehouseAuthority: warehouseAuthority.publicKey,
+      lot: lotPda(lotId),
+      recipientParticipant: null, // classic SPL receipt mint: no allowlist
+      tokenProgram: spl.TOKEN_PROGRAM_ID,
+      systemProgram: web3.SystemProgram.programId,
+    });
+    const location = Buffer.alloc(16);
+    location.write("BAY-07/ROW-3");
+    const lotMeta = {
+      grade: 2,
+      grossWeight: new BN(60_500_000), // grams
+      netWeight: new BN(60_000_000),
+      location: [...location],
+      intakeTs: new BN(Math.floor(Date.now() / 1000) - 3600),
+      certificateHash: Array(32).fill(7),
+    };
+
+    // applied but not yet certified → minting is refused
+    let minted = true;
+    try {
+      await program.methods
+        .mintReceipt(new BN(amount), lotMeta)
+        .accounts(mintAccounts(0))
+        .signers([warehouseAuthority])
+        .rpc();
+    } catch (e) {
+      minted = false;
+    }
+    assert.equal(minted, false);
+
+    tx = await program.methods
+      .approveWarehouse(new BN(Math.floor(Date.now() / 1000) + 365 * 86400), new BN(Math.round(1_000 * 10 ** DECIMALS)))
+      .accounts({ signer: wallet.publicKey, market: marketPda, warehouse: warehousePda, council: null, councilProposal: null })
+      .rpc();
+    await connection.confirmTransaction(tx, "confirmed");
+
+    // certified, but nothing attested yet → still refused
+    minted = true;
+    try {
+      await program.methods
+        .mintReceipt(new BN(amount), lotMeta)
+        .accounts(mintAccounts(0))
+        .signers([warehouseAuthority])
+        .rpc();
+    } catch (e) {
+      minted = false;
+    }
+    assert.equal(minted, false);
+
+    // auditor (defaults to governance) attests stock on hand
+    tx = await program.methods
+      .attestInventory(new BN(amount))
+      .accounts({ auditor: wallet.publicKey, market: marketPda, warehouse: warehousePda })
+      .rpc();
+    await connection.confirmTransaction(tx, "confirmed");
+
     tx = await program.methods
-      .mintReceipt(new BN(amount))
+      .mintReceipt(new BN(amount), lotMeta)
+      .accounts(mintAccounts(0))
+      .signers([warehouseAuthority])
+      .rpc();
+    await connection.confirmTransaction(tx, "confirmed");
+
+    const bal = await getTokenAmount(toReceiptAta);
+    assert.equal(bal, amount);
+
+    const w = await program.account.warehouse.fetch(warehousePda);
+    assert.equal(Number(w.totalMinted), amount);
+    assert.equal(Number(w.mintQuota), Math.round(1_000 * 10 ** DECIMALS));
+    const mintInfo = await spl.getMint(connection, receiptMint);
+    assert.equal(mintInfo.mintAuthority.toBase58(), receiptMintAuthPda.toBase58());
+    assert.equal(Number(w.attestedQuantity), amount);
+
+    const lot = await program.account.receiptLot.fetch(lotPda(0));
+    assert.equal(lot.grade, 2);
+    assert.equal(Number(lot.netWeight), 60_000_000);
+    assert.equal(Number(lot.quantityMinted), amount);
+    assert.equal(Buffer.from(lot.location).toString().replace(/\0+$/, ""), "BAY-07/ROW-3");
+
+    // outstanding is now at the attested cap
+    minted = true;
+    try {
+      await program.methods
+        .mintReceipt(new BN(1), lotMeta)
+        .accounts(mintAccounts(1))
+        .signers([warehouseAuthority])
+        .rpc();
+    } catch (e) {
+      minted = false;
+    }
+    assert.equal(minted, false);
+
+    // redemption: escrow → warehouse declines → escrow again → warehouse releases (burns)
+    const redemptionPdas = async () => {
+      const wh = await program.account.warehouse.fetch(warehousePda);
+      const [redemption] = web3.PublicKey.findProgramAddressSync(
+        [Buffer.from("redemption"), warehousePda.toBuffer(), new BN(wh.nextRedemptionId).toArrayLike(Buffer, "le", 8)],
+        program.programId
+      );
+      const [redemptionAuth] = web3.PublicKey.findProgramAddressSync(
+        [Buffer.from("redemption_auth"), redemption.toBuffer()],
+        program.programId
+      );
+      const redemptionVault = spl.getAssociatedTokenAddressSync(receiptMint, redemptionAuth, true);
+      // allowlist is off on this market: no escrow participant to register or close
+      return { redemption, redemptionAuth, redemptionVault, escrowParticipant: null };
+    };
+    // storage: 0.01 quote per receipt unit per day (set before minting), accrued since the lot's intake
+    const recipientQuoteAta = (
+      await spl.getOrCreateAssociatedTokenAccount(connection, mintAuthority, quoteMint, recipient.publicKey)
+    ).address;
+    await spl.mintTo(connection, mintAuthority, quoteMint, recipientQuoteAta, mintAuthority, 10 * 10 ** DECIMALS);
+    // with receipts outstanding an increase is only queued; setting the current rate again drops it
+    const setRate = async (rate: number) => {
+      const t = await program.methods
+        .setStorageRate(new BN(Math.round(rate * 10 ** DECIMALS)))
+        .accounts({ warehouseAuthority: warehouseAuthority.publicKey, warehouse: warehousePda })
+        .signers([warehouseAuthority])
+        .rpc();
+      await connection.confirmTransaction(t, "confirmed");
+    };
+    await setRate(0.02);
+    let wh = await program.account.warehouse.fetch(warehousePda);
+    assert.equal(Number(wh.storageRate), Math.round(0.01 * 10 ** DECIMALS));
+    assert.equal(Number(wh.pendingStorageRate), Math.round(0.02 * 10 ** DECIMALS));
+    assert.equal(Number(wh.storageRateEta) > Math.floor(Date.now() / 1000) + 29 * 86400, true);
+    await setRate(0.01);
+    wh = await program.account.warehouse.fetch(warehousePda);
+    assert.equal(Number(wh.storageRateEta), 0);
+    let accepted = true;
+    try {
+      await setRate(1_000_000); // above MAX_STORAGE_RATE
+    } catch (e) {
+      accepted = false;
+    }
+    assert.equal(accepted, false);
+    const storageAccounts = {
+      market: marketPda,
+      receiptMintAuth: receiptMintAuthPda,
+      quoteMint,
+      holderQuoteAta: recipientQuoteAta,
+      storageAuth: storageAuthPda,
+      storageVault,
+    };
+
+    const delivery = Buffer.alloc(64);
+    delivery.write("CONSIGNEE-42 / DOCK 3");
+    const redeemAmount = Math.round(100 * 10 ** DECIMALS);
+
+    const requestRedemption = async () => {
+      const r = await redemptionPdas();
+      const t = await program.methods
+        .requestRedemption(new BN(redeemAmount), [...delivery])
+        .accounts({
+          holder: recipient.publicKey,
+          warehouse: warehousePda,
+          lot: lotPda(0),
+          receiptMint,
+          holderReceiptAta: toReceiptAta,
+          ...storageAccounts,
+          ...r,
+          tokenProgram: spl.TOKEN_PROGRAM_ID,
+          receiptTokenProgram: spl.TOKEN_PROGRAM_ID,
+          associatedTokenProgram: spl.ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: web3.SystemProgram.programId,
+        })
+        .signers([recipient])
+        .rpc();
+      await connection.confirmTransaction(t, "confirmed");
+      return r;
+    };
+
+    const quoteBefore = await getTokenAmount(recipientQuoteAta);
+    const r0 = await requestRedemption();
+    assert.equal(await getTokenAmount(toReceiptAta), amount - redeemAmount);
+    const fee0 = Number((await program.account.redemption.fetch(r0.redemption)).storageFee);
+    // intake is 1h back, but storage only accrues since the mint a few seconds ago
+    const oneHourFee = (redeemAmount * Math.round(0.01 * 10 ** DECIMALS) * 3600) / (86400 * 10 ** DECIMALS);
+    assert.equal(fee0 > 0 && fee0 < oneHourFee / 10, true);
+    assert.equal(await getTokenAmount(recipientQuoteAta), quoteBefore - fee0);
+    tx = await program.methods
+      .cancelRedemption()
       .accounts({
+        signer: warehouseAuthority.publicKey,
         warehouse: warehousePda,
-        market: marketPda,
+        lot: lotPda(0),
         receiptMint,
-        receiptMintAuth: receiptMintAuthPda,
-        toReceiptAta,
+        ...storageAccounts,
+        ...r0,
+        holder: recipient.publicKey,
+        holderReceiptAta: toReceiptAta,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+        receiptTokenProgram: spl.TOKEN_PROGRAM_ID,
+      })
+      .signers([warehouseAuthority])
+      .rpc();
+    await connection.confirmTransaction(tx, "confirmed");
+    assert.equal(await getTokenAmount(toReceiptAta), amount);
+    assert.equal(await getTokenAmount(recipientQuoteAta), quoteBefore); // storage escrow refunded
+    assert.equal((await program.account.redemption.fetch(r0.redemption)).status, 2); // cancelled
+
+    const r1 = await requestRedemption();
+    tx = await program.methods
+      .confirmRelease()
+      .accounts({
         warehouseAuthority: warehouseAuthority.publicKey,
+        warehouse: warehousePda,
+        lot: lotPda(0),
+        receiptMint,
+        ...r1,
+        holder: recipient.publicKey,
         tokenProgram: spl.TOKEN_PROGRAM_ID,
       })
       .signers([warehouseAuthority])
       .rpc();
     await connection.confirmTransaction(tx, "confirmed");
 
-    const bal = await getTokenAmount(toReceiptAta);
-    assert.equal(bal, amount);
+    assert.equal((await program.account.redemption.fetch(r1.redemption)).status, 1); // released
+    assert.equal(Number((await program.account.receiptLot.fetch(lotPda(0))).quantityBurned), redeemAmount);
+    assert.equal(Number((await program.account.warehouse.fetch(warehousePda)).totalBurned), redeemAmount);
+    assert.equal(await getTokenAmount(toReceiptAta), amount - redeemAmount);
+
+    // released storage fee is now collectible by the warehouse
+    const fee1 = Number((await program.account.redemption.fetch(r1.redemption)).storageFee);
+    assert.equal(Number((await program.account.warehouse.fetch(warehousePda)).storageFeesAccrued), fee1);
+    const whQuoteAta = (
+      await spl.getOrCreateAssociatedTokenAccount(connection, mintAuthority, quoteMint, warehouseAuthority.publicKey)
+    ).address;
+    tx = await program.methods
+      .collectStorageFees()
+      .accounts({
+        warehouseAuthority: warehouseAuthority.publicKey,
+        market: marketPda,
+        warehouse: warehousePda,
+        quoteMint,
+        storageAuth: storageAuthPda,
+        storageVault,
+        toQuoteAta: whQuoteAta,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+      })
+      .signers([warehouseAuthority])
+      .rpc();
+    await connection.confirmTransaction(tx, "confirmed");
+    assert.equal(await getTokenAmount(whQuoteAta), fee1);
+  });
+
+  it("performance bond: post_bond → timelocked withdrawal → slash_warehouse", async () => {
+    const whQuoteAta = (
+      await spl.getOrCreateAssociatedTokenAccount(connection, mintAuthority, quoteMint, warehouseAuthority.publicKey)
+    ).address;
+    await spl.mintTo(connection, mintAuthority, quoteMint, whQuoteAta, mintAuthority, Math.round(1_000 * 10 ** DECIMALS));
+    const bondAccounts = {
+      warehouseAuthority: warehouseAuthority.publicKey,
+      market: marketPda,
+      warehouse: warehousePda,
+      quoteMint,
+      bondAuth: bondAuthPda,
+      bondVault,
+      tokenProgram: spl.TOKEN_PROGRAM_ID,
+    };
+
+    const bond = Math.round(500 * 10 ** DECIMALS);
+    let tx = await program.methods
+      .postBond(new BN(bond))
+      .accounts({ ...bondAccounts, fromQuoteAta: whQuoteAta })
+      .signers([warehouseAuthority])
+      .rpc();
+    await connection.confirmTransaction(tx, "confirmed");
+    assert.equal(await getTokenAmount(bondVault), bond);
+
+    // withdrawal waits out the market's bond delay
+    tx = await program.methods
+      .requestBondWithdrawal(new BN(100 * 10 ** DECIMALS))
+      .accounts({ warehouseAuthority: warehouseAuthority.publicKey, market: marketPda, warehouse: warehousePda })
+      .signers([warehouseAuthority])
+      .rpc();
+    await connection.confirmTransaction(tx, "confirmed");
+    let withdrawn = true;
+    try {
+      await program.methods
+        .withdrawBond()
+        .accounts({ ...bondAccounts, toQuoteAta: whQuoteAta })
+        .signers([warehouseAuthority])
+        .rpc();
+    } catch (e) {
+      withdrawn = false;
+    }
+    assert.equal(withdrawn, false);
+
+    // requesting 0 cancels
+    tx = await program.methods
+      .requestBondWithdrawal(new BN(0))
+      .accounts({ warehouseAuthority: warehouseAuthority.publicKey, market: marketPda, warehouse: warehousePda })
+      .signers([warehouseAuthority])
+      .rpc();
+    await connection.confirmTransaction(tx, "confirmed");
+    assert.equal(Number((await program.account.warehouse.fetch(warehousePda)).pendingBondWithdrawal), 0);
+
+    // a slash compensates a failed delivery: the redemption the warehouse declined (id 0) has
+    // already returned the holder's receipts, so it can't also be paid out of the bond
+    const [redemption] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("redemption"), warehousePda.toBuffer(), new BN(0).toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    const holderQuoteAta = spl.getAssociatedTokenAddressSync(quoteMint, (await program.account.redemption.fetch(redemption)).holder);
+    let slashed = true;
+    try {
+      await program.methods
+        .slashWarehouse(new BN(Math.round(40 * 10 ** DECIMALS)))
+        .accounts({
+          signer: wallet.publicKey,
+          market: marketPda,
+          warehouse: warehousePda,
+          quoteMint,
+          bondAuth: bondAuthPda,
+          bondVault,
+          recipientQuoteAta: holderQuoteAta,
+          redemption,
+          council: null,
+          councilProposal: null,
+          tokenProgram: spl.TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+    } catch (e) {
+      slashed = false;
+    }
+    assert.equal(slashed, false);
+    // (an open redemption only becomes slashable after its 7-day timeout, beyond this suite)
+    const w = await program.account.warehouse.fetch(warehousePda);
+    assert.equal(Number(w.bondAmount), bond);
+    assert.equal(Number(w.totalSlashed), 0);
+  });
+
+  it("post_reserve_snapshot → verify_lot_inclusion", async () => {
+    const sha256 = async (b: Buffer) => Buffer.from(await (globalThis as any).crypto.subtle.digest("SHA-256", b));
+    const [reservePda] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("reserve_snapshot"), warehousePda.toBuffer()],
+      program.programId
+    );
+    const qty = new BN(Math.round(150 * 10 ** DECIMALS));
+    const leafFor = (lot: web3.PublicKey, q: any) => sha256(Buffer.concat([lot.toBuffer(), q.toArrayLike(Buffer, "le", 8)]));
+
+    // two-lot tree: the second leaf is some other lot on hand
+    const other = web3.Keypair.generate().publicKey;
+    const leaf0 = await leafFor(lot0Pda, qty);
+    const leaf1 = await leafFor(other, new BN(1_000));
+    const [a, b] = Buffer.compare(leaf0, leaf1) <= 0 ? [leaf0, leaf1] : [leaf1, leaf0];
+    const root = await sha256(Buffer.concat([a, b]));
+
+    let tx = await program.methods
+      .postReserveSnapshot([...root], qty.addn(1_000), 2, new BN(Math.floor(Date.now() / 1000) - 60))
+      .accounts({
+        poster: wallet.publicKey, // market auditor
+        market: marketPda,
+        warehouse: warehousePda,
+        reserveSnapshot: reservePda,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .rpc();
+    await connection.confirmTransaction(tx, "confirmed");
+    assert.equal(Number((await program.account.reserveSnapshot.fetch(reservePda)).seq), 1);
+
+    tx = await program.methods
+      .verifyLotInclusion(qty, [[...leaf1]])
+      .accounts({ reserveSnapshot: reservePda, lot: lot0Pda })
+      .rpc();
+    await connection.confirmTransaction(tx, "confirmed");
+
+    let ok = true;
+    try {
+      await program.methods
+        .verifyLotInclusion(qty.addn(1), [[...leaf1]])
+        .accounts({ reserveSnapshot: reservePda, lot: lot0Pda })
+        .rpc();
+    } catch (e) {
+      ok = false;
+    }
+    assert.equal(ok, false);
   });
 
   it("open_deal (cash) with required initial margin → deposit_margin → settle_cash", async () => {
-    // PDAs for deal
+    // PDAs for deal (deal_id is part of the address, so a pair can hold many deals)
+    const dealId = new BN(101);
     [dealPda] = web3.PublicKey.findProgramAddressSync(
       [
         Buffer.from("deal"),
         marketPda.toBuffer(),
         long.publicKey.toBuffer(),
         short.publicKey.toBuffer(),
+        dealId.toArrayLike(Buffer, "le", 8),
       ],
       program.programId
     );
@@ -313,7 +1092,6 @@ describe("tokenized_warehouse_receipts ()", () => {
 
     // params
     const DEAL_VERSION = 1; // must match on-chain
-    const dealId = new BN(101);
     const strike = toUnitsBN(100); // 100 quote per receipt unit
     const qty = toUnitsBN(8);      // 8 receipts
     const settleTs = new BN(Math.floor(Date.now() / 1000) + 2);
@@ -366,6 +1144,56 @@ describe("tokenized_warehouse_receipts ()", () => {
       .rpc();
     await connection.confirmTransaction(tx, "confirmed");
 
+    // the same pair can hold a second, concurrent deal under another deal_id
+    const dealIdB = new BN(102);
+    const [dealBPda] = web3.PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("deal"),
+        marketPda.toBuffer(),
+        long.publicKey.toBuffer(),
+        short.publicKey.toBuffer(),
+        dealIdB.toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    const [vaultAuthBPda] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("vault_auth"), dealBPda.toBuffer()],
+      program.programId
+    );
+    const vaultB = spl.getAssociatedTokenAddressSync(quoteMint, vaultAuthBPda, true);
+    tx = await program.methods
+      .openDeal(
+        dealIdB,
+        DEAL_VERSION,
+        strike,
+        qty,
+        new BN(Math.floor(Date.now() / 1000) + 3600),
+        settlementKind,
+        imLong,
+        imShort
+      )
+      .accounts({
+        market: marketPda,
+        long: long.publicKey,
+        short: short.publicKey,
+        quoteMint,
+        longQuoteAta,
+        shortQuoteAta,
+        deal: dealBPda,
+        longMarginVault: vaultB,
+        shortMarginVault: vaultB,
+        vaultAuth: vaultAuthBPda,
+        feeVault,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+        associatedTokenProgram: spl.ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .signers([long, short])
+      .rpc();
+    await connection.confirmTransaction(tx, "confirmed");
+    assert.equal(Number((await program.account.deal.fetch(dealBPda)).dealId), 102);
+    assert.equal((await program.account.deal.fetch(dealPda)).isSettled, false);
+
     // extra deposits
     const addLong = toUnitsBN(1.0);
     const addShort = toUnitsBN(1.5);
@@ -407,6 +1235,60 @@ describe("tokenized_warehouse_receipts ()", () => {
     // wait to pass settle_ts
     await sleep(2500);
 
+    // oracle quotes this expiry, then freezes its settlement price
+    tx = await program.methods
+      .postPrice(toUnitsBN(120.0), PRICE_EXPONENT, settleTs, 500)
+      .accounts({
+        market: marketPda,
+        priceHistory: priceHistoryPda,
+        oracleSet: oracleSetPda,
+        twap: twapPda(marketPda, settleTs),
+        poster: wallet.publicKey,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .rpc();
+    await connection.confirmTransaction(tx, "confirmed");
+
+    const [settlementPricePda] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("settlement_price"), marketPda.toBuffer(), settleTs.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    tx = await program.methods
+      .finalizeSettlementPrice(settleTs)
+      .accounts({
+        oracle: wallet.publicKey,
+        market: marketPda,
+        twap: twapPda(marketPda, settleTs),
+        oracleSet: oracleSetPda,
+        settlementPrice: settlementPricePda,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .rpc();
+    await connection.confirmTransaction(tx, "confirmed");
+
+    const sp = await program.account.settlementPrice.fetch(settlementPricePda);
+    assert.equal(sp.price.toString(), toUnitsBN(120.0).toString());
+    assert.notEqual(Number(sp.finalizedTs), 0); // quorum 1: the first approval freezes it
+
+    // frozen: a second approval cannot rewrite it
+    let refinalized = true;
+    try {
+      await program.methods
+        .finalizeSettlementPrice(settleTs)
+        .accounts({
+          oracle: wallet.publicKey,
+          market: marketPda,
+          twap: twapPda(marketPda, settleTs),
+          oracleSet: oracleSetPda,
+          settlementPrice: settlementPricePda,
+          systemProgram: web3.SystemProgram.programId,
+        })
+        .rpc();
+    } catch (e) {
+      refinalized = false;
+    }
+    assert.equal(refinalized, false);
+
     const longReceiveQuoteAta = longQuoteAta;
     const shortReceiveQuoteAta = spl.getAssociatedTokenAddressSync(quoteMint, short.publicKey);
 
@@ -419,6 +1301,7 @@ describe("tokenized_warehouse_receipts ()", () => {
       .accounts({
         market: marketPda,
         deal: dealPda,
+        settlementPrice: settlementPricePda,
         quoteMint,
         receiptMint,
         vaultAuth: vaultAuthPda,
@@ -502,12 +1385,14 @@ describe("tokenized_warehouse_receipts ()", () => {
     );
 
     // PDAs
+    const dealId = new BN(202);
     const [deal2Pda] = web3.PublicKey.findProgramAddressSync(
       [
         Buffer.from("deal"),
         marketPda.toBuffer(),
         long2.publicKey.toBuffer(),
         short2.publicKey.toBuffer(),
+        dealId.toArrayLike(Buffer, "le", 8),
       ],
       program.programId
     );
@@ -520,7 +1405,6 @@ describe("tokenized_warehouse_receipts ()", () => {
 
     // params
     const DEAL_VERSION = 1;
-    const dealId = new BN(202);
     const strike = toUnitsBN(50);
     const qty = toUnitsBN(6); // we will partially settle first
     const settleTs = new BN(Math.floor(Date.now() / 1000) + 2);
@@ -584,6 +1468,7 @@ describe("tokenized_warehouse_receipts ()", () => {
         market: marketPda,
         quoteMint,
         receiptMint,
+        receiptMintAuth: receiptMintAuthPda,
         vaultAuth: vaultAuth2Pda,
         longMarginVault: long2MarginVault,
         shortMarginVault: short2MarginVault,
@@ -593,7 +1478,12 @@ describe("tokenized_warehouse_receipts ()", () => {
         shortReceiptAta: short2ReceiptAta,
         longReceiveQuoteAta: long2QuoteAta,
         shortReceiveQuoteAta: short2QuoteAta,
+        warehouse: warehousePda,
+        lot: lot0Pda,
+        storageAuth: storageAuthPda,
+        storageVault,
         tokenProgram: spl.TOKEN_PROGRAM_ID,
+        receiptTokenProgram: spl.TOKEN_PROGRAM_ID,
         associatedTokenProgram: spl.ASSOCIATED_TOKEN_PROGRAM_ID,
       })
       .signers([long2, short2])
@@ -608,6 +1498,7 @@ describe("tokenized_warehouse_receipts ()", () => {
         market: marketPda,
         quoteMint,
         receiptMint,
+        receiptMintAuth: receiptMintAuthPda,
         vaultAuth: vaultAuth2Pda,
         longMarginVault: long2MarginVault,
         shortMarginVault: short2MarginVault,
@@ -617,7 +1508,12 @@ describe("tokenized_warehouse_receipts ()", () => {
         shortReceiptAta: short2ReceiptAta,
         longReceiveQuoteAta: long2QuoteAta,
         shortReceiveQuoteAta: short2QuoteAta,
+        warehouse: warehousePda,
+        lot: lot0Pda,
+        storageAuth: storageAuthPda,
+        storageVault,
         tokenProgram: spl.TOKEN_PROGRAM_ID,
+        receiptTokenProgram: spl.TOKEN_PROGRAM_ID,
         associatedTokenProgram: spl.ASSOCIATED_TOKEN_PROGRAM_ID,
       })
       .signers([long2, short2])
@@ -703,12 +1599,14 @@ describe("tokenized_warehouse_receipts ()", () => {
       )
     ).address;
 
+    const dId = new BN(303);
     const [deal3Pda] = web3.PublicKey.findProgramAddressSync(
       [
         Buffer.from("deal"),
         marketPda.toBuffer(),
         owner.publicKey.toBuffer(),
         tempShort.publicKey.toBuffer(),
+        dId.toArrayLike(Buffer, "le", 8),
       ],
       program.programId
     );
@@ -719,7 +1617,6 @@ describe("tokenized_warehouse_receipts ()", () => {
     const long3MarginVault = spl.getAssociatedTokenAddressSync(quoteMint, vaultAuth3Pda, true);
     const short3MarginVault = spl.getAssociatedTokenAddressSync(quoteMint, vaultAuth3Pda, true);
 
-    const dId = new BN(303);
     const strike = toUnitsBN(90);
     const qty = toUnitsBN(1);
     const settleTs = new BN(Math.floor(Date.now() / 1000) + 5);
@@ -832,5 +1729,1371 @@ describe("tokenized_warehouse_receipts ()", () => {
     const cmBal = await getTokenAmount(cmVaultAta);
     assert.equal(cmBal > 0, true);
   });
-});
 
+  it("liquidate_deal closes an undercollateralized long", async () => {
+    const long4 = web3.Keypair.generate();
+    const short4 = web3.Keypair.generate();
+    const liquidator = web3.Keypair.generate();
+    await airdrop(long4.publicKey);
+    await airdrop(short4.publicKey);
+    await airdrop(liquidator.publicKey);
+
+    const long4QuoteAta = (
+      await spl.getOrCreateAssociatedTokenAccount(connection, mintAuthority, quoteMint, long4.publicKey)
+    ).address;
+    const short4QuoteAta = (
+      await spl.getOrCreateAssociatedTokenAccount(connection, mintAuthority, quoteMint, short4.publicKey)
+    ).address;
+    const liquidatorQuoteAta = (
+      await spl.getOrCreateAssociatedTokenAccount(connection, mintAuthority, quoteMint, liquidator.publicKey)
+    ).address;
+    await spl.mintTo(connection, mintAuthority, quoteMint, long4QuoteAta, mintAuthority, Math.round(1_000 * 10 ** DECIMALS));
+    await spl.mintTo(connection, mintAuthority, quoteMint, short4QuoteAta, mintAuthority, Math.round(1_000 * 10 ** DECIMALS));
+
+    const deal4Id = new BN(404);
+    const [deal4Pda] = web3.PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("deal"),
+        marketPda.toBuffer(),
+        long4.publicKey.toBuffer(),
+        short4.publicKey.toBuffer(),
+        deal4Id.toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    const [vaultAuth4Pda] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("vault_auth"), deal4Pda.toBuffer()],
+      program.programId
+    );
+    const long4MarginVault = spl.getAssociatedTokenAddressSync(quoteMint, vaultAuth4Pda, true);
+    const short4MarginVault = spl.getAssociatedTokenAddressSync(quoteMint, vaultAuth4Pda, true);
+
+    // strike well above the posted 120 → long is underwater from the start
+    const strike = toUnitsBN(130);
+    const qty = toUnitsBN(4);
+    const m = await program.account.market.fetch(marketPda);
+    const reqIM = requiredInitialMargin(
+      m.priceExponent,
+      m.baseInitialMarginBps,
+      m.volMultiplierBps,
+      m.lastVolBps,
+      new BN(strike),
+      new BN(qty)
+    );
+
+    const settle4Ts = new BN(Math.floor(Date.now() / 1000) + 3600);
+    let tx = await program.methods
+      .openDeal(
+        deal4Id,
+        1,
+        strike,
+        qty,
+        settle4Ts,
+        { cash: {} },
+        reqIM,
+        reqIM
+      )
+      .accounts({
+        market: marketPda,
+        long: long4.publicKey,
+        short: short4.publicKey,
+        quoteMint,
+        longQuoteAta: long4QuoteAta,
+        shortQuoteAta: short4QuoteAta,
+        deal: deal4Pda,
+        longMarginVault: long4MarginVault,
+        shortMarginVault: short4MarginVault,
+        vaultAuth: vaultAuth4Pda,
+        feeVault,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+        associatedTokenProgram: spl.ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .signers([long4, short4])
+      .rpc();
+    await connection.confirmTransaction(tx, "confirmed");
+
+    // liquidation only prices against a fresh print quoted for the deal's own expiry
+    const liquidate = () =>
+      program.methods
+        .liquidateDeal()
+        .accounts({
+          liquidator: liquidator.publicKey,
+          market: marketPda,
+          deal: deal4Pda,
+          quoteMint,
+          vaultAuth: vaultAuth4Pda,
+          longMarginVault: long4MarginVault,
+          shortMarginVault: short4MarginVault,
+          longReceiveQuoteAta: long4QuoteAta,
+          shortReceiveQuoteAta: short4QuoteAta,
+          liquidatorQuoteAta,
+          tokenProgram: spl.TOKEN_PROGRAM_ID,
+          associatedTokenProgram: spl.ASSOCIATED_TOKEN_PROGRAM_ID,
+        })
+        .signers([liquidator])
+        .rpc();
+    let liquidated = true;
+    try {
+      await liquidate();
+    } catch (e) {
+      liquidated = false;
+      assert.include(String(e), "SettlementExpiryMismatch");
+    }
+    assert.equal(liquidated, false);
+
+    tx = await program.methods
+      .postPrice(toUnitsBN(120.0), PRICE_EXPONENT, settle4Ts, 500)
+      .accounts({
+        market: marketPda,
+        priceHistory: priceHistoryPda,
+        oracleSet: oracleSetPda,
+        twap: twapPda(marketPda, settle4Ts),
+        poster: wallet.publicKey,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .rpc();
+    await connection.confirmTransaction(tx, "confirmed");
+
+    const preLiq = await getTokenAmount(liquidatorQuoteAta);
+    tx = await liquidate();
+    await connection.confirmTransaction(tx, "confirmed");
+
+    const d = await program.account.deal.fetch(deal4Pda);
+    assert.equal(d.isSettled, true);
+    assert.equal(Number(d.longMargin), 0);
+    assert.equal(Number(d.shortMargin), 0);
+    // long margin is fully consumed by PnL, so any bounty is capped at zero
+    assert.equal((await getTokenAmount(liquidatorQuoteAta)) >= preLiq, true);
+  });
+
+  it("mark_to_market moves variation margin both ways and rejects a mark the margin can't cover", async () => {
+    // fresh market so the prints below don't disturb the shared one
+    const mtmReceiptMint = await spl.createMint(connection, mintAuthority, mintAuthority.publicKey, null, DECIMALS);
+    const [mtmMarket] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("market"), wallet.publicKey.toBuffer(), mtmReceiptMint.toBuffer(), quoteMint.toBuffer()],
+      program.programId
+    );
+    const pda = (tag: string, key: web3.PublicKey) =>
+      web3.PublicKey.findProgramAddressSync([Buffer.from(tag), key.toBuffer()], program.programId)[0];
+    let tx = await program.methods
+      .initMarket(FEE_BPS, ORACLE(), GOVERNANCE(), BASE_IM_BPS, MAINT_BPS, VOL_MULT_BPS)
+      .accounts({
+        authority: wallet.publicKey,
+        quoteMint,
+        receiptMint: mtmReceiptMint,
+        market: mtmMarket,
+        priceHistory: pda("price_history", mtmMarket),
+        oracleSet: pda("oracle_set", mtmMarket),
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .rpc();
+    await connection.confirmTransaction(tx, "confirmed");
+
+    // cash deal: 10 units at 100, 60 quote of margin per side (base IM is 50)
+    const dealId = new BN(1);
+    const settleTs = new BN(Math.floor(Date.now() / 1000) + 3600);
+    const [deal] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("deal"), mtmMarket.toBuffer(), long.publicKey.toBuffer(), short.publicKey.toBuffer(), dealId.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    const vaultAuth = pda("vault_auth", deal);
+    const marginVault = spl.getAssociatedTokenAddressSync(quoteMint, vaultAuth, true);
+    const margin = toUnitsBN(60);
+    tx = await program.methods
+      .openDeal(dealId, 1, toUnitsBN(100), toUnitsBN(10), settleTs, { cash: {} }, margin, margin)
+      .accounts({
+        market: mtmMarket,
+        long: long.publicKey,
+        short: short.publicKey,
+        quoteMint,
+        longQuoteAta,
+        shortQuoteAta,
+        deal,
+        longMarginVault: marginVault,
+        shortMarginVault: marginVault,
+        vaultAuth,
+        feeVault: spl.getAssociatedTokenAddressSync(quoteMint, mtmMarket, true),
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+        associatedTokenProgram: spl.ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .signers([long, short])
+      .rpc();
+    await connection.confirmTransaction(tx, "confirmed");
+
+    const postAndMark = async (price: number, expiry = settleTs) => {
+      const t = await program.methods
+        .postPrice(toUnitsBN(price), PRICE_EXPONENT, expiry, 0)
+        .accounts({
+          market: mtmMarket,
+          priceHistory: pda("price_history", mtmMarket),
+          oracleSet: pda("oracle_set", mtmMarket),
+          twap: twapPda(mtmMarket, expiry),
+          poster: wallet.publicKey,
+          systemProgram: web3.SystemProgram.programId,
+        })
+        .rpc();
+      await connection.confirmTransaction(t, "confirmed");
+      return program.methods
+        .markToMarket()
+        .accounts({
+          market: mtmMarket,
+          deal,
+          quoteMint,
+          vaultAuth,
+          longMarginVault: marginVault,
+          shortMarginVault: marginVault,
+          tokenProgram: spl.TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+    };
+    const margins = async () => {
+      const d = await program.account.deal.fetch(deal);
+      return [d.longMargin.toString(), d.shortMargin.toString(), d.lastMarkPrice.toString()];
+    };
+
+    // up 5: the short pays 50 to the long
+    tx = await postAndMark(105);
+    await connection.confirmTransaction(tx, "confirmed");
+    assert.deepEqual(await margins(), [toUnitsBN(110).toString(), toUnitsBN(10).toString(), toUnitsBN(105).toString()]);
+
+    // back down 5: the long pays it back, measured from the last mark rather than the strike
+    tx = await postAndMark(100);
+    await connection.confirmTransaction(tx, "confirmed");
+    assert.deepEqual(await margins(), [margin.toString(), margin.toString(), toUnitsBN(100).toString()]);
+
+    // up 10 would take 100 from a short holding 60: refused, nothing moves
+    let marked = true;
+    try {
+      await postAndMark(110);
+    } catch (e) {
+      marked = false;
+      assert.include(String(e), "InsufficientVariationMargin");
+    }
+    assert.equal(marked, false);
+    assert.deepEqual(await margins(), [margin.toString(), margin.toString(), toUnitsBN(100).toString()]);
+
+    // a print quoted for another expiry can't move this deal's margin
+    marked = true;
+    try {
+      await postAndMark(105, settleTs.addn(86400));
+    } catch (e) {
+      marked = false;
+      assert.include(String(e), "SettlementExpiryMismatch");
+    }
+    assert.equal(marked, false);
+    assert.deepEqual(await margins(), [margin.toString(), margin.toString(), toUnitsBN(100).toString()]);
+  });
+
+  it("deal offers: propose_deal → cancel_offer refunds; propose_deal → accept_deal opens the deal", async () => {
+    const maker = web3.Keypair.generate();
+    const taker = web3.Keypair.generate();
+    await airdrop(maker.publicKey);
+    await airdrop(taker.publicKey);
+    const makerQuoteAta = (
+      await spl.getOrCreateAssociatedTokenAccount(connection, mintAuthority, quoteMint, maker.publicKey)
+    ).address;
+    const takerQuoteAta = (
+      await spl.getOrCreateAssociatedTokenAccount(connection, mintAuthority, quoteMint, taker.publicKey)
+    ).address;
+    await spl.mintTo(connection, mintAuthority, quoteMint, makerQuoteAta, mintAuthority, Math.round(1_000 * 10 ** DECIMALS));
+    await spl.mintTo(connection, mintAuthority, quoteMint, takerQuoteAta, mintAuthority, Math.round(1_000 * 10 ** DECIMALS));
+
+    const strike = toUnitsBN(120);
+    const qty = toUnitsBN(2);
+    const m = await program.account.market.fetch(marketPda);
+    const reqIM = requiredInitialMargin(
+      m.priceExponent,
+      m.baseInitialMarginBps,
+      m.volMultiplierBps,
+      m.lastVolBps,
+      new BN(strike),
+      new BN(qty)
+    );
+    const now = Math.floor(Date.now() / 1000);
+    const settleTs = new BN(now + 3600);
+    const dealId = new BN(505);
+
+    function offerPdas(offerId: any) {
+      const [offer] = web3.PublicKey.findProgramAddressSync(
+        [Buffer.from("deal_offer"), marketPda.toBuffer(), maker.publicKey.toBuffer(), offerId.toArrayLike(Buffer, "le", 8)],
+        program.programId
+      );
+      const [offerAuth] = web3.PublicKey.findProgramAddressSync(
+        [Buffer.from("offer_auth"), offer.toBuffer()],
+        program.programId
+      );
+      return { offer, offerAuth, offerVault: spl.getAssociatedTokenAddressSync(quoteMint, offerAuth, true) };
+    }
+    async function propose(offerId: any) {
+      const p = offerPdas(offerId);
+      const tx = await program.methods
+        .proposeDeal(
+          offerId,
+          1,
+          { short: {} }, // maker sells
+          web3.PublicKey.default, // any taker
+          dealId,
+          strike,
+          qty,
+          settleTs,
+          { cash: {} },
+          reqIM,
+          new BN(now + 600)
+        )
+        .accounts({
+          market: marketPda,
+          maker: maker.publicKey,
+          quoteMint,
+          makerQuoteAta,
+          offer: p.offer,
+          offerAuth: p.offerAuth,
+          offerVault: p.offerVault,
+          tokenProgram: spl.TOKEN_PROGRAM_ID,
+          associatedTokenProgram: spl.ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: web3.SystemProgram.programId,
+        })
+        .signers([maker])
+        .rpc();
+      await connection.confirmTransaction(tx, "confirmed");
+      return p;
+    }
+
+    // 1) propose → cancel: escrow goes back to the maker
+    const pre = await getTokenAmount(makerQuoteAta);
+    const first = await propose(new BN(1));
+    assert.equal(await getTokenAmount(makerQuoteAta), pre - Number(reqIM));
+    let tx = await program.methods
+      .cancelOffer()
+      .accounts({
+        maker: maker.publicKey,
+        offer: first.offer,
+        quoteMint,
+        makerQuoteAta,
+        offerAuth: first.offerAuth,
+        offerVault: first.offerVault,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+      })
+      .signers([maker])
+      .rpc();
+    await connection.confirmTransaction(tx, "confirmed");
+    assert.equal(await getTokenAmount(makerQuoteAta), pre);
+    assert.equal((await program.account.dealOffer.fetch(first.offer)).status, 2); // Cancelled
+
+    // 2) propose → accept: taker goes long, the deal is created with the offer's terms
+    const second = await propose(new BN(2));
+    const [dealPdaX] = web3.PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("deal"),
+        marketPda.toBuffer(),
+        taker.publicKey.toBuffer(),
+        maker.publicKey.toBuffer(),
+        dealId.toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    const [vaultAuthX] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("vault_auth"), dealPdaX.toBuffer()],
+      program.programId
+    );
+    const marginVaultX = spl.getAssociatedTokenAddressSync(quoteMint, vaultAuthX, true);
+    tx = await program.methods
+      .acceptDeal(reqIM)
+      .accounts({
+        market: marketPda,
+        offer: second.offer,
+        taker: taker.publicKey,
+        long: taker.publicKey,
+        short: maker.publicKey,
+        quoteMint,
+        takerQuoteAta,
+        offerAuth: second.offerAuth,
+        offerVault: second.offerVault,
+        deal: dealPdaX,
+        longMarginVault: marginVaultX,
+        shortMarginVault: marginVaultX,
+        vaultAuth: vaultAuthX,
+        feeVault,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+        associatedTokenProgram: spl.ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .signers([taker])
+      .rpc();
+    await connection.confirmTransaction(tx, "confirmed");
+
+    const d = await program.account.deal.fetch(dealPdaX);
+    assert.equal(d.long.toBase58(), taker.publicKey.toBase58());
+    assert.equal(d.short.toBase58(), maker.publicKey.toBase58());
+    assert.equal(d.longMargin.toString(), reqIM.toString());
+    assert.equal(d.shortMargin.toString(), reqIM.toString());
+    assert.equal(await getTokenAmount(second.offerVault), 0);
+    const o = await program.account.dealOffer.fetch(second.offer);
+    assert.equal(o.status, 1); // Accepted
+    assert.equal(o.deal.toBase58(), dealPdaX.toBase58());
+  });
+
+  it("order book: place bid/ask from cross-margin → match_orders opens a deal → cancel_order releases the rest", async () => {
+    const buyer = web3.Keypair.generate();
+    const seller = web3.Keypair.generate();
+    await airdrop(buyer.publicKey);
+    await airdrop(seller.publicKey);
+
+    async function fundedCrossMargin(owner: web3.Keypair) {
+      const ownerQuoteAta = (
+        await spl.getOrCreateAssociatedTokenAccount(connection, mintAuthority, quoteMint, owner.publicKey)
+      ).address;
+      await spl.mintTo(connection, mintAuthority, quoteMint, ownerQuoteAta, mintAuthority, Math.round(1_000 * 10 ** DECIMALS));
+      const [crossMargin] = web3.PublicKey.findProgramAddressSync(
+        [Buffer.from("cross_margin"), marketPda.toBuffer(), owner.publicKey.toBuffer(), quoteMint.toBuffer()],
+        program.programId
+      );
+      const [cmVaultAuth] = web3.PublicKey.findProgramAddressSync(
+        [Buffer.from("cm_vault_auth"), crossMargin.toBuffer()],
+        program.programId
+      );
+      const cmVault = spl.getAssociatedTokenAddressSync(quoteMint, cmVaultAuth, true);
+      let tx = await program.methods
+        .cmCreate()
+        .accounts({
+          owner: owner.publicKey,
+          market: marketPda,
+          quoteMint,
+          crossMargin,
+          cmVaultAuth,
+          cmVaultAta: cmVault,
+          tokenProgram: spl.TOKEN_PROGRAM_ID,
+          associatedTokenProgram: spl.ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: web3.SystemProgram.programId,
+        })
+        .signers([owner])
+        .rpc();
+      await connection.confirmTransaction(tx, "confirmed");
+      tx = await program.methods
+        .cmDeposit(toUnitsBN(100))
+        .accounts({
+          owner: owner.publicKey,
+          market: marketPda,
+          quoteMint,
+          crossMargin,
+          cmVaultAuth,
+          cmVaultAta: cmVault,
+          ownerQuoteAta,
+          tokenProgram: spl.TOKEN_PROGRAM_ID,
+          associatedTokenProgram: spl.ASSOCIATED_TOKEN_PROGRAM_ID,
+        })
+        .signers([owner])
+        .rpc();
+      await connection.confirmTransaction(tx, "confirmed");
+      return { owner, ownerQuoteAta, crossMargin, cmVaultAuth, cmVault };
+    }
+    const b = await fundedCrossMargin(buyer);
+    const a = await fundedCrossMargin(seller);
+    const seller2 = web3.Keypair.generate();
+    await airdrop(seller2.publicKey);
+    const a2 = await fundedCrossMargin(seller2);
+
+    const settleTs = new BN(Math.floor(Date.now() / 1000) + 7200);
+    const [bookPda] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("order_book"), marketPda.toBuffer(), quoteMint.toBuffer(), settleTs.toArrayLike(Buffer, "le", 8), Buffer.from([0])],
+      program.programId
+    );
+    let tx = await program.methods
+      .initOrderBook(settleTs, { cash: {} })
+      .accounts({ payer: wallet.publicKey, market: marketPda, quoteMint, book: bookPda, systemProgram: web3.SystemProgram.programId })
+      .rpc();
+    await connection.confirmTransaction(tx, "confirmed");
+
+    async function place(cm: any, side: any, strike: any, qty: any) {
+      const book = await program.account.orderBook.fetch(bookPda);
+      const [order] = web3.PublicKey.findProgramAddressSync(
+        [Buffer.from("order"), bookPda.toBuffer(), book.nextOrderId.toArrayLike(Buffer, "le", 8)],
+        program.programId
+      );
+      const t = await program.methods
+        .placeOrder(side, strike, qty)
+        .accounts({
+          owner: cm.owner.publicKey,
+          market: marketPda,
+          book: bookPda,
+          order,
+          crossMargin: cm.crossMargin,
+          cmVaultAuth: cm.cmVaultAuth,
+          cmVaultAta: cm.cmVault,
+          systemProgram: web3.SystemProgram.programId,
+        })
+        .signers([cm.owner])
+        .rpc();
+      await connection.confirmTransaction(t, "confirmed");
+      return order;
+    }
+    // resting ask at 118 for 3; the bid at 121 for 2 crosses it
+    const askOrder = await place(a, { short: {} }, toUnitsBN(118), toUnitsBN(3));
+    const worseAsk = await place(a2, { short: {} }, toUnitsBN(119), toUnitsBN(3));
+    const bidOrder = await place(b, { long: {} }, toUnitsBN(121), toUnitsBN(2));
+    assert.equal((await program.account.orderBook.fetch(bookPda)).askCount, 2);
+    const lockedBefore = (await program.account.crossMargin.fetch(a.crossMargin)).locked;
+    assert.equal(lockedBefore.gtn(0), true);
+
+    // locked margin can't be withdrawn
+    let rejected = false;
+    try {
+      await program.methods
+        .cmWithdraw(toUnitsBN(100))
+        .accounts({
+          owner: seller.publicKey,
+          market: marketPda,
+          quoteMint,
+          crossMargin: a.crossMargin,
+          cmVaultAuth: a.cmVaultAuth,
+          cmVaultAta: a.cmVault,
+          ownerQuoteAta: a.ownerQuoteAta,
+          tokenProgram: spl.TOKEN_PROGRAM_ID,
+          associatedTokenProgram: spl.ASSOCIATED_TOKEN_PROGRAM_ID,
+        })
+        .signers([seller])
+        .rpc();
+    } catch (e) {
+      rejected = true;
+    }
+    assert.equal(rejected, true);
+
+    const dealId = new BN(606);
+    const [dealPdaY] = web3.PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("deal"),
+        marketPda.toBuffer(),
+        buyer.publicKey.toBuffer(),
+        seller.publicKey.toBuffer(),
+        dealId.toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    const [vaultAuthY] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("vault_auth"), dealPdaY.toBuffer()],
+      program.programId
+    );
+    const marginVaultY = spl.getAssociatedTokenAddressSync(quoteMint, vaultAuthY, true);
+    const matchAccounts = {
+      payer: wallet.publicKey,
+      market: marketPda,
+      book: bookPda,
+      quoteMint,
+      bid: bidOrder,
+      ask: askOrder,
+      long: buyer.publicKey,
+      short: seller.publicKey,
+      bidCrossMargin: b.crossMargin,
+      bidCmVaultAuth: b.cmVaultAuth,
+      bidCmVaultAta: b.cmVault,
+      askCrossMargin: a.crossMargin,
+      askCmVaultAuth: a.cmVaultAuth,
+      askCmVaultAta: a.cmVault,
+      deal: dealPdaY,
+      longMarginVault: marginVaultY,
+      shortMarginVault: marginVaultY,
+      vaultAuth: vaultAuthY,
+      feeVault,
+      tokenProgram: spl.TOKEN_PROGRAM_ID,
+      associatedTokenProgram: spl.ASSOCIATED_TOKEN_PROGRAM_ID,
+      systemProgram: web3.SystemProgram.programId,
+    };
+
+    // price-time priority: the 119 ask can't trade ahead of the 118 ask
+    const [dealPdaZ] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("deal"), marketPda.toBuffer(), buyer.publicKey.toBuffer(), seller2.publicKey.toBuffer(), dealId.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    const [vaultAuthZ] = web3.PublicKey.findProgramAddressSync([Buffer.from("vault_auth"), dealPdaZ.toBuffer()], program.programId);
+    const marginVaultZ = spl.getAssociatedTokenAddressSync(quoteMint, vaultAuthZ, true);
+    let matched = true;
+    try {
+      await program.methods
+        .matchOrders(dealId)
+        .accounts({
+          ...matchAccounts,
+          ask: worseAsk,
+          short: seller2.publicKey,
+          askCrossMargin: a2.crossMargin,
+          askCmVaultAuth: a2.cmVaultAuth,
+          askCmVaultAta: a2.cmVault,
+          deal: dealPdaZ,
+          longMarginVault: marginVaultZ,
+          shortMarginVault: marginVaultZ,
+          vaultAuth: vaultAuthZ,
+        })
+        .rpc();
+    } catch (e) {
+      matched = false;
+    }
+    assert.equal(matched, false);
+
+    tx = await program.methods.matchOrders(dealId).accounts(matchAccounts).rpc();
+    await connection.confirmTransaction(tx, "confirmed");
+
+    // trade prints at the resting ask's price for the bid's full size
+    const d = await program.account.deal.fetch(dealPdaY);
+    assert.equal(d.strikePrice.toString(), toUnitsBN(118).toString());
+    assert.equal(d.qtyReceiptAmount.toString(), toUnitsBN(2).toString());
+    assert.equal(d.settleTs.toString(), settleTs.toString());
+    assert.equal(d.longMargin.gtn(0), true);
+    assert.equal((await program.account.order.fetch(bidOrder)).status, 1); // Filled
+    const askAfter = await program.account.order.fetch(askOrder);
+    assert.equal(askAfter.remainingQty.toString(), toUnitsBN(1).toString());
+
+    for (const [owner, order, cm] of [
+      [seller, askOrder, a],
+      [seller2, worseAsk, a2],
+    ] as [web3.Keypair, web3.PublicKey, any][]) {
+      tx = await program.methods
+        .cancelOrder()
+        .accounts({ owner: owner.publicKey, order, crossMargin: cm.crossMargin, book: bookPda })
+        .signers([owner])
+        .rpc();
+      await connection.confirmTransaction(tx, "confirmed");
+      assert.equal((await program.account.order.fetch(order)).status, 2); // Cancelled
+    }
+    const bookAfter = await program.account.orderBook.fetch(bookPda);
+    assert.equal(bookAfter.bidCount + bookAfter.askCount, 0);
+    assert.equal((await program.account.crossMargin.fetch(a.crossMargin)).locked.toString(), "0");
+    assert.equal((await program.account.crossMargin.fetch(b.crossMargin)).locked.toString(), "0");
+  });
+
+  it("novate_deal hands the long side to a new holder and refunds the old one net of its marked loss", async () => {
+    const [oldLong, newLong, cpty] = [web3.Keypair.generate(), web3.Keypair.generate(), web3.Keypair.generate()];
+    const atas: web3.PublicKey[] = [];
+    for (const k of [oldLong, newLong, cpty]) {
+      await airdrop(k.publicKey);
+      const ata = (await spl.getOrCreateAssociatedTokenAccount(connection, mintAuthority, quoteMint, k.publicKey)).address;
+      await spl.mintTo(connection, mintAuthority, quoteMint, ata, mintAuthority, Math.round(1_000 * 10 ** DECIMALS));
+      atas.push(ata);
+    }
+    const [oldLongAta, newLongAta, cptyAta] = atas;
+
+    const dealId = new BN(707);
+    const [dealPdaZ] = web3.PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("deal"),
+        marketPda.toBuffer(),
+        oldLong.publicKey.toBuffer(),
+        cpty.publicKey.toBuffer(),
+        dealId.toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    const [vaultAuthZ] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("vault_auth"), dealPdaZ.toBuffer()],
+      program.programId
+    );
+    const marginVaultZ = spl.getAssociatedTokenAddressSync(quoteMint, vaultAuthZ, true);
+
+    const strike = toUnitsBN(120);
+    const qty = toUnitsBN(2);
+    const m = await program.account.market.fetch(marketPda);
+    const reqIM = requiredInitialMargin(
+      m.priceExponent,
+      m.baseInitialMarginBps,
+      m.volMultiplierBps,
+      m.lastVolBps,
+      new BN(strike),
+      new BN(qty)
+    );
+    const settleTsZ = new BN(Math.floor(Date.now() / 1000) + 3600);
+    let tx = await program.methods
+      .openDeal(dealId, 1, strike, qty, settleTsZ, { cash: {} }, reqIM, reqIM)
+      .accounts({
+        market: marketPda,
+        long: oldLong.publicKey,
+        short: cpty.publicKey,
+        quoteMint,
+        longQuoteAta: oldLongAta,
+        shortQuoteAta: cptyAta,
+        deal: dealPdaZ,
+        longMarginVault: marginVaultZ,
+        shortMarginVault: marginVaultZ,
+        vaultAuth: vaultAuthZ,
+        feeVault,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+        associatedTokenProgram: spl.ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .signers([oldLong, cpty])
+      .rpc();
+    await connection.confirmTransaction(tx, "confirmed");
+
+    const novateAccounts = {
+      market: marketPda,
+      deal: dealPdaZ,
+      quoteMint,
+      outgoing: oldLong.publicKey,
+      outgoingQuoteAta: oldLongAta,
+      incoming: newLong.publicKey,
+      incomingQuoteAta: newLongAta,
+      vaultAuth: vaultAuthZ,
+      longMarginVault: marginVaultZ,
+      shortMarginVault: marginVaultZ,
+      tokenProgram: spl.TOKEN_PROGRAM_ID,
+    };
+
+    // below required initial margin → rejected
+    let rejected = false;
+    try {
+      await program.methods
+        .novateDeal({ long: {} }, reqIM.subn(1))
+        .accounts(novateAccounts)
+        .signers([oldLong, newLong])
+        .rpc();
+    } catch (e) {
+      rejected = true;
+    }
+    assert.equal(rejected, true);
+
+    // no print for this expiry yet → can't be priced, so can't be novated
+    rejected = false;
+    try {
+      await program.methods.novateDeal({ long: {} }, reqIM).accounts(novateAccounts).signers([oldLong, newLong]).rpc();
+    } catch (e) {
+      rejected = true;
+    }
+    assert.equal(rejected, true);
+
+    // price drops 5 below strike: the old long's 10 loss is marked to the short before it exits
+    tx = await program.methods
+      .postPrice(toUnitsBN(115), PRICE_EXPONENT, settleTsZ, 500)
+      .accounts({
+        market: marketPda,
+        priceHistory: priceHistoryPda,
+        oracleSet: oracleSetPda,
+        twap: twapPda(marketPda, settleTsZ),
+        poster: wallet.publicKey,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .rpc();
+    await connection.confirmTransaction(tx, "confirmed");
+
+    const loss = toUnitsBN(10);
+    const preOld = await getTokenAmount(oldLongAta);
+    tx = await program.methods
+      .novateDeal({ long: {} }, reqIM)
+      .accounts(novateAccounts)
+      .signers([oldLong, newLong])
+      .rpc();
+    await connection.confirmTransaction(tx, "confirmed");
+
+    const d = await program.account.deal.fetch(dealPdaZ);
+    assert.equal(d.long.toBase58(), newLong.publicKey.toBase58());
+    assert.equal(d.short.toBase58(), cpty.publicKey.toBase58());
+    assert.equal(d.longMargin.toString(), reqIM.toString());
+    assert.equal(d.shortMargin.toString(), reqIM.add(loss).toString());
+    assert.equal(d.lastMarkPrice.toString(), toUnitsBN(115).toString());
+    assert.equal(await getTokenAmount(oldLongAta), preOld + Number(reqIM.sub(loss)));
+  });
+
+  it("terminate_deal unwinds a physical deal at an agreed price", async () => {
+    const [tLong, tShort] = [web3.Keypair.generate(), web3.Keypair.generate()];
+    const atas: web3.PublicKey[] = [];
+    for (const k of [tLong, tShort]) {
+      await airdrop(k.publicKey);
+      const ata = (await spl.getOrCreateAssociatedTokenAccount(connection, mintAuthority, quoteMint, k.publicKey)).address;
+      await spl.mintTo(connection, mintAuthority, quoteMint, ata, mintAuthority, Math.round(1_000 * 10 ** DECIMALS));
+      atas.push(ata);
+    }
+    const [tLongAta, tShortAta] = atas;
+
+    const dealId = new BN(808);
+    const [dealPdaT] = web3.PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("deal"),
+        marketPda.toBuffer(),
+        tLong.publicKey.toBuffer(),
+        tShort.publicKey.toBuffer(),
+        dealId.toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    const [vaultAuthT] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("vault_auth"), dealPdaT.toBuffer()],
+      program.programId
+    );
+    const marginVaultT = spl.getAssociatedTokenAddressSync(quoteMint, vaultAuthT, true);
+
+    const strike = toUnitsBN(100);
+    const qty = toUnitsBN(2);
+    const m = await program.account.market.fetch(marketPda);
+    const reqIM = requiredInitialMargin(
+      m.priceExponent,
+      m.baseInitialMarginBps,
+      m.volMultiplierBps,
+      m.lastVolBps,
+      new BN(strike),
+      new BN(qty)
+    );
+    let tx = await program.methods
+      .openDeal(dealId, 1, strike, qty, new BN(Math.floor(Date.now() / 1000) + 3600), { physical: {} }, reqIM, reqIM)
+      .accounts({
+        market: marketPda,
+        long: tLong.publicKey,
+        short: tShort.publicKey,
+        quoteMint,
+        longQuoteAta: tLongAta,
+        shortQuoteAta: tShortAta,
+        deal: dealPdaT,
+        longMarginVault: marginVaultT,
+        shortMarginVault: marginVaultT,
+        vaultAuth: vaultAuthT,
+        feeVault,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+        associatedTokenProgram: spl.ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .signers([tLong, tShort])
+      .rpc();
+    await connection.confirmTransaction(tx, "confirmed");
+
+    // agreed close 1 above strike → long gains 2 quote units less the fee
+    const preLong = await getTokenAmount(tLongAta);
+    const preFee = await getTokenAmount(feeVault);
+    tx = await program.methods
+      .terminateDeal(toUnitsBN(101))
+      .accounts({
+        market: marketPda,
+        deal: dealPdaT,
+        long: tLong.publicKey,
+        short: tShort.publicKey,
+        quoteMint,
+        vaultAuth: vaultAuthT,
+        longMarginVault: marginVaultT,
+        shortMarginVault: marginVaultT,
+        longQuoteAta: tLongAta,
+        shortQuoteAta: tShortAta,
+        feeVault,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+        associatedTokenProgram: spl.ASSOCIATED_TOKEN_PROGRAM_ID,
+      })
+      .signers([tLong, tShort])
+      .rpc();
+    await connection.confirmTransaction(tx, "confirmed");
+
+    const pnl = toUnitsBN(2);
+    const fee = pnl.muln(FEE_BPS).divn(10_000);
+    assert.equal((await program.account.deal.fetch(dealPdaT)).isSettled, true);
+    assert.equal(await getTokenAmount(feeVault), preFee + Number(fee));
+    assert.equal((await getTokenAmount(tLongAta)) > preLong, true);
+  });
+
+  it("token-2022 receipt mint: transfer hook enforces the participant allowlist", async () => {
+    const T22 = spl.TOKEN_2022_PROGRAM_ID;
+    hookMint = web3.Keypair.generate();
+    const mintLen = spl.getMintLen([spl.ExtensionType.TransferHook]);
+    const lamports = await connection.getMinimumBalanceForRentExemption(mintLen);
+    await web3.sendAndConfirmTransaction(
+      connection,
+      new web3.Transaction().add(
+        web3.SystemProgram.createAccount({
+          fromPubkey: mintAuthority.publicKey,
+          newAccountPubkey: hookMint.publicKey,
+          space: mintLen,
+          lamports,
+          programId: T22,
+        }),
+        spl.createInitializeTransferHookInstruction(hookMint.publicKey, mintAuthority.publicKey, program.programId, T22),
+        spl.createInitializeMintInstruction(hookMint.publicKey, DECIMALS, mintAuthority.publicKey, null, T22)
+      ),
+      [mintAuthority, hookMint]
+    );
+
+    [hookMarket] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("market"), wallet.publicKey.toBuffer(), hookMint.publicKey.toBuffer(), quoteMint.toBuffer()],
+      program.programId
+    );
+    const pda = (tag: string, key: web3.PublicKey) =>
+      web3.PublicKey.findProgramAddressSync([Buffer.from(tag), key.toBuffer()], program.programId)[0];
+    let tx = await program.methods
+      .initMarket(FEE_BPS, ORACLE(), GOVERNANCE(), BASE_IM_BPS, MAINT_BPS, VOL_MULT_BPS)
+      .accounts({
+        authority: wallet.publicKey,
+        quoteMint,
+        receiptMint: hookMint.publicKey,
+        market: hookMarket,
+        priceHistory: pda("price_history", hookMarket),
+        oracleSet: pda("oracle_set", hookMarket),
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .rpc();
+    await connection.confirmTransaction(tx, "confirmed");
+    assert.equal((await program.account.market.fetch(hookMarket)).allowlistEnabled, true);
+
+    tx = await program.methods
+      .initializeExtraAccountMetaList()
+      .accounts({
+        signer: wallet.publicKey,
+        market: hookMarket,
+        receiptMint: hookMint.publicKey,
+        extraAccountMetaList: pda("extra-account-metas", hookMint.publicKey),
+        council: null,
+        councilProposal: null,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .rpc();
+    await connection.confirmTransaction(tx, "confirmed");
+
+    const ata = async (owner: web3.PublicKey) =>
+      (
+        await spl.getOrCreateAssociatedTokenAccount(connection, mintAuthority, hookMint.publicKey, owner, false, "confirmed", undefined, T22)
+      ).address;
+    const longAta = await ata(long.publicKey);
+    const shortAta = await ata(short.publicKey);
+    const units = 10 * 10 ** DECIMALS;
+    await spl.mintTo(connection, mintAuthority, hookMint.publicKey, longAta, mintAuthority, units, [], undefined, T22);
+
+    const setParticipant = async (owner: web3.PublicKey, active: boolean) => {
+      const [participant] = web3.PublicKey.findProgramAddressSync(
+        [Buffer.from("participant"), hookMarket.toBuffer(), owner.toBuffer()],
+        program.programId
+      );
+      const t = await program.methods
+        .setParticipant(owner, active)
+        .accounts({
+          signer: wallet.publicKey,
+          market: hookMarket,
+          participant,
+          council: null,
+          councilProposal: null,
+          systemProgram: web3.SystemProgram.programId,
+        })
+        .rpc();
+      await connection.confirmTransaction(t, "confirmed");
+    };
+    const transfer = () =>
+      spl.transferCheckedWithTransferHook(
+        connection, long, longAta, hookMint.publicKey, shortAta, long, BigInt(units / 2), DECIMALS, [], { commitment: "confirmed" }, T22
+      );
+
+    // only the sender is onboarded → the hook rejects the transfer
+    await setParticipant(long.publicKey, true);
+    let moved = true;
+    try {
+      await transfer();
+    } catch (e) {
+      moved = false;
+    }
+    assert.equal(moved, false);
+
+    await setParticipant(short.publicKey, true);
+    await transfer();
+    assert.equal(Number((await spl.getAccount(connection, shortAta, "confirmed", T22)).amount), units / 2);
+  });
+
+  it("token-2022 receipt mint: mint_receipt, redemption escrow and settle_physical apply the allowlist", async () => {
+    const T22 = spl.TOKEN_2022_PROGRAM_ID;
+    const mint = hookMint.publicKey;
+    const pda = (tag: string, key: web3.PublicKey) =>
+      web3.PublicKey.findProgramAddressSync([Buffer.from(tag), key.toBuffer()], program.programId)[0];
+    const participantPda = (owner: web3.PublicKey) =>
+      web3.PublicKey.findProgramAddressSync(
+        [Buffer.from("participant"), hookMarket.toBuffer(), owner.toBuffer()],
+        program.programId
+      )[0];
+    const setParticipant = async (owner: web3.PublicKey, active: boolean) => {
+      const t = await program.methods
+        .setParticipant(owner, active)
+        .accounts({
+          signer: wallet.publicKey,
+          market: hookMarket,
+          participant: participantPda(owner),
+          council: null,
+          councilProposal: null,
+          systemProgram: web3.SystemProgram.programId,
+        })
+        .rpc();
+      await connection.confirmTransaction(t, "confirmed");
+    };
+    // what Token-2022 would resolve for the hook; the program checks the two participants itself
+    const hookAccounts = (fromOwner: web3.PublicKey, toOwner: web3.PublicKey) =>
+      [pda("extra-account-metas", mint), hookMarket, participantPda(fromOwner), participantPda(toOwner), program.programId].map(
+        (pubkey) => ({ pubkey, isSigner: false, isWritable: false })
+      );
+    const receiptAta = (owner: web3.PublicKey, offCurve = false) =>
+      spl.getAssociatedTokenAddressSync(mint, owner, offCurve, T22);
+    const receiptBalance = async (ata: web3.PublicKey) =>
+      Number((await spl.getAccount(connection, ata, "confirmed", T22)).amount);
+
+    // the mint's current authority opens a warehouse, handing mint authority to the market PDA
+    const whAuthority = mintAuthority;
+    const [warehouse] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("warehouse"), hookMarket.toBuffer(), whAuthority.publicKey.toBuffer()],
+      program.programId
+    );
+    const storageAuth = pda("storage_auth", warehouse);
+    const storageVault2 = spl.getAssociatedTokenAddressSync(quoteMint, storageAuth, true);
+    const bondAuth = pda("bond_auth", warehouse);
+    const receiptMintAuth = pda("receipt_auth", hookMarket);
+    let tx = await program.methods
+      .initWarehouse()
+      .accounts({
+        warehouseAuthority: whAuthority.publicKey,
+        market: hookMarket,
+        quoteMint,
+        receiptMint: mint,
+        receiptMintAuth,
+        warehouse,
+        storageAuth,
+        storageVault: storageVault2,
+        bondAuth,
+        bondVault: spl.getAssociatedTokenAddressSync(quoteMint, bondAuth, true),
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+        receiptTokenProgram: T22,
+        associatedTokenProgram: spl.ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .signers([whAuthority])
+      .rpc();
+    await connection.confirmTransaction(tx, "confirmed");
+    const units = 5 * 10 ** DECIMALS;
+    tx = await program.methods
+      .approveWarehouse(new BN(Math.floor(Date.now() / 1000) + 365 * 86400), new BN(units))
+      .accounts({ signer: wallet.publicKey, market: hookMarket, warehouse, council: null, councilProposal: null })
+      .rpc();
+    await connection.confirmTransaction(tx, "confirmed");
+    tx = await program.methods
+      .attestInventory(new BN(units))
+      .accounts({ auditor: wallet.publicKey, market: hookMarket, warehouse })
+      .rpc();
+    await connection.confirmTransaction(tx, "confirmed");
+
+    // mint_receipt: the recipient must be an active participant in its own right
+    const holder = web3.Keypair.generate();
+    await airdrop(holder.publicKey);
+    const holderAta = (
+      await spl.getOrCreateAssociatedTokenAccount(connection, mintAuthority, mint, holder.publicKey, false, "confirmed", undefined, T22)
+    ).address;
+    const lot = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("lot"), warehouse.toBuffer(), new BN(0).toArrayLike(Buffer, "le", 8)],
+      program.programId
+    )[0];
+    const mintTo = (recipientParticipant: web3.PublicKey | null) =>
+      program.methods
+        .mintReceipt(new BN(units), {
+          grade: 0,
+          grossWeight: new BN(5_000_000),
+          netWeight: new BN(5_000_000),
+          location: Array(16).fill(0),
+          intakeTs: new BN(Math.floor(Date.now() / 1000) - 60),
+          certificateHash: Array(32).fill(1),
+        })
+        .accounts({
+          warehouse,
+          market: hookMarket,
+          receiptMint: mint,
+          receiptMintAuth,
+          toReceiptAta: holderAta,
+          warehouseAuthority: whAuthority.publicKey,
+          lot,
+          recipientParticipant,
+          tokenProgram: T22,
+          systemProgram: web3.SystemProgram.programId,
+        })
+        .signers([whAuthority])
+        .rpc();
+    for (const participant of [null, participantPda(long.publicKey)]) {
+      // no participant, then someone else's (active) participant
+      let minted = true;
+      try {
+        await mintTo(participant);
+      } catch (e) {
+        minted = false;
+      }
+      assert.equal(minted, false);
+    }
+    await setParticipant(holder.publicKey, true);
+    tx = await mintTo(participantPda(holder.publicKey));
+    await connection.confirmTransaction(tx, "confirmed");
+    assert.equal(await receiptBalance(holderAta), units);
+
+    // request_redemption registers the escrow as a participant; cancel returns the units and closes it
+    const holderQuoteAta = (
+      await spl.getOrCreateAssociatedTokenAccount(connection, mintAuthority, quoteMint, holder.publicKey)
+    ).address;
+    await spl.mintTo(connection, mintAuthority, quoteMint, holderQuoteAta, mintAuthority, 10 * 10 ** DECIMALS);
+    const [redemption] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("redemption"), warehouse.toBuffer(), new BN(0).toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    const redemptionAuth = pda("redemption_auth", redemption);
+    const escrow = {
+      market: hookMarket,
+      warehouse,
+      lot,
+      receiptMint: mint,
+      receiptMintAuth,
+      quoteMint,
+      storageAuth,
+      storageVault: storageVault2,
+      holderReceiptAta: holderAta,
+      redemption,
+      redemptionAuth,
+      redemptionVault: receiptAta(redemptionAuth, true),
+      escrowParticipant: participantPda(redemptionAuth),
+      tokenProgram: spl.TOKEN_PROGRAM_ID,
+      receiptTokenProgram: T22,
+    };
+    const redeemAmount = 2 * 10 ** DECIMALS;
+    tx = await program.methods
+      .requestRedemption(new BN(redeemAmount), Array(64).fill(0))
+      .accounts({
+        ...escrow,
+        holder: holder.publicKey,
+        holderQuoteAta,
+        associatedTokenProgram: spl.ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .remainingAccounts(hookAccounts(holder.publicKey, redemptionAuth))
+      .signers([holder])
+      .rpc();
+    await connection.confirmTransaction(tx, "confirmed");
+    assert.equal(await receiptBalance(holderAta), units - redeemAmount);
+    assert.equal(await receiptBalance(escrow.redemptionVault), redeemAmount);
+    assert.equal((await program.account.participant.fetch(escrow.escrowParticipant)).isActive, true);
+
+    tx = await program.methods
+      .cancelRedemption()
+      .accounts({ ...escrow, signer: whAuthority.publicKey, holder: holder.publicKey, holderQuoteAta })
+      .remainingAccounts(hookAccounts(redemptionAuth, holder.publicKey))
+      .signers([whAuthority])
+      .rpc();
+    await connection.confirmTransaction(tx, "confirmed");
+    assert.equal(await receiptBalance(holderAta), units);
+    assert.equal(await connection.getAccountInfo(escrow.escrowParticipant, "confirmed"), null);
+
+    // settle_physical: the holder delivers to `long`, who was onboarded in the previous test
+    const dealId = new BN(1);
+    const [deal] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("deal"), hookMarket.toBuffer(), long.publicKey.toBuffer(), holder.publicKey.toBuffer(), dealId.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    const vaultAuth = pda("vault_auth", deal);
+    const marginVault = spl.getAssociatedTokenAddressSync(quoteMint, vaultAuth, true);
+    const strike = toUnitsBN(1);
+    const qty = new BN(10 ** DECIMALS);
+    const margin = toUnitsBN(1.1); // covers the 1.0 notional the long pays on delivery
+    tx = await program.methods
+      .openDeal(dealId, 1, strike, qty, new BN(Math.floor(Date.now() / 1000) + 2), { physical: {} }, margin, margin)
+      .accounts({
+        market: hookMarket,
+        long: long.publicKey,
+        short: holder.publicKey,
+        quoteMint,
+        longQuoteAta,
+        shortQuoteAta: holderQuoteAta,
+        deal,
+        longMarginVault: marginVault,
+        shortMarginVault: marginVault,
+        vaultAuth,
+        feeVault: spl.getAssociatedTokenAddressSync(quoteMint, hookMarket, true),
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+        associatedTokenProgram: spl.ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .signers([long, holder])
+      .rpc();
+    await connection.confirmTransaction(tx, "confirmed");
+    await sleep(2500);
+
+    const settle = (remaining: { pubkey: web3.PublicKey; isSigner: boolean; isWritable: boolean }[]) =>
+      program.methods
+        .settlePhysical()
+        .accounts({
+          deal,
+          market: hookMarket,
+          quoteMint,
+          receiptMint: mint,
+          receiptMintAuth,
+          vaultAuth,
+          longMarginVault: marginVault,
+          shortMarginVault: marginVault,
+          long: long.publicKey,
+          short: holder.publicKey,
+          longReceiptAta: receiptAta(long.publicKey),
+          shortReceiptAta: holderAta,
+          longReceiveQuoteAta: longQuoteAta,
+          shortReceiveQuoteAta: holderQuoteAta,
+          warehouse,
+          lot,
+          storageAuth,
+          storageVault: storageVault2,
+          tokenProgram: spl.TOKEN_PROGRAM_ID,
+          receiptTokenProgram: T22,
+          associatedTokenProgram: spl.ASSOCIATED_TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts(remaining)
+        .signers([long, holder])
+        .rpc();
+    // without the participants the delivery is refused
+    let settled = true;
+    try {
+      await settle([]);
+    } catch (e) {
+      settled = false;
+    }
+    assert.equal(settled, false);
+
+    const longBefore = await receiptBalance(receiptAta(long.publicKey));
+    tx = await settle(hookAccounts(holder.publicKey, long.publicKey));
+    await connection.confirmTransaction(tx, "confirmed");
+    assert.equal(await receiptBalance(receiptAta(long.publicKey)), longBefore + qty.toNumber());
+    assert.equal(await receiptBalance(holderAta), units - qty.toNumber());
+    assert.equal(Number((await spl.getMint(connection, mint, "confirmed", T22)).supply), 10 * 10 ** DECIMALS + units);
+    assert.equal((await program.account.deal.fetch(deal)).isSettled, true);
+  });
+
+  // Runs last: once a council is installed, single-key admin paths are closed for this market.
+  it("council: freeze_deal and cancel_action need an approved 2-of-3 proposal", async () => {
+    const m2 = web3.Keypair.generate();
+    const m3 = web3.Keypair.generate();
+    const [councilPda] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("council"), marketPda.toBuffer()],
+      program.programId
+    );
+
+    // queued by a single key before the council exists
+    const m0 = await program.account.market.fetch(marketPda);
+    const actionId = new BN(m0.nextActionId);
+    const [pendingPda] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("pending_action"), marketPda.toBuffer(), actionId.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    let tx = await program.methods
+      .proposeAction({ addAllowedCollateral: { mint: web3.Keypair.generate().publicKey } })
+      .accounts({
+        proposer: wallet.publicKey,
+        market: marketPda,
+        pendingAction: pendingPda,
+        council: null,
+        councilProposal: null,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .rpc();
+    await connection.confirmTransaction(tx, "confirmed");
+
+    tx = await program.methods
+      .initCouncil([wallet.publicKey, m2.publicKey, m3.publicKey], 2)
+      .accounts({
+        signer: wallet.publicKey,
+        market: marketPda,
+        council: councilPda,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .rpc();
+    await connection.confirmTransaction(tx, "confirmed");
+
+    const freeze = (proposal: web3.PublicKey | null) =>
+      program.methods
+        .freezeDeal()
+        .accounts({
+          signer: wallet.publicKey,
+          market: marketPda,
+          deal: dealPda,
+          council: proposal ? councilPda : null,
+          councilProposal: proposal,
+        })
+        .rpc();
+
+    let ok = true;
+    try {
+      await freeze(null);
+    } catch (e) {
+      ok = false;
+    }
+    assert.equal(ok, false); // a lone admin key is no longer enough
+
+    const c = await program.account.governanceCouncil.fetch(councilPda);
+    const [proposalPda] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("council_proposal"), councilPda.toBuffer(), new BN(c.nextProposalId).toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    tx = await program.methods
+      .councilPropose({ freezeDeal: { deal: dealPda } })
+      .accounts({
+        member: wallet.publicKey,
+        council: councilPda,
+        proposal: proposalPda,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .rpc();
+    await connection.confirmTransaction(tx, "confirmed");
+
+    ok = true;
+    try {
+      await freeze(proposalPda);
+    } catch (e) {
+      ok = false;
+    }
+    assert.equal(ok, false); // 1 of 2 approvals
+
+    tx = await program.methods
+      .councilApprove()
+      .accounts({ member: m2.publicKey, council: councilPda, proposal: proposalPda })
+      .signers([m2])
+      .rpc();
+    await connection.confirmTransaction(tx, "confirmed");
+
+    tx = await freeze(proposalPda);
+    await connection.confirmTransaction(tx, "confirmed");
+    assert.equal((await program.account.deal.fetch(dealPda)).isFrozen, true);
+    assert.equal((await program.account.councilProposal.fetch(proposalPda)).executed, true);
+
+    ok = true;
+    try {
+      await freeze(proposalPda);
+    } catch (e) {
+      ok = false;
+    }
+    assert.equal(ok, false); // proposals are single-use
+
+    // the queue is council-gated too: a lone admin key can't cancel (or execute) the action
+    const cancel = (proposal: web3.PublicKey | null) =>
+      program.methods
+        .cancelAction()
+        .accounts({
+          signer: wallet.publicKey,
+          market: marketPda,
+          pendingAction: pendingPda,
+          council: proposal ? councilPda : null,
+          councilProposal: proposal,
+        })
+        .rpc();
+    ok = true;
+    try {
+      await cancel(null);
+    } catch (e) {
+      ok = false;
+    }
+    assert.equal(ok, false);
+
+    const c2 = await program.account.governanceCouncil.fetch(councilPda);
+    const [cancelProposalPda] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("council_proposal"), councilPda.toBuffer(), new BN(c2.nextProposalId).toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    tx = await program.methods
+      .councilPropose({ cancelAction: { actionId } })
+      .accounts({
+        member: wallet.publicKey,
+        council: councilPda,
+        proposal: cancelProposalPda,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .rpc();
+    await connection.confirmTransaction(tx, "confirmed");
+    tx = await program.methods
+      .councilApprove()
+      .accounts({ member: m3.publicKey, council: councilPda, proposal: cancelProposalPda })
+      .signers([m3])
+      .rpc();
+    await connection.confirmTransaction(tx, "confirmed");
+    tx = await cancel(cancelProposalPda);
+    await connection.confirmTransaction(tx, "confirmed");
+    assert.equal((await program.account.pendingAction.fetch(pendingPda)).status, 2); // cancelled
+  });
+});